@@ -0,0 +1,62 @@
+use crate::canvas::Canvas;
+use crate::tuples::Color;
+
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+impl Canvas {
+    pub fn to_ascii(&self) -> String {
+        if self.width == 0 {
+            return String::new();
+        }
+        self.pixels
+            .chunks(self.width)
+            .map(|row| row.iter().map(shade).collect::<String>() + "\n")
+            .collect()
+    }
+}
+
+fn shade(c: &Color) -> char {
+    let luminance = 0.2126 * c.red + 0.7152 * c.green + 0.0722 * c.blue;
+    let level = luminance.clamp(0., 1.) * (RAMP.len() - 1) as f64;
+    RAMP[level.round() as usize] as char
+}
+
+#[cfg(test)]
+mod spec {
+    use crate::canvas::canvas;
+    use crate::tuples::color;
+
+    #[test]
+    fn a_black_canvas_is_blank() {
+        let c = canvas(3, 2);
+
+        assert_eq!(c.to_ascii(), "   \n   \n");
+    }
+
+    #[test]
+    fn a_white_canvas_uses_the_densest_character() {
+        let mut c = canvas(3, 2);
+        for x in 0..3 {
+            for y in 0..2 {
+                c.write_pixel(x, y, color(1., 1., 1.));
+            }
+        }
+
+        assert_eq!(c.to_ascii(), "@@@\n@@@\n");
+    }
+
+    #[test]
+    fn ascii_shades_follow_luminance() {
+        let mut c = canvas(3, 1);
+        c.write_pixel(1, 0, color(0.5, 0.5, 0.5));
+        c.write_pixel(2, 0, color(5., 5., 5.));
+
+        assert_eq!(c.to_ascii(), " +@\n");
+    }
+
+    #[test]
+    fn an_empty_canvas_has_no_ascii() {
+        assert_eq!(canvas(0, 2).to_ascii(), "");
+        assert_eq!(canvas(2, 0).to_ascii(), "");
+    }
+}
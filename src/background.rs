@@ -0,0 +1,60 @@
+use crate::tuples::Color;
+use crate::tuples::Tuple;
+use crate::uv::UvImage;
+use crate::uv::UvPattern;
+use std::f64::consts::PI;
+
+// longitude and latitude of a direction as texture coordinates, +x looks at the horizontal
+// center of the panorama and v grows from the bottom (-y) to the top (+y) like in uv textures
+fn equirect_uv(direction: &Tuple) -> (f64, f64) {
+    let d = direction.normalized();
+    let u = 0.5 + d.z.atan2(d.x) / (2. * PI);
+    let v = 1. - d.y.clamp(-1., 1.).acos() / PI;
+    (u, v)
+}
+
+// the color a panorama shows in the direction of a ray that missed every object
+pub fn environment_at(image: &UvImage, direction: &Tuple) -> Color {
+    let (u, v) = equirect_uv(direction);
+    image.at_uv(u, v)
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use crate::canvas::canvas;
+    use crate::tuples::color;
+    use crate::tuples::vector;
+    use crate::uv::uv_image;
+
+    fn panorama() -> UvImage {
+        let mut image = canvas(5, 3);
+        for x in 0..5 {
+            for y in 0..3 {
+                image.write_pixel(x, y, color(x as f64, y as f64, 0.));
+            }
+        }
+        uv_image(image)
+    }
+
+    #[test]
+    fn a_ray_along_x_samples_the_horizontal_center() {
+        let c = environment_at(&panorama(), &vector(1., 0., 0.));
+
+        assert_eq!(c, color(2., 1., 0.));
+    }
+
+    #[test]
+    fn an_up_ray_samples_the_top_row() {
+        let c = environment_at(&panorama(), &vector(0., 1., 0.));
+
+        assert_eq!(c.green, 0.);
+    }
+
+    #[test]
+    fn a_down_ray_samples_the_bottom_row() {
+        let c = environment_at(&panorama(), &vector(0., -1., 0.));
+
+        assert_eq!(c.green, 2.);
+    }
+}
@@ -13,6 +13,11 @@ pub struct Bounds {
 pub fn bound(min: Tuple, max: Tuple) -> Bounds {
     Bounds { min, max }
 }
+// contains nothing, adding any bounds to it gives those bounds back
+pub fn bound_empty() -> Bounds {
+    let inf = f64::INFINITY;
+    bound(point(inf, inf, inf), point(-inf, -inf, -inf))
+}
 pub fn bound_single(p: Tuple) -> Bounds {
     bound(p.clone(), p.clone())
 }
@@ -36,23 +41,24 @@ fn check_axis(origin: f64, direction: f64, minimum: f64, maximum: f64) -> (f64,
     }
 }
 impl Bounds {
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
     pub fn intersects(&self, ray: &Ray) -> bool {
+        if self.is_empty() {
+            return false;
+        }
         let (xtmin, xtmax) = check_axis(ray.origin.x, ray.direction.x, self.min.x, self.max.x);
         let (ytmin, ytmax) = check_axis(ray.origin.y, ray.direction.y, self.min.y, self.max.y);
         let (ztmin, ztmax) = check_axis(ray.origin.z, ray.direction.z, self.min.z, self.max.z);
-        let tmin = vec![xtmin, ytmin, ztmin]
-            .into_iter()
-            .max_by(|a, b| a.partial_cmp(&b).unwrap())
-            .unwrap();
-        let tmax = vec![xtmax, ytmax, ztmax]
-            .into_iter()
-            .min_by(|a, b| a.partial_cmp(&b).unwrap())
-            .unwrap();
+        // f64::max and f64::min skip the NaN of an axis the ray runs along the face of
+        let tmin = xtmin.max(ytmin).max(ztmin);
+        let tmax = xtmax.min(ytmax).min(ztmax);
 
         tmin <= tmax && (tmin >= 0. || tmax >= 0.)
     }
-    pub fn transform(&self, transform: &Matrix) -> Bounds {
-        let points: Vec<Tuple> = vec![
+    pub fn corners(&self) -> Vec<Tuple> {
+        vec![
             point(self.min.x, self.min.y, self.min.z),
             point(self.min.x, self.max.y, self.min.z),
             point(self.min.x, self.min.y, self.max.z),
@@ -62,9 +68,12 @@ impl Bounds {
             point(self.max.x, self.min.y, self.max.z),
             point(self.max.x, self.max.y, self.max.z),
         ]
-        .into_iter()
-        .map(|p| transform * &p)
-        .collect();
+    }
+    pub fn transform(&self, transform: &Matrix) -> Bounds {
+        if self.is_empty() {
+            return self.clone();
+        }
+        let points: Vec<Tuple> = self.corners().into_iter().map(|p| transform * &p).collect();
         bound_vector(points)
     }
 }
@@ -106,6 +115,16 @@ mod spec {
         assert_eq!(bound.max, point(2., 2., 2.));
     }
 
+    #[test]
+    fn empty_bounds_are_neutral_when_joined() {
+        let a = bound(point(-1., 1., 2.), point(2., 3., 4.));
+
+        assert_eq!(bound_empty() + a.clone(), a);
+        assert!(bound_empty().is_empty());
+        assert!(bound_empty().transform(&rotation_x(PI / 3.)).is_empty());
+        assert!(!bound_empty().intersects(&ray(point(0., 0., -5.), vector(0., 0., 1.))));
+    }
+
     #[test]
     fn join_bounds_into_one() {
         let a = bound(point(-1., 1., 2.), point(2., 3., 4.));
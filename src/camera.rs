@@ -1,21 +1,42 @@
+use crate::bounds::Bounds;
 use crate::canvas::canvas;
 use crate::canvas::Canvas;
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
 use crate::rays::ray;
 use crate::rays::Ray;
+use crate::tuples::color;
+use crate::tuples::origin;
 use crate::tuples::point;
+use crate::tuples::vector;
 use crate::tuples::Color;
 use crate::world::World;
 use crate::world::MAX_REFLECTIONS;
+use rayon::prelude::*;
 use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
 
 #[derive(Clone)]
 pub struct Camera {
     hsize: usize,
     vsize: usize,
     pub invtransform: Matrix,
+    // number of sub-samples along each side of a pixel
+    pub aa: usize,
+    // how many reflections and refractions each primary ray follows
+    pub max_depth: usize,
+    pub projection: Projection,
+    // how long the shutter stays open, samples of a pixel are spread over 0..shutter so moving
+    // objects blur along their path, it is kept within 0..1 where their motion is defined
+    pub shutter: f64,
     pixel_size: f64,
     half_width: f64,
     half_height: f64,
@@ -38,60 +59,336 @@ pub fn camera(hsize: usize, vsize: usize, field_of_view: f64) -> Camera {
         hsize,
         vsize,
         invtransform: identity_matrix(),
+        aa: 1,
+        max_depth: MAX_REFLECTIONS,
+        projection: Projection::Perspective,
+        shutter: 0.,
         pixel_size: half_width * 2. / hsize as f64,
         half_height,
         half_width,
     }
 }
 
+// parallel projection of a world_width wide view, rays share the direction and start on the
+// image plane at z = 0
+pub fn orthographic_camera(hsize: usize, vsize: usize, world_width: f64) -> Camera {
+    let half_width = world_width / 2.;
+    let half_height = half_width * vsize as f64 / hsize as f64;
+    Camera {
+        hsize,
+        vsize,
+        invtransform: identity_matrix(),
+        aa: 1,
+        max_depth: MAX_REFLECTIONS,
+        projection: Projection::Orthographic,
+        shutter: 0.,
+        pixel_size: world_width / hsize as f64,
+        half_height,
+        half_width,
+    }
+}
+
+fn mix(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    h
+}
+fn pixel_hash(x: usize, y: usize) -> u64 {
+    mix((x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F))
+}
+
+// a fraction in 0..1 that looks random but is fixed for every pixel, it shifts the sample times
+// of a pixel so neighbours do not all see the same moments
+fn pixel_jitter(x: usize, y: usize) -> f64 {
+    (pixel_hash(x, y) >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+// the moment each sample of a pixel is taken at, as a shuffled order of the n time slots,
+// so where a sample lands in the pixel says nothing about when it is taken
+fn time_slots(x: usize, y: usize, n: usize) -> Vec<usize> {
+    let seed = pixel_hash(x, y);
+    let mut slots: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        let j =
+            (mix(seed ^ (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)) % (i as u64 + 1)) as usize;
+        slots.swap(i, j);
+    }
+    slots
+}
+
 impl Camera {
     fn ray_for_pixel(self: &Camera, x: usize, y: usize) -> Ray {
-        // the offset from the edge of the canvas to the pixel's center
-        let xoffset = (x as f64 + 0.5) * self.pixel_size;
-        let yoffset = (y as f64 + 0.5) * self.pixel_size;
+        self.ray_for_subpixel(x, y, 0.5, 0.5)
+    }
+
+    // dx and dy are the fractions of the pixel, from its top left corner, the ray passes through
+    fn ray_for_subpixel(self: &Camera, x: usize, y: usize, dx: f64, dy: f64) -> Ray {
+        // the offset from the edge of the canvas to the sample point
+        let xoffset = (x as f64 + dx) * self.pixel_size;
+        let yoffset = (y as f64 + dy) * self.pixel_size;
 
         // the untransformed coordinates of the pixel in world space.
         // (remember that the camera looks toward -z, so +x is to the *left*)
         let world_x = self.half_width - xoffset;
         let world_y = self.half_height - yoffset;
 
+        if self.projection == Projection::Orthographic {
+            let origin = &self.invtransform * &point(world_x, world_y, 0.);
+            let direction = (&self.invtransform * &vector(0., 0., -1.)).normalized();
+            return ray(origin, direction);
+        }
+
         // using the camera matrix, transform the canvas point and the origin,
         // and then compute the ray's direction vector.
         // (remember that the canvas is at z = -1)
         let pixel = &self.invtransform * &point(world_x, world_y, -1.);
-        let origin = &self.invtransform * &point(0., 0., 0.);
+        let origin = &self.invtransform * &origin();
         let direction = (&pixel - &origin).normalized();
 
         ray(origin, direction)
     }
 
+    // average of an aa by aa grid of samples evenly spread over the pixel, and over the time the
+    // shutter is open
+    fn color_for_pixel(self: &Camera, world: &World, x: usize, y: usize) -> Color {
+        let aa = self.aa.max(1);
+        let step = 1. / aa as f64;
+        let samples = (aa * aa) as f64;
+        // moving objects are only defined from time 0 to 1, a longer shutter would carry
+        // them past their end pose
+        let shutter = self.shutter.clamp(0., 1.);
+        // with a closed shutter every sample is taken at time 0 and needs no time slots
+        let slots = if shutter > 0. {
+            Some((time_slots(x, y, aa * aa), pixel_jitter(x, y)))
+        } else {
+            None
+        };
+        let mut sum = Color::BLACK;
+        for sx in 0..aa {
+            for sy in 0..aa {
+                let (dx, dy) = ((sx as f64 + 0.5) * step, (sy as f64 + 0.5) * step);
+                let time = slots.as_ref().map_or(0., |(slots, offset)| {
+                    let slot = slots[sx * aa + sy] as f64;
+                    shutter * ((slot + 0.5) / samples + offset).fract()
+                });
+                let ray = self.ray_for_subpixel(x, y, dx, dy).at_time(time);
+                sum = sum + world.color_at(&ray, self.max_depth);
+            }
+        }
+        sum * (step * step)
+    }
+
+    // rays through the top left, top right, bottom left and bottom right pixels of the canvas
+    pub fn frustum_corners(self: &Camera) -> [Ray; 4] {
+        let (right, bottom) = (self.hsize - 1, self.vsize - 1);
+        [
+            self.ray_for_pixel(0, 0),
+            self.ray_for_pixel(right, 0),
+            self.ray_for_pixel(0, bottom),
+            self.ray_for_pixel(right, bottom),
+        ]
+    }
+
     pub fn render(self: &Camera, world: World) -> Canvas {
+        self.render_with_progress(world, |_| {})
+    }
+
+    // reports the finished fraction of the image after every column of pixels
+    pub fn render_with_progress<F: FnMut(f64)>(
+        self: &Camera,
+        world: World,
+        mut on_progress: F,
+    ) -> Canvas {
         let mut canvas = canvas(self.hsize, self.vsize);
         for x in 0..canvas.width {
             for y in 0..canvas.height {
-                let ray = self.ray_for_pixel(x, y);
-                let color = world.color_at(&ray, MAX_REFLECTIONS);
+                let color = self.color_for_pixel(&world, x, y);
                 canvas.write_pixel(x, y, color);
             }
-            eprint!(
-                "rendering {} \r",
-                (100.0 * x as f64) / (canvas.width as f64)
-            )
+            on_progress((x + 1) as f64 / canvas.width as f64);
+        }
+        canvas
+    }
+
+    // stops between columns once the flag is set and returns what was rendered so far
+    pub fn render_cancellable(self: &Camera, world: World, cancel: Arc<AtomicBool>) -> Canvas {
+        let mut canvas = canvas(self.hsize, self.vsize);
+        for x in 0..canvas.width {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            for y in 0..canvas.height {
+                let color = self.color_for_pixel(&world, x, y);
+                canvas.write_pixel(x, y, color);
+            }
+        }
+        canvas
+    }
+
+    // colors of the pixels in the half-open rectangle x0..x1 by y0..y1, row by row
+    pub fn render_tile(
+        self: &Camera,
+        world: World,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> Vec<(usize, usize, Color)> {
+        (y0..y1.min(self.vsize))
+            .flat_map(|y| (x0..x1.min(self.hsize)).map(move |x| (x, y)))
+            .map(|(x, y)| (x, y, self.color_for_pixel(&world, x, y)))
+            .collect()
+    }
+
+    // surface normals remapped from -1..1 to 0..1, pixels that miss stay black
+    pub fn render_normals(self: &Camera, world: World) -> Canvas {
+        let mut canvas = canvas(self.hsize, self.vsize);
+        for x in 0..self.hsize {
+            for y in 0..self.vsize {
+                if let Some(comps) = world.hit_comps(&self.ray_for_pixel(x, y)) {
+                    let n = comps.normalv;
+                    let encoded = color(n.x + 1., n.y + 1., n.z + 1.) * 0.5;
+                    canvas.write_pixel(x, y, encoded);
+                }
+            }
         }
         canvas
     }
 
+    // distance to the nearest hit of every pixel row by row, None where the ray misses
+    pub fn render_depth(self: &Camera, world: World) -> Vec<Option<f64>> {
+        (0..self.vsize)
+            .flat_map(|y| (0..self.hsize).map(move |x| (x, y)))
+            .map(|(x, y)| world.hit_distance(&self.ray_for_pixel(x, y)))
+            .collect()
+    }
+
+    // index into world.objects of the object seen by every pixel row by row, for picking
+    pub fn render_ids(self: &Camera, world: World) -> Vec<Option<usize>> {
+        (0..self.vsize)
+            .flat_map(|y| (0..self.hsize).map(move |x| (x, y)))
+            .map(|(x, y)| world.hit_object_index(&self.ray_for_pixel(x, y)))
+            .collect()
+    }
+
+    // every pixel is independent, rayon spreads them over its thread pool
+    pub fn render_parallel(self: &Camera, world: World) -> Canvas {
+        let mut canvas = canvas(self.hsize, self.vsize);
+        canvas
+            .pixels
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(i, pixel)| {
+                *pixel = self.color_for_pixel(&world, i % self.hsize, i / self.hsize);
+            });
+        canvas
+    }
+
+    // the half-open pixel rectangle (x0, y0, x1, y1) containing every primary ray that could hit
+    // the world space region, the whole frame when the region is not entirely in front of camera
+    pub fn projected_rect(self: &Camera, region: &Bounds) -> (usize, usize, usize, usize) {
+        let view = self.invtransform.inverse();
+        let mut us = vec![];
+        let mut vs = vec![];
+        for corner in region.corners() {
+            let p = &view * &corner;
+            if !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()) || p.z >= 0. {
+                return (0, 0, self.hsize, self.vsize);
+            }
+            // project onto the canvas at z = -1 and measure from its top left edge
+            let (x, y) = match self.projection {
+                Projection::Perspective => (-p.x / p.z, -p.y / p.z),
+                Projection::Orthographic => (p.x, p.y),
+            };
+            us.push((self.half_width - x) / self.pixel_size);
+            vs.push((self.half_height - y) / self.pixel_size);
+        }
+        let span = |fs: Vec<f64>| {
+            fs.into_iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), f| {
+                    (lo.min(f), hi.max(f))
+                })
+        };
+        let (u0, u1) = span(us);
+        let (v0, v1) = span(vs);
+        let clip = |f: f64, size: usize| f.clamp(0., size as f64) as usize;
+        (
+            clip(u0.floor(), self.hsize),
+            clip(v0.floor(), self.vsize),
+            clip(u1.ceil(), self.hsize),
+            clip(v1.ceil(), self.vsize),
+        )
+    }
+
+    // re-renders only the pixels that can see the dirty region and keeps the rest of previous
+    // frame, the region has to cover both old and new positions of whatever changed
+    pub fn render_dirty(self: &Camera, world: World, previous: &Canvas, dirty: &Bounds) -> Canvas {
+        let mut canvas = previous.clone();
+        let (x0, y0, x1, y1) = self.projected_rect(dirty);
+        for (x, y, color) in self.render_tile(world, x0, y0, x1, y1) {
+            canvas.write_pixel(x, y, color);
+        }
+        canvas
+    }
+
+    // the top left pixel of every divisor by divisor block and the pixel traced to fill it
+    fn preview_samples(self: &Camera, divisor: usize) -> Vec<((usize, usize), (usize, usize))> {
+        let d = divisor.max(1);
+        let center = |block: usize, size: usize| (block + d / 2).min(size.saturating_sub(1));
+        (0..self.hsize)
+            .step_by(d)
+            .flat_map(|x| (0..self.vsize).step_by(d).map(move |y| (x, y)))
+            .map(|(x, y)| ((x, y), (center(x, self.hsize), center(y, self.vsize))))
+            .collect()
+    }
+
+    // traces one ray per divisor by divisor block and repeats its color over the whole block
+    pub fn render_preview(self: &Camera, world: World, divisor: usize) -> Canvas {
+        let d = divisor.max(1);
+        let mut canvas = canvas(self.hsize, self.vsize);
+        for ((x0, y0), (x, y)) in self.preview_samples(d) {
+            let color = world.color_at(&self.ray_for_pixel(x, y), self.max_depth);
+            for bx in x0..(x0 + d).min(self.hsize) {
+                for by in y0..(y0 + d).min(self.vsize) {
+                    canvas.write_pixel(bx, by, color.clone());
+                }
+            }
+        }
+        canvas
+    }
+
+    // sends the same coarse to fine passes as ProgressiveRenderer, so a preview fed by the
+    // channel is never blank and sharpens with every pass
+    pub fn render_progressive(
+        self: &Camera,
+        world: World,
+        pixel_sender: Sender<(usize, usize, Color)>,
+    ) {
+        let mut renderer = progressive_renderer(self, world);
+        let mut connected = true;
+        while connected && renderer.stride > 0 {
+            renderer.pass(|x, y, color| {
+                connected = connected && pixel_sender.send((x, y, color.clone())).is_ok();
+            });
+        }
+    }
+
     pub fn render_async(
         self: &Camera,
         world: World,
         pixel_sender: Sender<(usize, usize, Color)>,
         ix: Range<usize>,
+        cancel: Arc<AtomicBool>,
     ) -> () {
         for i in ix {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
             let x = i % self.hsize;
             let y = i / self.hsize;
-            let ray = self.ray_for_pixel(x, y);
-            let color = world.color_at(&ray, MAX_REFLECTIONS);
+            let color = self.color_for_pixel(&world, x, y);
             if let Err(_msg) = pixel_sender.send((x, y, color)) {
                 // receiver dropped the handle
                 break;
@@ -100,20 +397,90 @@ impl Camera {
     }
 }
 
+// renders coarse to fine, every step halves the stride between traced pixels and fills the gaps
+// with the nearest traced sample, pixels traced by earlier steps are not traced again
+pub struct ProgressiveRenderer {
+    camera: Camera,
+    world: World,
+    canvas: Canvas,
+    traced: Vec<bool>,
+    stride: usize,
+}
+
+pub fn progressive_renderer(camera: &Camera, world: World) -> ProgressiveRenderer {
+    ProgressiveRenderer {
+        canvas: canvas(camera.hsize, camera.vsize),
+        traced: vec![false; camera.hsize * camera.vsize],
+        camera: camera.clone(),
+        world,
+        stride: 8,
+    }
+}
+
+impl ProgressiveRenderer {
+    // one refinement step, every pixel it traces or fills is also handed to emit
+    fn pass<F: FnMut(usize, usize, &Color)>(&mut self, mut emit: F) {
+        let (w, h, stride) = (self.camera.hsize, self.camera.vsize, self.stride);
+        for x in (0..w).step_by(stride) {
+            for y in (0..h).step_by(stride) {
+                if !self.traced[y * w + x] {
+                    let color = self.camera.color_for_pixel(&self.world, x, y);
+                    emit(x, y, &color);
+                    self.canvas.write_pixel(x, y, color);
+                    self.traced[y * w + x] = true;
+                }
+                let sample = self.canvas.pixel_at(x, y).clone();
+                for bx in x..(x + stride).min(w) {
+                    for by in y..(y + stride).min(h) {
+                        if !self.traced[by * w + bx] {
+                            emit(bx, by, &sample);
+                            self.canvas.write_pixel(bx, by, sample.clone());
+                        }
+                    }
+                }
+            }
+        }
+        self.stride /= 2;
+    }
+}
+
+impl Iterator for ProgressiveRenderer {
+    type Item = Canvas;
+    fn next(&mut self) -> Option<Canvas> {
+        if self.stride == 0 {
+            return None;
+        }
+        self.pass(|_, _, _| {});
+        Some(self.canvas.clone())
+    }
+}
+
 #[cfg(test)]
 mod spec {
     use super::*;
+    use crate::bounds::bound;
+    use crate::canvas::canvas;
+    use crate::cubes::cube;
+    use crate::groups::group;
+    use crate::instances::moving_instance;
+    use crate::lights::point_light;
     use crate::matrices::identity_matrix;
+    use crate::planes::plane;
+    use crate::shapes::Shape;
+    use crate::spheres::sphere;
+    use crate::transformations::rotation_x;
     use crate::transformations::rotation_y;
+    use crate::transformations::scaling;
     use crate::transformations::translation;
     use crate::transformations::view_transform;
-    use crate::tuples::color;
     use crate::tuples::point;
     use crate::tuples::vector;
     use crate::world::spec::default_world;
+    use crate::world::world;
     use hamcrest2::prelude::*;
     use std::f64::consts::PI;
     use std::f64::EPSILON;
+    use std::sync::mpsc::channel;
 
     #[test]
     fn constructing_a_camera() {
@@ -172,6 +539,65 @@ mod spec {
         assert_that!(r.direction, eq(vector(sq2 / 2., 0., -sq2 / 2.)));
     }
 
+    #[test]
+    fn the_frustum_corners_bracket_the_center_ray() {
+        let mut c = camera(201, 101, PI / 2.);
+        c.invtransform = (rotation_y(PI / 4.) * translation(0., -2., 5.)).inverse();
+
+        let center = c.ray_for_pixel(100, 50);
+        let [tl, tr, bl, br] = c.frustum_corners();
+
+        for corner in &[&tl, &tr, &bl, &br] {
+            assert_that!(&corner.origin, eq(&center.origin));
+        }
+        assert_that!(
+            (&tl.direction + br.direction.clone()).normalized(),
+            eq(center.direction.clone())
+        );
+        assert_that!(
+            (&tr.direction + bl.direction.clone()).normalized(),
+            eq(center.direction.clone())
+        );
+        assert_that!(
+            tl.direction.dot(&center.direction),
+            close_to(tr.direction.dot(&center.direction), 1e-9)
+        );
+        assert_that!(
+            tl.direction.dot(&center.direction),
+            close_to(bl.direction.dot(&center.direction), 1e-9)
+        );
+    }
+
+    #[test]
+    fn an_orthographic_camera_shoots_parallel_rays() {
+        let c = orthographic_camera(201, 101, 4.);
+
+        let center = c.ray_for_pixel(100, 50);
+        let corner = c.ray_for_pixel(0, 0);
+
+        assert_that!(center.origin, eq(point(0., 0., 0.)));
+        assert_that!(center.direction, eq(vector(0., 0., -1.)));
+        assert_that!(corner.origin, eq(point(1.99005, 0.99502, 0.)));
+        assert_that!(corner.direction, eq(vector(0., 0., -1.)));
+    }
+
+    #[test]
+    fn orthographic_projection_keeps_the_separation_of_lines_at_any_depth() {
+        let near = bound(point(-1., 0., -2.), point(1., 0., -2.));
+        let far = bound(point(-1., 0., -8.), point(1., 0., -8.));
+        let width = |c: &Camera, b: &Bounds| {
+            let (x0, _, x1, _) = c.projected_rect(b);
+            x1 - x0
+        };
+
+        let ortho = orthographic_camera(100, 100, 4.);
+        let perspective = camera(100, 100, PI / 2.);
+
+        assert_eq!(width(&ortho, &near), width(&ortho, &far));
+        assert_eq!(width(&ortho, &near), 50);
+        assert_that!(width(&perspective, &near), gt(width(&perspective, &far)));
+    }
+
     #[test]
     fn rendering_a_world_with_a_camera() {
         let w = default_world();
@@ -185,4 +611,377 @@ mod spec {
 
         assert_eq!(image.pixel_at(5, 5), &color(0.38066, 0.47583, 0.2855));
     }
+
+    #[test]
+    fn a_cancelled_render_leaves_the_canvas_black() {
+        let w = default_world();
+        let mut c = camera(11, 11, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+
+        let image = c.render_cancellable(w, Arc::new(AtomicBool::new(true)));
+
+        assert!(image.pixels.iter().all(|p| p == &Color::BLACK));
+    }
+
+    #[test]
+    fn an_uncancelled_render_matches_render() {
+        let w = default_world();
+        let mut c = camera(11, 11, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+
+        let image = c.render_cancellable(w.clone(), Arc::new(AtomicBool::new(false)));
+
+        assert_eq!(image.pixels, c.render(w).pixels);
+    }
+
+    #[test]
+    fn stitching_rendered_tiles_reproduces_the_full_image() {
+        let w = default_world();
+        let mut c = camera(11, 11, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+        let mut stitched = canvas(11, 11);
+
+        for (x0, y0, x1, y1) in [(0, 0, 6, 6), (6, 0, 11, 6), (0, 6, 6, 11), (6, 6, 11, 11)].iter()
+        {
+            for (x, y, color) in c.render_tile(w.clone(), *x0, *y0, *x1, *y1) {
+                stitched.write_pixel(x, y, color);
+            }
+        }
+
+        assert_eq!(stitched.pixels, c.render(w).pixels);
+    }
+
+    #[test]
+    fn a_progressive_render_sends_every_pixel() {
+        let w = default_world();
+        let c = camera(11, 11, PI / 2.);
+        let (sender, receiver) = channel();
+
+        c.render_progressive(w, sender);
+
+        let mut sent = [false; 11 * 11];
+        for (x, y, _) in receiver.iter() {
+            sent[y * 11 + x] = true;
+        }
+        assert!(sent.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn rendering_the_normals_of_a_sphere_facing_the_camera() {
+        let mut w = world();
+        w.objects = vec![Arc::new(sphere())];
+        let mut c = camera(11, 11, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., 5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+
+        let image = c.render_normals(w);
+
+        assert_eq!(image.pixel_at(5, 5), &color(0.5, 0.5, 1.));
+        assert_eq!(image.pixel_at(0, 0), &Color::BLACK);
+    }
+
+    #[test]
+    fn rendering_the_depth_of_a_wall() {
+        let mut wall = plane();
+        wall.set_transform(translation(0., 0., -5.) * rotation_x(PI / 2.));
+        let mut w = world();
+        w.objects = vec![Arc::new(wall)];
+        let c = camera(11, 11, PI / 2.);
+
+        let depth = c.render_depth(w);
+
+        for y in 0..11 {
+            let r = c.ray_for_pixel(5, y);
+            let expected = 5. / -r.direction.z;
+            assert_that!(depth[y * 11 + 5].unwrap(), close_to(expected, 1e-9));
+        }
+        assert_that!(depth[5 * 11 + 5].unwrap(), close_to(5., 1e-9));
+    }
+
+    #[test]
+    fn rendering_the_ids_of_objects() {
+        let mut left = sphere();
+        left.set_transform(translation(-2., 0., 0.));
+        let mut right = group();
+        let mut s = sphere();
+        s.set_transform(translation(2., 0., 0.));
+        right.add_child(s);
+        let mut w = world();
+        w.objects = vec![Arc::new(left), Arc::new(right)];
+        let mut c = camera(11, 11, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+
+        let ids = c.render_ids(w);
+
+        assert_eq!(ids[5 * 11 + 3], Some(0));
+        assert_eq!(ids[5 * 11 + 7], Some(1));
+        assert_eq!(ids[5 * 11 + 5], None);
+        assert_eq!(ids[0], None);
+    }
+
+    #[test]
+    fn rendering_reports_increasing_progress() {
+        let w = default_world();
+        let c = camera(11, 11, PI / 2.);
+        let mut reports = vec![];
+
+        c.render_with_progress(w, |fraction| reports.push(fraction));
+
+        assert_eq!(reports.len(), 11);
+        assert!(reports.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_that!(*reports.last().unwrap(), close_to(1., 1e-9));
+    }
+
+    #[test]
+    fn supersampling_averages_a_pixel_on_a_hard_edge() {
+        let mut wall = cube();
+        wall.material.ambient = 1.;
+        wall.material.diffuse = 0.;
+        wall.material.specular = 0.;
+        wall.set_transform(translation(-3., 0., 0.) * scaling(3., 10., 1.));
+        let mut w = world();
+        w.light_sources = vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()];
+        w.objects = vec![Arc::new(wall)];
+        let mut c = camera(3, 3, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+        c.aa = 2;
+
+        let image = c.render(w);
+
+        assert_eq!(image.pixel_at(0, 1), &color(1., 1., 1.));
+        assert_eq!(image.pixel_at(1, 1), &color(0.5, 0.5, 0.5));
+        assert_eq!(image.pixel_at(2, 1), &color(0., 0., 0.));
+    }
+
+    #[test]
+    fn rendering_in_parallel_matches_the_serial_render() {
+        let mut c = camera(11, 11, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+
+        let serial = c.render(default_world());
+        let parallel = c.render_parallel(default_world());
+
+        assert_eq!(parallel.pixels, serial.pixels);
+    }
+
+    #[test]
+    fn rendering_mutually_reflective_planes_with_limited_depth() {
+        let mut lower = plane();
+        lower.material.reflective = 1.;
+        lower.set_transform(translation(0., -1., 0.));
+        let mut upper = plane();
+        upper.material.reflective = 1.;
+        upper.set_transform(translation(0., 1., 0.));
+        let mut w = world();
+        w.light_sources = vec![point_light(point(0., 0., 0.), color(1., 1., 1.)).into()];
+        w.objects = vec![Arc::new(lower), Arc::new(upper)];
+        let mut c = camera(5, 5, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., 0.), &point(0., 1., 1.), &vector(0., 1., 0.)).inverse();
+
+        let deep = c.render(w.clone());
+        c.max_depth = 0;
+        let shallow = c.render(w);
+
+        assert_that!(deep.pixel_at(2, 2), not(eq(shallow.pixel_at(2, 2))));
+    }
+
+    #[test]
+    fn a_deeper_render_sees_through_more_panes_of_glass_than_the_default() {
+        let mut w = world();
+        w.light_sources = vec![point_light(point(0., 0., 0.), color(1., 1., 1.)).into()];
+        for z in 1..=MAX_REFLECTIONS + 2 {
+            let mut pane = plane();
+            pane.material.color = Color::BLACK;
+            pane.material.specular = 0.;
+            pane.material.transparency = 1.;
+            pane.material.refractive_index = 1.;
+            pane.set_transform(translation(0., 0., -(z as f64)) * rotation_x(PI / 2.));
+            w.add_object(pane);
+        }
+        let mut wall = plane();
+        wall.material.ambient = 1.;
+        wall.set_transform(translation(0., 0., -20.) * rotation_x(PI / 2.));
+        w.add_object(wall);
+        let mut c = camera(5, 5, PI / 2.);
+        let mut center = |max_depth| {
+            c.max_depth = max_depth;
+            c.render(w.clone()).pixel_at(2, 2).clone()
+        };
+
+        assert_eq!(center(MAX_REFLECTIONS), Color::BLACK);
+        assert_that!(center(MAX_REFLECTIONS + 2), not(eq(Color::BLACK)));
+    }
+
+    #[test]
+    fn a_preview_of_an_empty_camera_has_no_samples() {
+        for &(hsize, vsize) in &[(0, 4), (4, 0), (0, 0)] {
+            let c = camera(hsize, vsize, PI / 2.);
+
+            assert!(c.preview_samples(2).is_empty());
+            assert!(c.render_preview(default_world(), 2).pixels.is_empty());
+        }
+    }
+
+    #[test]
+    fn rendering_a_preview_at_half_resolution() {
+        let w = default_world();
+        let mut c = camera(11, 11, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+
+        let samples = c.preview_samples(2);
+        let image = c.render_preview(w, 2);
+
+        assert_eq!(samples.len(), 36);
+        assert_eq!((image.width, image.height), (11, 11));
+        assert_eq!(image.pixel_at(4, 4), image.pixel_at(5, 5));
+        assert_eq!(image.pixel_at(0, 0), image.pixel_at(1, 1));
+        assert_eq!(image.pixel_at(5, 5), &color(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn progressive_rendering_converges_to_the_full_render() {
+        let mut c = camera(21, 21, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+        let full = c.render(default_world());
+        let difference = |image: &Canvas| -> f64 {
+            image
+                .pixels
+                .iter()
+                .zip(&full.pixels)
+                .map(|(a, b)| {
+                    (a.red - b.red).abs() + (a.green - b.green).abs() + (a.blue - b.blue).abs()
+                })
+                .sum()
+        };
+
+        let steps: Vec<f64> = progressive_renderer(&c, default_world())
+            .map(|image| difference(&image))
+            .collect();
+
+        assert_eq!(steps.len(), 4);
+        assert!(steps.windows(2).all(|w| w[1] <= w[0]), "{:?}", steps);
+        assert!(steps[0] > 0.);
+        assert_eq!(steps[3], 0.);
+    }
+
+    #[test]
+    fn rerendering_only_the_region_of_a_moved_object() {
+        let mut c = camera(21, 21, PI / 2.);
+        c.invtransform =
+            view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+        let mut before = sphere();
+        before.set_transform(translation(-1., 0., 0.) * scaling(0.3, 0.3, 0.3));
+        let mut after = sphere();
+        after.set_transform(translation(-0.8, 0.2, 0.) * scaling(0.3, 0.3, 0.3));
+        let dirty = before
+            .local_bounds()
+            .transform(&before.invtransform().inverse())
+            + after
+                .local_bounds()
+                .transform(&after.invtransform().inverse());
+        let mut w = world();
+        w.light_sources = vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()];
+        w.objects = vec![Arc::new(after)];
+        let mut previous = canvas(21, 21);
+        for x in 0..21 {
+            for y in 0..21 {
+                previous.write_pixel(x, y, color(1., 0., 0.));
+            }
+        }
+
+        let (x0, y0, x1, y1) = c.projected_rect(&dirty);
+        let image = c.render_dirty(w.clone(), &previous, &dirty);
+        let full = c.render(w);
+
+        assert_that!((x1 - x0) * (y1 - y0), lt(21 * 21 / 4));
+        for x in 0..21 {
+            for y in 0..21 {
+                if x0 <= x && x < x1 && y0 <= y && y < y1 {
+                    assert_eq!(image.pixel_at(x, y), full.pixel_at(x, y));
+                } else {
+                    assert_eq!(image.pixel_at(x, y), &color(1., 0., 0.));
+                    assert_eq!(full.pixel_at(x, y), &color(0., 0., 0.));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_moving_sphere_is_smeared_along_its_path() {
+        let covered = |end: f64| {
+            let mut w = world();
+            w.add_light(point_light(point(0., 0., 10.), color(1., 1., 1.)));
+            w.add_object(moving_instance(
+                Arc::new(sphere()),
+                translation(-2., 0., -5.),
+                translation(end, 0., -5.),
+            ));
+            let mut c = orthographic_camera(41, 11, 10.);
+            c.aa = 4;
+            c.shutter = 1.;
+            let image = c.render(w);
+            (0..41)
+                .filter(|&x| image.pixel_at(x, 5) != &Color::BLACK)
+                .count()
+        };
+
+        let still = covered(-2.);
+        let moving = covered(2.);
+
+        assert_that!(still, is(less_than(10)));
+        assert_that!(moving, is(greater_than(still + 10)));
+    }
+
+    #[test]
+    fn every_sample_of_a_pixel_gets_its_own_time_slot() {
+        for &(x, y) in &[(0, 0), (3, 7), (100, 20)] {
+            let mut slots = time_slots(x, y, 16);
+            slots.sort_unstable();
+
+            assert_eq!(slots, (0..16).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn the_time_of_a_sample_does_not_follow_its_position_in_the_pixel() {
+        let in_order = (0..16).collect::<Vec<_>>();
+
+        let shuffled = (0..10)
+            .filter(|&x| time_slots(x, 0, 16) != in_order)
+            .count();
+
+        assert_eq!(shuffled, 10);
+        assert_ne!(time_slots(0, 0, 16), time_slots(1, 0, 16));
+    }
+
+    #[test]
+    fn a_shutter_longer_than_the_motion_stops_at_the_end_pose() {
+        let render = |shutter| {
+            let mut w = world();
+            w.add_light(point_light(point(0., 0., 10.), color(1., 1., 1.)));
+            w.add_object(moving_instance(
+                Arc::new(sphere()),
+                translation(-2., 0., -5.),
+                translation(2., 0., -5.),
+            ));
+            let mut c = orthographic_camera(41, 11, 10.);
+            c.aa = 4;
+            c.shutter = shutter;
+            let image = c.render(w);
+            (0..41)
+                .map(|x| image.pixel_at(x, 5).clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(render(3.), render(1.));
+    }
 }
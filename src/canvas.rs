@@ -1,29 +1,127 @@
 use crate::tuples::{color, Color};
 
+#[derive(Clone)]
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
     pub pixels: Vec<Color>,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct OutOfBounds {
+    pub x: usize,
+    pub y: usize,
+}
+
 pub fn canvas(width: usize, height: usize) -> Canvas {
     Canvas {
         width,
         height,
-        pixels: vec![color(0.0, 0.0, 0.0); width * height],
+        pixels: vec![Color::BLACK; width * height],
     }
 }
 
 impl Canvas {
     pub fn write_pixel(&mut self, x: usize, y: usize, c: Color) {
+        self.assert_contains(x, y);
         let i = self.width * y + x;
         self.pixels[i] = c;
     }
 
     pub fn pixel_at(&self, x: usize, y: usize) -> &Color {
+        self.assert_contains(x, y);
         let i = self.width * y + x;
         &self.pixels[i]
     }
+
+    pub fn try_write_pixel(&mut self, x: usize, y: usize, c: Color) -> Result<(), OutOfBounds> {
+        if self.contains(x, y) {
+            self.write_pixel(x, y, c);
+            Ok(())
+        } else {
+            Err(OutOfBounds { x, y })
+        }
+    }
+
+    pub fn try_pixel_at(&self, x: usize, y: usize) -> Option<&Color> {
+        if self.contains(x, y) {
+            Some(self.pixel_at(x, y))
+        } else {
+            None
+        }
+    }
+
+    // the w by h region starting at x, y, cut short where it runs past the canvas
+    pub fn crop(&self, x: usize, y: usize, w: usize, h: usize) -> Canvas {
+        let w = w.min(self.width.saturating_sub(x));
+        let h = h.min(self.height.saturating_sub(y));
+        let mut sub = canvas(w, h);
+        for row in 0..h {
+            let from = self.width * (y + row) + x;
+            sub.pixels[w * row..w * (row + 1)].clone_from_slice(&self.pixels[from..from + w]);
+        }
+        sub
+    }
+
+    // copies src with its top left corner at x, y, the pixels that fall outside are dropped
+    pub fn blit(&mut self, src: &Canvas, x: usize, y: usize) {
+        let w = src.width.min(self.width.saturating_sub(x));
+        let h = src.height.min(self.height.saturating_sub(y));
+        for row in 0..h {
+            let to = self.width * (y + row) + x;
+            let from = src.width * row;
+            self.pixels[to..to + w].clone_from_slice(&src.pixels[from..from + w]);
+        }
+    }
+
+    // channels clamped to 0..1 and raised to 1/gamma, gamma 1 keeps the linear values
+    pub fn with_gamma(&self, gamma: f64) -> Canvas {
+        self.map_channels(|f| f.clamp(0., 1.).powf(1. / gamma))
+    }
+
+    // scales the light by 2^stops, the result is not clamped so it can still be tone mapped
+    pub fn with_exposure(&self, stops: f64) -> Canvas {
+        let scale = 2_f64.powf(stops);
+        self.map_channels(|f| f * scale)
+    }
+
+    // compresses any brightness into 0..1 instead of clipping it to white
+    pub fn tone_map_reinhard(&self) -> Canvas {
+        self.map_channels(|f| f / (1. + f))
+    }
+
+    // the extended operator, channels at the white point and above map to 1
+    pub fn tone_map_reinhard_with_white(&self, white: f64) -> Canvas {
+        self.map_channels(|f| (f * (1. + f / (white * white)) / (1. + f)).min(1.))
+    }
+
+    fn map_channels<F: Fn(f64) -> f64>(&self, f: F) -> Canvas {
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|c| color(f(c.red), f(c.green), f(c.blue)))
+                .collect(),
+        }
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height
+    }
+
+    // a large x would otherwise alias a pixel of a later row instead of failing
+    fn assert_contains(&self, x: usize, y: usize) {
+        assert!(
+            self.contains(x, y),
+            "coordinate out of bounds: ({}, {}) on a {}x{} canvas",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+    }
 }
 
 #[cfg(test)]
@@ -48,4 +146,129 @@ mod spec {
         c.write_pixel(2, 3, red.clone());
         assert_eq!(c.pixel_at(2, 3), &red);
     }
+
+    #[test]
+    fn checked_access_inside_a_canvas() {
+        let mut c = canvas(10, 20);
+        let red = color(1.0, 0.0, 0.0);
+
+        assert_eq!(c.try_write_pixel(9, 19, red.clone()), Ok(()));
+        assert_eq!(c.try_pixel_at(9, 19), Some(&red));
+        assert_eq!(c.pixel_at(9, 19), &red);
+    }
+
+    #[test]
+    fn checked_access_outside_a_canvas() {
+        let mut c = canvas(10, 20);
+        let red = color(1.0, 0.0, 0.0);
+
+        assert_eq!(
+            c.try_write_pixel(10, 0, red.clone()),
+            Err(OutOfBounds { x: 10, y: 0 })
+        );
+        assert_eq!(
+            c.try_write_pixel(0, 20, red.clone()),
+            Err(OutOfBounds { x: 0, y: 20 })
+        );
+        assert_eq!(c.try_pixel_at(10, 0), None);
+        assert_eq!(c.try_pixel_at(0, 20), None);
+        assert_eq!(c.pixel_at(0, 1), &color(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "coordinate out of bounds: (10, 0) on a 10x20 canvas")]
+    fn writing_outside_a_canvas_panics() {
+        let mut c = canvas(10, 20);
+        c.write_pixel(10, 0, color(1.0, 0.0, 0.0));
+    }
+
+    fn gradient(width: usize, height: usize) -> Canvas {
+        let mut c = canvas(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                c.write_pixel(x, y, color(x as f64, y as f64, 0.));
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn cropping_a_canvas() {
+        let c = gradient(10, 20);
+
+        let sub = c.crop(2, 3, 4, 5);
+
+        assert_eq!((sub.width, sub.height), (4, 5));
+        assert_eq!(sub.pixel_at(0, 0), &color(2., 3., 0.));
+        assert_eq!(sub.pixel_at(3, 4), &color(5., 7., 0.));
+    }
+
+    #[test]
+    fn cropping_past_the_edge_keeps_what_is_inside() {
+        let c = gradient(10, 20);
+
+        let sub = c.crop(8, 18, 4, 5);
+
+        assert_eq!((sub.width, sub.height), (2, 2));
+        assert_eq!(sub.pixel_at(1, 1), &color(9., 19., 0.));
+    }
+
+    #[test]
+    fn blitting_a_crop_back_restores_the_canvas() {
+        let original = gradient(10, 20);
+        let sub = original.crop(2, 3, 4, 5);
+        let mut c = original.clone();
+        c.blit(&canvas(4, 5), 2, 3);
+        assert_eq!(c.pixel_at(2, 3), &Color::BLACK);
+
+        c.blit(&sub, 2, 3);
+
+        assert_eq!(c.pixels, original.pixels);
+    }
+
+    #[test]
+    fn blitting_past_the_edge_clips() {
+        let mut c = canvas(10, 20);
+        let src = gradient(4, 4);
+
+        c.blit(&src, 8, 18);
+        c.blit(&src, 30, 30);
+
+        assert_eq!(c.pixel_at(8, 18), &color(0., 0., 0.));
+        assert_eq!(c.pixel_at(9, 19), &color(1., 1., 0.));
+        assert_eq!(c.pixel_at(7, 17), &Color::BLACK);
+    }
+
+    #[test]
+    fn reinhard_tone_mapping_compresses_bright_channels() {
+        let mut c = canvas(1, 1);
+        c.write_pixel(0, 0, color(3., 0., 1.));
+
+        let mapped = c.tone_map_reinhard();
+
+        assert_eq!(mapped.pixel_at(0, 0), &color(0.75, 0., 0.5));
+    }
+
+    #[test]
+    fn reinhard_tone_mapping_with_a_white_point() {
+        let mut c = canvas(1, 1);
+        c.write_pixel(0, 0, color(4., 0., 8.));
+
+        let mapped = c.tone_map_reinhard_with_white(4.);
+
+        assert_eq!(mapped.pixel_at(0, 0), &color(1., 0., 1.));
+    }
+
+    #[test]
+    fn each_stop_of_exposure_doubles_the_light() {
+        let mut c = canvas(1, 1);
+        c.write_pixel(0, 0, color(0.25, 0.5, 0.75));
+
+        assert_eq!(c.with_exposure(1.).pixel_at(0, 0), &color(0.5, 1., 1.5));
+        assert_eq!(
+            c.with_exposure(-1.).pixel_at(0, 0),
+            &color(0.125, 0.25, 0.375)
+        );
+        assert_eq!(c.with_exposure(0.).pixels, c.pixels);
+    }
 }
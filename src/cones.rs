@@ -2,13 +2,16 @@ use crate::bounds::bound;
 use crate::bounds::Bounds;
 use crate::intersections::intersection;
 use crate::intersections::Intersection;
+use crate::intersections::Part;
 use crate::intersections::EPSILON;
 use crate::materials::material;
 use crate::materials::Material;
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
 use crate::rays::Ray;
+use crate::shapes::shape_base;
 use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
 use crate::shapes::SyncShape;
 use crate::tuples::point;
 use crate::tuples::vector;
@@ -17,13 +20,17 @@ use std::f64::INFINITY;
 use std::f64::NEG_INFINITY;
 use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Cone {
-    pub invtransform: Matrix,
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
     pub material: Material,
     pub minimum: f64,
     pub maximum: f64,
     pub closed: bool,
+    // radius of the sides at y = 1, the slope of the cone
+    pub radius: f64,
 }
 
 fn check_cap(ray: &Ray, t: f64, r: f64) -> bool {
@@ -40,13 +47,16 @@ impl Cone {
                 .into_iter()
                 .filter_map(|m| {
                     let t = (m - ray.origin.y) / ray.direction.y;
-                    if check_cap(ray, t, m) {
+                    if check_cap(ray, t, m * self.radius) {
                         Some(t)
                     } else {
                         None
                     }
                 })
-                .map(|t| intersection(t, rc.clone()))
+                .map(|t| Intersection {
+                    part: Part::Cap,
+                    ..intersection(t, rc.clone())
+                })
                 .collect()
         }
     }
@@ -58,13 +68,17 @@ impl Cone {
         let oy = ray.origin.y;
         let oz = ray.origin.z;
 
-        let a = dx.powi(2) - dy.powi(2) + dz.powi(2);
-        let b = 2. * (ox * dx - oy * dy + oz * dz);
-        let c = ox.powi(2) - oy.powi(2) + oz.powi(2);
+        let r2 = self.radius.powi(2);
+        let a = dx.powi(2) - r2 * dy.powi(2) + dz.powi(2);
+        let b = 2. * (ox * dx - r2 * oy * dy + oz * dz);
+        let c = ox.powi(2) - r2 * oy.powi(2) + oz.powi(2);
         let discriminant = b.powi(2) - 4. * a * c;
 
         if a.abs() < EPSILON && EPSILON < b.abs() {
-            vec![intersection(-c / (2. * b), rc.clone())]
+            vec![Intersection {
+                part: Part::Side,
+                ..intersection(-c / (2. * b), rc.clone())
+            }]
         } else if discriminant < 0. {
             vec![]
         } else {
@@ -77,7 +91,10 @@ impl Cone {
                 let y = oy + t * dy;
                 self.minimum < y && y < self.maximum
             })
-            .map(|t| intersection(t, rc.clone()))
+            .map(|t| Intersection {
+                part: Part::Side,
+                ..intersection(t, rc.clone())
+            })
             .collect()
         }
     }
@@ -85,10 +102,14 @@ impl Cone {
 
 impl Shape for Cone {
     fn local_bounds(&self) -> Bounds {
-        bound(
-            point(self.minimum, self.minimum, self.minimum),
-            point(self.maximum, self.maximum, self.maximum),
-        )
+        // the sides are widest at whichever end is furthest from the tip,
+        // a flat cone stays flat even when it is unbounded
+        let e = if self.radius == 0. {
+            0.
+        } else {
+            self.radius.abs() * self.minimum.abs().max(self.maximum.abs())
+        };
+        bound(point(-e, self.minimum, -e), point(e, self.maximum, e))
     }
     fn material(&self) -> &Material {
         &self.material
@@ -100,16 +121,28 @@ impl Shape for Cone {
         &self.invtransform
     }
     fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
         self.invtransform = invtransform;
     }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
     fn local_normal_at(&self, point: Tuple) -> Tuple {
         let dist = point.x.powi(2) + point.z.powi(2);
-        if dist < 1. && point.y >= self.maximum - EPSILON {
+        // the caps are as wide as the sides at their height
+        let cap = (self.radius * point.y).powi(2);
+        if dist < cap && point.y >= self.maximum - EPSILON {
             vector(0., 1., 0.)
-        } else if dist < 1. && point.y <= self.minimum + EPSILON {
+        } else if dist < cap && point.y <= self.minimum + EPSILON {
             vector(0., -1., 0.)
         } else {
-            let y = dist.sqrt();
+            let y = dist.sqrt() * self.radius;
             let y = if point.y > 0. { -y } else { y };
             vector(point.x, y, point.z)
         }
@@ -126,9 +159,12 @@ pub fn cone() -> Cone {
     Cone {
         material,
         invtransform,
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         minimum: NEG_INFINITY,
         maximum: INFINITY,
         closed: false,
+        radius: 1.,
     }
 }
 #[cfg(test)]
@@ -184,6 +220,20 @@ mod spec {
         }
     }
     #[test]
+    fn intersections_record_which_part_of_a_closed_cone_was_hit() {
+        let mut c = cone();
+        c.minimum = -0.5;
+        c.maximum = 0.5;
+        c.closed = true;
+        let c = Arc::new(c);
+
+        let xs = c.local_intersects(c.clone(), ray(point(0., 0., -0.25), vector(0., 1., 0.)));
+
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs.iter().filter(|i| i.part == Part::Side).count(), 2);
+        assert_eq!(xs.iter().filter(|i| i.part == Part::Cap).count(), 2);
+    }
+    #[test]
     fn normal_vector_on_a_cone() {
         let c = cone();
         for (point, normal) in vec![
@@ -214,7 +264,60 @@ mod spec {
 
         assert_eq!(
             c.local_bounds(),
-            bound(point(-2., -2., -2.), point(4., 4., 4.))
+            bound(point(-4., -2., -4.), point(4., 4., 4.))
+        );
+    }
+    #[test]
+    fn the_bounds_of_a_cone_follow_its_widest_end() {
+        let mut c = cone();
+        c.minimum = -1.;
+        c.maximum = 0.5;
+        c.radius = 2.;
+
+        assert_eq!(
+            c.local_bounds(),
+            bound(point(-2., -1., -2.), point(2., 0.5, 2.))
+        );
+        c.radius = 0.;
+        c.minimum = f64::NEG_INFINITY;
+        assert_eq!(
+            c.local_bounds(),
+            bound(point(0., f64::NEG_INFINITY, 0.), point(0., 0.5, 0.))
+        );
+    }
+    #[test]
+    fn the_normal_on_the_caps_of_a_cone_with_radius() {
+        let mut c = cone();
+        c.minimum = -1.;
+        c.maximum = 2.;
+        c.closed = true;
+        c.radius = 0.5;
+        for (point, normal) in [
+            (point(0.5, 2., 0.), vector(0., 1., 0.)),
+            (point(0., 2., 0.9), vector(0., 1., 0.)),
+            (point(0.2, -1., 0.), vector(0., -1., 0.)),
+        ] {
+            assert_eq!(c.local_normal_at(point), normal);
+        }
+        // the side next to the narrow bottom cap keeps the side normal
+        assert_ne!(c.local_normal_at(point(0.5, -1., 0.)), vector(0., -1., 0.));
+    }
+
+    #[test]
+    fn intersecting_a_cone_with_radius() {
+        let mut c = cone();
+        c.radius = 2.;
+        let c = Arc::new(c);
+        let r = ray(point(0., 1., -5.), vector(0., 0., 1.));
+
+        let xs = c.local_intersects(c.clone(), r);
+
+        assert_eq!(xs.len(), 2);
+        assert_that!(xs[0].t, close_to(3., 1e-9));
+        assert_that!(xs[1].t, close_to(7., 1e-9));
+        assert_that!(
+            c.local_normal_at(point(0., 1., -2.)).normalized(),
+            eq(vector(0., -2., -1.).normalized())
         );
     }
 }
@@ -0,0 +1,316 @@
+use crate::bounds::Bounds;
+use crate::groups::parent_space_bounds;
+use crate::groups::wrap;
+use crate::intersections::compare_t;
+use crate::intersections::Intersection;
+use crate::materials::material;
+use crate::materials::Material;
+use crate::matrices::identity_matrix;
+use crate::matrices::Matrix;
+use crate::rays::Ray;
+use crate::shapes::shape_base;
+use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
+use crate::shapes::SyncShape;
+use crate::tuples::Tuple;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+#[derive(Clone, Debug)]
+pub struct Csg {
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
+    pub operation: CsgOp,
+    pub left: Arc<SyncShape>,
+    pub right: Arc<SyncShape>,
+    bounds: Bounds,
+    material: Material,
+}
+
+impl PartialEq for Csg {
+    fn eq(&self, other: &Csg) -> bool {
+        self.invtransform == other.invtransform
+            && self.operation == other.operation
+            && self.left.eq(&other.left)
+            && self.right.eq(&other.right)
+    }
+}
+
+pub fn csg(operation: CsgOp, left: Arc<SyncShape>, right: Arc<SyncShape>) -> Csg {
+    let bounds = parent_space_bounds(&left) + parent_space_bounds(&right);
+    Csg {
+        invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
+        operation,
+        left,
+        right,
+        bounds,
+        material: material(),
+    }
+}
+
+pub fn intersection_allowed(op: CsgOp, lhit: bool, inl: bool, inr: bool) -> bool {
+    match op {
+        CsgOp::Union => (lhit && !inr) || (!lhit && !inl),
+        CsgOp::Intersection => (lhit && inr) || (!lhit && inl),
+        CsgOp::Difference => (lhit && !inr) || (!lhit && inl),
+    }
+}
+
+impl Csg {
+    // xs are sorted hits, each tagged with true when it belongs to the left child
+    fn filter_intersections(&self, xs: Vec<(bool, Intersection)>) -> Vec<Intersection> {
+        let mut inl = false;
+        let mut inr = false;
+        let mut result = vec![];
+        for (lhit, i) in xs {
+            if intersection_allowed(self.operation, lhit, inl, inr) {
+                result.push(i);
+            }
+            if lhit {
+                inl = !inl;
+            } else {
+                inr = !inr;
+            }
+        }
+        result
+    }
+}
+
+impl Shape for Csg {
+    fn local_bounds(&self) -> Bounds {
+        self.bounds.clone()
+    }
+    fn primitive_count(&self) -> usize {
+        self.left.primitive_count() + self.right.primitive_count()
+    }
+    fn has_bounding_hierarchy(&self) -> bool {
+        true
+    }
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    // hits are shaded with the material of the child, so the material is passed down,
+    // children shared outside of the csg keep their own
+    fn set_material(&mut self, material: Material) {
+        for child in [&mut self.left, &mut self.right].iter_mut() {
+            if let Some(c) = Arc::get_mut(child) {
+                c.set_material(material.clone());
+            }
+        }
+        self.material = material;
+    }
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
+        self.invtransform = invtransform;
+    }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
+    fn local_intersects(&self, _rc: Arc<SyncShape>, ray: Ray) -> Vec<Intersection> {
+        if !self.bounds.intersects(&ray) {
+            return vec![];
+        }
+        let tagged = |lhit: bool, c: &Arc<SyncShape>| {
+            c.intersects(c.clone(), &ray)
+                .into_iter()
+                .map(move |i| (lhit, i))
+        };
+        let mut xs: Vec<(bool, Intersection)> = tagged(true, &self.left)
+            .chain(tagged(false, &self.right))
+            .collect();
+        xs.sort_by(|a, b| compare_t(a.1.t, b.1.t));
+        self.filter_intersections(xs)
+            .into_iter()
+            .map(|mut i| {
+                i.object = wrap(&self.invtransform, i.object);
+                i
+            })
+            .collect()
+    }
+    // hits are reported on the child that owns them, so normals never come from csg itself
+    fn local_normal_at(&self, _local_point: Tuple) -> Tuple {
+        unreachable!("normals of a csg are computed by the child owning the hit")
+    }
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use crate::bounds::bound;
+    use crate::cubes::cube;
+    use crate::intersections::intersection;
+    use crate::rays::ray;
+    use crate::spheres::sphere;
+    use crate::transformations::translation;
+    use crate::tuples::point;
+    use crate::tuples::vector;
+    use hamcrest2::prelude::*;
+
+    #[test]
+    fn csg_is_created_with_an_operation_and_two_shapes() {
+        let s1: Arc<SyncShape> = Arc::new(sphere());
+        let s2: Arc<SyncShape> = Arc::new(cube());
+
+        let c = csg(CsgOp::Union, s1.clone(), s2.clone());
+
+        assert_eq!(c.operation, CsgOp::Union);
+        assert!(Arc::ptr_eq(&c.left, &s1));
+        assert!(Arc::ptr_eq(&c.right, &s2));
+    }
+
+    #[test]
+    fn evaluating_the_rule_for_a_csg_operation() {
+        use CsgOp::*;
+        for &(op, lhit, inl, inr, result) in &[
+            (Union, true, true, true, false),
+            (Union, true, true, false, true),
+            (Union, true, false, true, false),
+            (Union, true, false, false, true),
+            (Union, false, true, true, false),
+            (Union, false, true, false, false),
+            (Union, false, false, true, true),
+            (Union, false, false, false, true),
+            (Intersection, true, true, true, true),
+            (Intersection, true, true, false, false),
+            (Intersection, true, false, true, true),
+            (Intersection, true, false, false, false),
+            (Intersection, false, true, true, true),
+            (Intersection, false, true, false, true),
+            (Intersection, false, false, true, false),
+            (Intersection, false, false, false, false),
+            (Difference, true, true, true, false),
+            (Difference, true, true, false, true),
+            (Difference, true, false, true, false),
+            (Difference, true, false, false, true),
+            (Difference, false, true, true, true),
+            (Difference, false, true, false, true),
+            (Difference, false, false, true, false),
+            (Difference, false, false, false, false),
+        ] {
+            assert_eq!(
+                intersection_allowed(op, lhit, inl, inr),
+                result,
+                "{:?} {} {} {}",
+                op,
+                lhit,
+                inl,
+                inr
+            );
+        }
+    }
+
+    #[test]
+    fn filtering_a_list_of_intersections() {
+        let s1: Arc<SyncShape> = Arc::new(sphere());
+        let s2: Arc<SyncShape> = Arc::new(cube());
+        for &(op, x0, x1) in &[
+            (CsgOp::Union, 0, 3),
+            (CsgOp::Intersection, 1, 2),
+            (CsgOp::Difference, 0, 1),
+        ] {
+            let c = csg(op, s1.clone(), s2.clone());
+            let xs = vec![
+                (true, intersection(1., s1.clone())),
+                (false, intersection(2., s2.clone())),
+                (true, intersection(3., s1.clone())),
+                (false, intersection(4., s2.clone())),
+            ];
+            let ts: Vec<f64> = xs.iter().map(|(_, i)| i.t).collect();
+
+            let result = c.filter_intersections(xs);
+
+            assert_eq!(result.len(), 2);
+            assert_eq!(result[0].t, ts[x0]);
+            assert_eq!(result[1].t, ts[x1]);
+        }
+    }
+
+    #[test]
+    fn a_ray_misses_a_csg_object() {
+        let c: Arc<SyncShape> = Arc::new(csg(CsgOp::Union, Arc::new(sphere()), Arc::new(cube())));
+        let r = ray(point(0., 2., -5.), vector(0., 0., 1.));
+
+        let xs = c.local_intersects(c.clone(), r);
+
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn a_ray_hits_a_csg_object() {
+        let s1: Arc<SyncShape> = Arc::new(sphere());
+        let mut s2 = sphere();
+        s2.set_transform(translation(0., 0., 0.5));
+        let s2: Arc<SyncShape> = Arc::new(s2);
+        let c = Arc::new(csg(CsgOp::Union, s1.clone(), s2.clone()));
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+
+        let xs = c.local_intersects(c.clone(), r);
+
+        assert_eq!(xs.len(), 2);
+        assert_that!(xs[0].t, close_to(4., 1e-10));
+        assert_that!(xs[0].object.clone(), eq(wrap(&c.invtransform, s1)));
+        assert_that!(xs[1].t, close_to(6.5, 1e-10));
+        assert_that!(xs[1].object.clone(), eq(wrap(&c.invtransform, s2)));
+    }
+
+    #[test]
+    fn the_normal_of_a_difference_comes_from_the_carved_out_child() {
+        let mut s2 = sphere();
+        s2.set_transform(translation(0., 0., 0.5));
+        let mut c = csg(CsgOp::Difference, Arc::new(sphere()), Arc::new(s2));
+        c.set_transform(translation(1., 0., 0.));
+        let c: Arc<SyncShape> = Arc::new(c);
+        let r = ray(point(1., 0., -5.), vector(0., 0., 1.));
+
+        let xs = c.intersects(c.clone(), &r);
+
+        assert_eq!(xs.len(), 2);
+        assert_that!(xs[1].t, close_to(4.5, 1e-10));
+        let n = xs[1].object.normal_at_hit(&r.position(xs[1].t), &xs[1]);
+        assert_that!(n, eq(vector(0., 0., -1.)));
+    }
+
+    #[test]
+    fn a_csg_is_bounded_by_its_children() {
+        let mut s2 = cube();
+        s2.set_transform(translation(2., 0., 0.));
+        let c = csg(CsgOp::Difference, Arc::new(sphere()), Arc::new(s2));
+
+        assert_eq!(
+            c.local_bounds(),
+            bound(point(-1., -1., -1.), point(3., 1., 1.))
+        );
+    }
+
+    #[test]
+    fn a_material_is_passed_down_to_children_owned_by_the_csg() {
+        let shared: Arc<SyncShape> = Arc::new(cube());
+        let mut c = csg(CsgOp::Union, Arc::new(sphere()), shared.clone());
+        let mut m = material();
+        m.ambient = 1.;
+
+        c.set_material(m.clone());
+
+        assert_eq!(c.material(), &m);
+        assert_eq!(c.left.material(), &m);
+        assert_eq!(c.right.material(), &material());
+    }
+}
@@ -8,16 +8,20 @@ use crate::materials::Material;
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
 use crate::rays::Ray;
+use crate::shapes::shape_base;
 use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
 use crate::shapes::SyncShape;
 use crate::tuples::point;
 use crate::tuples::vector;
 use crate::tuples::Tuple;
 use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Cube {
-    pub invtransform: Matrix,
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
     pub material: Material,
     bounds: Bounds,
 }
@@ -54,14 +58,21 @@ impl Shape for Cube {
         &self.invtransform
     }
     fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
         self.invtransform = invtransform;
     }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
     fn local_normal_at(&self, point: Tuple) -> Tuple {
         let comps = [point.x.abs(), point.y.abs(), point.z.abs()];
-        let maxc = *comps
-            .iter()
-            .max_by(|a, b| a.partial_cmp(&b).unwrap())
-            .unwrap();
+        let maxc = comps[0].max(comps[1]).max(comps[2]);
 
         if maxc == comps[0] {
             vector(point.x, 0., 0.)
@@ -75,23 +86,16 @@ impl Shape for Cube {
         let (xtmin, xtmax) = check_axis(ray.origin.x, ray.direction.x);
         let (ytmin, ytmax) = check_axis(ray.origin.y, ray.direction.y);
         let (ztmin, ztmax) = check_axis(ray.origin.z, ray.direction.z);
-        let mins = [xtmin, ytmin, ztmin];
-        let maxs = [xtmax, ytmax, ztmax];
-        let tmin = mins
-            .iter()
-            .max_by(|a, b| a.partial_cmp(&b).unwrap())
-            .unwrap();
-        let tmax = maxs
-            .iter()
-            .min_by(|a, b| a.partial_cmp(&b).unwrap())
-            .unwrap();
+        // f64::max and f64::min skip the NaN of an axis the ray runs along the face of
+        let tmin = xtmin.max(ytmin).max(ztmin);
+        let tmax = xtmax.min(ytmax).min(ztmax);
 
         if tmin > tmax {
             vec![]
         } else {
             vec![
-                intersection(*tmin, rc.clone()),
-                intersection(*tmax, rc.clone()),
+                intersection(tmin, rc.clone()),
+                intersection(tmax, rc.clone()),
             ]
         }
     }
@@ -103,6 +107,8 @@ pub fn cube() -> Cube {
     Cube {
         material,
         invtransform,
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         bounds: bound(point(-1., -1., -1.), point(1., 1., 1.)),
     }
 }
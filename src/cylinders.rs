@@ -2,13 +2,16 @@ use crate::bounds::bound;
 use crate::bounds::Bounds;
 use crate::intersections::intersection;
 use crate::intersections::Intersection;
+use crate::intersections::Part;
 use crate::intersections::EPSILON;
 use crate::materials::material;
 use crate::materials::Material;
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
 use crate::rays::Ray;
+use crate::shapes::shape_base;
 use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
 use crate::shapes::SyncShape;
 use crate::tuples::point;
 use crate::tuples::vector;
@@ -17,19 +20,22 @@ use std::f64::INFINITY;
 use std::f64::NEG_INFINITY;
 use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Cylinder {
-    pub invtransform: Matrix,
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
     pub material: Material,
     pub minimum: f64,
     pub maximum: f64,
     pub closed: bool,
+    pub radius: f64,
 }
 
-fn check_cap(ray: &Ray, t: f64) -> bool {
+fn check_cap(ray: &Ray, t: f64, r: f64) -> bool {
     let x = ray.origin.x + t * ray.direction.x;
     let z = ray.origin.z + t * ray.direction.z;
-    x.powi(2) + z.powi(2) <= 1.
+    x.powi(2) + z.powi(2) <= r.powi(2)
 }
 
 impl Cylinder {
@@ -40,8 +46,11 @@ impl Cylinder {
             vec![self.minimum, self.maximum]
                 .into_iter()
                 .map(|m| (m - ray.origin.y) / ray.direction.y)
-                .filter(|t| check_cap(ray, *t))
-                .map(|t| intersection(t, rc.clone()))
+                .filter(|t| check_cap(ray, *t, self.radius))
+                .map(|t| Intersection {
+                    part: Part::Cap,
+                    ..intersection(t, rc.clone())
+                })
                 .collect()
         }
     }
@@ -55,7 +64,7 @@ impl Cylinder {
 
         let a = dx.powi(2) + dz.powi(2);
         let b = 2. * (ox * dx + oz * dz);
-        let c = ox.powi(2) + oz.powi(2) - 1.;
+        let c = ox.powi(2) + oz.powi(2) - self.radius.powi(2);
         let discriminant = b.powi(2) - 4. * a * c;
 
         if discriminant < 0. {
@@ -70,7 +79,10 @@ impl Cylinder {
                 let y = oy + t * dy;
                 self.minimum < y && y < self.maximum
             })
-            .map(|t| intersection(t, rc.clone()))
+            .map(|t| Intersection {
+                part: Part::Side,
+                ..intersection(t, rc.clone())
+            })
             .collect()
         }
     }
@@ -78,7 +90,8 @@ impl Cylinder {
 
 impl Shape for Cylinder {
     fn local_bounds(&self) -> Bounds {
-        bound(point(-1., self.minimum, -1.), point(1., self.maximum, 1.))
+        let r = self.radius;
+        bound(point(-r, self.minimum, -r), point(r, self.maximum, r))
     }
     fn material(&self) -> &Material {
         &self.material
@@ -90,13 +103,24 @@ impl Shape for Cylinder {
         &self.invtransform
     }
     fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
         self.invtransform = invtransform;
     }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
     fn local_normal_at(&self, point: Tuple) -> Tuple {
         let dist = point.x.powi(2) + point.z.powi(2);
-        if dist < 1. && point.y >= self.maximum - EPSILON {
+        let cap = self.radius.powi(2);
+        if dist < cap && point.y >= self.maximum - EPSILON {
             vector(0., 1., 0.)
-        } else if dist < 1. && point.y <= self.minimum + EPSILON {
+        } else if dist < cap && point.y <= self.minimum + EPSILON {
             vector(0., -1., 0.)
         } else {
             vector(point.x, 0., point.z)
@@ -114,9 +138,12 @@ pub fn cylinder() -> Cylinder {
     Cylinder {
         material,
         invtransform,
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         minimum: NEG_INFINITY,
         maximum: INFINITY,
         closed: false,
+        radius: 1.,
     }
 }
 #[cfg(test)]
@@ -206,6 +233,22 @@ mod spec {
         }
     }
     #[test]
+    fn intersections_record_which_part_of_a_closed_cylinder_was_hit() {
+        let mut cyl = cylinder();
+        cyl.minimum = 1.;
+        cyl.maximum = 2.;
+        cyl.closed = true;
+        let cyl = Arc::new(cyl);
+
+        let top = cyl.local_intersects(cyl.clone(), ray(point(0., 3., 0.), vector(0., -1., 0.)));
+        let side = cyl.local_intersects(cyl.clone(), ray(point(0., 1.5, -2.), vector(0., 0., 1.)));
+
+        assert_eq!(top.len(), 2);
+        assert!(top.iter().all(|i| i.part == Part::Cap));
+        assert_eq!(side.len(), 2);
+        assert!(side.iter().all(|i| i.part == Part::Side));
+    }
+    #[test]
     fn the_normal_vector_on_a_cylinders_end_caps() {
         let mut cyl = cylinder();
         cyl.minimum = 1.;
@@ -244,4 +287,26 @@ mod spec {
             bound(point(-1., -2., -1.), point(1., 4., 1.))
         );
     }
+
+    #[test]
+    fn intersecting_a_cylinder_with_radius() {
+        let mut cyl = cylinder();
+        cyl.radius = 2.;
+        cyl.minimum = 1.;
+        cyl.maximum = 2.;
+        cyl.closed = true;
+        let cyl = Arc::new(cyl);
+
+        let sides = cyl.local_intersects(cyl.clone(), ray(point(0., 1.5, -5.), vector(0., 0., 1.)));
+        let caps = cyl.local_intersects(cyl.clone(), ray(point(1.5, 3., 0.), vector(0., -1., 0.)));
+
+        assert_eq!(sides.iter().map(|i| i.t).collect::<Vec<f64>>(), [3., 7.]);
+        assert_eq!(caps.len(), 2);
+        assert_eq!(cyl.local_normal_at(point(2., 1.5, 0.)), vector(2., 0., 0.));
+        assert_eq!(cyl.local_normal_at(point(1.5, 2., 0.)), vector(0., 1., 0.));
+        assert_eq!(
+            cyl.local_bounds(),
+            bound(point(-2., 1., -2.), point(2., 2., 2.))
+        );
+    }
 }
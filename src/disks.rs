@@ -0,0 +1,158 @@
+use crate::bounds::bound;
+use crate::bounds::Bounds;
+use crate::intersections::intersection;
+use crate::intersections::Intersection;
+use crate::intersections::EPSILON;
+use crate::materials::material;
+use crate::materials::Material;
+use crate::matrices::identity_matrix;
+use crate::matrices::Matrix;
+use crate::rays::Ray;
+use crate::shapes::shape_base;
+use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
+use crate::shapes::SyncShape;
+use crate::tuples::point;
+use crate::tuples::vector;
+use crate::tuples::Tuple;
+use std::sync::Arc;
+
+// a flat ring in the xz plane around the origin with normal pointing in the positive y direction
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disk {
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
+    pub material: Material,
+    pub inner_radius: f64,
+    pub outer_radius: f64,
+}
+
+impl Shape for Disk {
+    fn local_bounds(&self) -> Bounds {
+        let r = self.outer_radius;
+        bound(point(-r, 0., -r), point(r, 0., r))
+    }
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
+        self.invtransform = invtransform;
+    }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
+    fn local_normal_at(&self, _local_point: Tuple) -> Tuple {
+        vector(0., 1., 0.)
+    }
+    fn local_intersects(&self, rc: Arc<SyncShape>, local_ray: Ray) -> Vec<Intersection> {
+        if local_ray.direction.y.abs() < EPSILON {
+            return vec![];
+        }
+        let t = -local_ray.origin.y / local_ray.direction.y;
+        let p = local_ray.position(t);
+        let distance = p.x.powi(2) + p.z.powi(2);
+        if self.inner_radius.powi(2) <= distance && distance <= self.outer_radius.powi(2) {
+            vec![intersection(t, rc.clone())]
+        } else {
+            vec![]
+        }
+    }
+}
+
+pub fn disk() -> Disk {
+    Disk {
+        material: material(),
+        invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
+        inner_radius: 0.,
+        outer_radius: 1.,
+    }
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use crate::rays::ray;
+
+    fn annulus() -> Arc<Disk> {
+        let mut d = disk();
+        d.inner_radius = 0.5;
+        d.outer_radius = 2.;
+        Arc::new(d)
+    }
+
+    #[test]
+    fn a_ray_hits_the_annulus() {
+        let d = annulus();
+        let r = ray(point(1., 1., 0.), vector(0., -1., 0.));
+
+        let xs = d.local_intersects(d.clone(), r);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1.);
+    }
+
+    #[test]
+    fn a_ray_passes_through_the_inner_hole() {
+        let d = annulus();
+        let r = ray(point(0.2, 1., 0.2), vector(0., -1., 0.));
+
+        let xs = d.local_intersects(d.clone(), r);
+
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn a_ray_passes_outside_the_outer_radius() {
+        let d = annulus();
+        let r = ray(point(1.5, 1., 1.5), vector(0., -1., 0.));
+
+        let xs = d.local_intersects(d.clone(), r);
+
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn a_ray_parallel_to_the_disk() {
+        let d = annulus();
+        let r = ray(point(1., 0., -5.), vector(0., 0., 1.));
+
+        let xs = d.local_intersects(d.clone(), r);
+
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn the_normal_of_a_disk_is_constant_everywhere() {
+        let d = disk();
+
+        assert_eq!(d.local_normal_at(point(0.5, 0., 0.)), vector(0., 1., 0.));
+        assert_eq!(d.local_normal_at(point(0., 0., -1.)), vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn a_bounds_of_a_disk() {
+        let d = annulus();
+
+        assert_eq!(
+            d.local_bounds(),
+            bound(point(-2., 0., -2.), point(2., 0., 2.))
+        );
+    }
+}
@@ -1,20 +1,24 @@
-use crate::bounds::bound_single;
+use crate::bounds::bound_empty;
 use crate::bounds::Bounds;
+use crate::intersections::compare_t;
 use crate::intersections::Intersection;
 use crate::materials::material;
 use crate::materials::Material;
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
 use crate::rays::Ray;
+use crate::shapes::shape_base;
 use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
 use crate::shapes::SyncShape;
-use crate::tuples::point;
 use crate::tuples::Tuple;
 use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Group {
-    pub invtransform: Matrix,
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
     pub children: Vec<Arc<SyncShape>>,
     bounds: Bounds,
     material: Material,
@@ -30,31 +34,44 @@ impl Group {
         c
     }
     pub fn add_child_rc(&mut self, c: Arc<SyncShape>) {
+        self.bounds = self.bounds.clone() + parent_space_bounds(&c);
         self.children.push(c);
-
-        let bounds: Vec<Bounds> = self
+    }
+    // moves the group transform into its children without changing how they look in the world,
+    // children shared outside of the group are wrapped instead of modified
+    pub fn bake_transform(&mut self) {
+        let invtransform = self.invtransform.clone();
+        self.set_invtransform(identity_matrix());
+        for child in self.children.iter_mut() {
+            let baked = child.invtransform() * &invtransform;
+            match Arc::get_mut(child) {
+                Some(c) => c.set_invtransform(baked),
+                None => *child = wrap(&invtransform, child.clone()),
+            }
+        }
+        self.update_bounds();
+    }
+    fn update_bounds(&mut self) {
+        self.bounds = self
             .children
             .iter()
-            .map(|c| c.local_bounds().transform(&c.invtransform().inverse()))
-            .collect();
-        //unsafe sum
-        let mut i = bounds.into_iter();
-        let first = i.next().unwrap();
-        self.bounds = i.fold(first, |acc, b| acc + b);
+            .map(parent_space_bounds)
+            .fold(bound_empty(), |acc, b| acc + b);
     }
     fn wrap(&self, child: Arc<SyncShape>) -> Arc<SyncShape> {
-        Arc::new(Group {
-            invtransform: self.invtransform.clone(),
-            children: vec![child.clone()],
-            bounds: child.local_bounds(),
-            material: material(),
-        })
+        wrap(&self.invtransform, child)
     }
 }
 impl Shape for Group {
     fn local_bounds(&self) -> Bounds {
         self.bounds.clone()
     }
+    fn primitive_count(&self) -> usize {
+        self.children.iter().map(|c| c.primitive_count()).sum()
+    }
+    fn has_bounding_hierarchy(&self) -> bool {
+        true
+    }
     fn material(&self) -> &Material {
         if self.children.is_empty() {
             &self.material
@@ -67,8 +84,18 @@ impl Shape for Group {
         &self.invtransform
     }
     fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
         self.invtransform = invtransform;
     }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
     fn world_to_object(&self, world_point: &Tuple) -> Tuple {
         self.children[0].world_to_object(&(self.invtransform() * world_point))
     }
@@ -83,7 +110,7 @@ impl Shape for Group {
                     i
                 })
                 .collect();
-            xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+            xs.sort_unstable_by(|a, b| compare_t(a.t, b.t));
             xs
         } else {
             vec![]
@@ -92,35 +119,56 @@ impl Shape for Group {
     fn local_normal_at(&self, local_point: Tuple) -> Tuple {
         self.children[0].local_normal_at(local_point)
     }
+    fn local_normal_at_hit(&self, local_point: Tuple, hit: &Intersection) -> Tuple {
+        self.children[0].local_normal_at_hit(local_point, hit)
+    }
+    fn uv_at(&self, hit: &Intersection) -> Option<(f64, f64)> {
+        self.children[0].uv_at(hit)
+    }
     fn normal_to_world(&self, local_normal: Tuple) -> Tuple {
-        let mut normal =
-            self.invtransform().transpose() * self.children[0].normal_to_world(local_normal);
+        let mut normal = self.normal_transform() * &self.children[0].normal_to_world(local_normal);
         normal.w = 0.;
         normal.normalized()
     }
 }
+pub(crate) fn parent_space_bounds(child: &Arc<SyncShape>) -> Bounds {
+    child
+        .local_bounds()
+        .transform(&child.invtransform().inverse())
+}
+// a one child group carrying the transform of a composite shape along with a hit on its child,
+// it keeps the id and name of the child so the hit still reports the primitive it was on
+pub fn wrap(invtransform: &Matrix, child: Arc<SyncShape>) -> Arc<SyncShape> {
+    Arc::new(Group {
+        invtransform: invtransform.clone(),
+        normal_transform: invtransform.transpose(),
+        base: child.base().clone(),
+        children: vec![child.clone()],
+        bounds: child.local_bounds(),
+        material: material(),
+    })
+}
 pub fn group() -> Group {
     Group {
         invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         children: vec![],
-        bounds: bound_single(point(0., 0., 0.)),
+        bounds: bound_empty(),
         material: material(),
     }
 }
 pub fn group_with_children(children: Vec<Arc<SyncShape>>) -> Group {
-    let bounds = if children.len() > 0 {
-        let mut i = children.iter().map(|c| c.local_bounds()).into_iter();
-        let first = i.next().unwrap();
-        i.fold(first, |acc, b| acc + b)
-    } else {
-        bound_single(point(0., 0., 0.))
-    };
-    Group {
+    let mut g = Group {
         invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         children,
-        bounds,
+        bounds: bound_empty(),
         material: material(),
-    }
+    };
+    g.update_bounds();
+    g
 }
 #[cfg(test)]
 mod spec {
@@ -170,9 +218,9 @@ mod spec {
     fn intersecting_a_ray_with_a_nonempty_group() {
         let s1 = sphere();
         let mut s2 = sphere();
-        s2.invtransform = translation(0., 0., -3.).inverse();
+        s2.set_transform(translation(0., 0., -3.));
         let mut s3 = sphere();
-        s3.invtransform = translation(5., 0., 0.).inverse();
+        s3.set_transform(translation(5., 0., 0.));
         let mut g = group();
         let s1 = g.add_child(s1);
         let s2 = g.add_child(s2);
@@ -192,9 +240,9 @@ mod spec {
     #[test]
     fn intersecting_a_transformed_group() {
         let mut s = sphere();
-        s.invtransform = translation(5., 0., 0.).inverse();
+        s.set_transform(translation(5., 0., 0.));
         let mut g = group();
-        g.invtransform = scaling(2., 2., 2.).inverse();
+        g.set_transform(scaling(2., 2., 2.));
         g.add_child(s);
         let g = Arc::new(g);
 
@@ -206,9 +254,9 @@ mod spec {
     #[test]
     fn a_bounds_of_a_group() {
         let mut s1 = sphere();
-        s1.invtransform = translation(1., 1., 1.).inverse();
+        s1.set_transform(translation(1., 1., 1.));
         let mut s2 = sphere();
-        s2.invtransform = translation(-1., -2., -3.).inverse();
+        s2.set_transform(translation(-1., -2., -3.));
         let mut g = group();
         g.add_child(s1);
         g.add_child(s2);
@@ -218,4 +266,89 @@ mod spec {
             bound(point(-2., -3., -4.), point(2., 2., 2.))
         );
     }
+
+    #[test]
+    fn an_empty_group_has_empty_bounds() {
+        let g = group();
+
+        assert!(g.local_bounds().is_empty());
+    }
+
+    #[test]
+    fn growing_bounds_child_by_child_matches_the_full_recomputation() {
+        let mut g = group();
+        for i in 0..1000 {
+            let mut s = sphere();
+            let f = i as f64;
+            s.set_transform(translation(f.sin() * 10., f.cos() * 5., f * 0.01));
+            g.add_child(s);
+        }
+        let incremental = g.local_bounds();
+
+        g.update_bounds();
+
+        assert_eq!(incremental, g.local_bounds());
+    }
+
+    #[test]
+    fn baking_a_transform_keeps_the_intersections() {
+        let rays = [
+            ray(point(-2., 1.5, -10.), vector(0., 0., 1.)),
+            ray(point(2., 1., -10.), vector(0., 0., 1.)),
+            ray(point(-10., 0.6, 1.), vector(1., 0., 0.)),
+        ];
+        let transformed = || {
+            let mut s1 = sphere();
+            s1.set_transform(translation(1., 0., 0.));
+            let mut s2 = sphere();
+            s2.set_transform(translation(-1., 1., 0.) * scaling(0.5, 0.5, 0.5));
+            let mut g = group();
+            g.set_transform(translation(0., 0.5, 1.) * scaling(2., 1., 1.));
+            g.add_child(s1);
+            g.add_child(s2);
+            g
+        };
+        let before: Arc<SyncShape> = Arc::new(transformed());
+        let mut baked = transformed();
+
+        baked.bake_transform();
+
+        assert_eq!(baked.invtransform, identity_matrix());
+        let after: Arc<SyncShape> = Arc::new(baked);
+        for r in &rays {
+            let xs1: Vec<f64> = before
+                .intersects(before.clone(), r)
+                .iter()
+                .map(|i| i.t)
+                .collect();
+            let xs2: Vec<f64> = after
+                .intersects(after.clone(), r)
+                .iter()
+                .map(|i| i.t)
+                .collect();
+            assert!(!xs1.is_empty());
+            assert_eq!(xs1.len(), xs2.len());
+            for (t1, t2) in xs1.iter().zip(&xs2) {
+                assert_that!(*t2, close_to(*t1, 1e-9));
+            }
+        }
+    }
+
+    #[test]
+    fn a_hit_on_a_child_reports_the_id_and_name_of_the_child() {
+        let mut s = sphere();
+        s.set_name("ball");
+        let mut g = group();
+        g.set_transform(translation(0., 0., 1.));
+        let child = g.add_child(s);
+        let g: Arc<SyncShape> = Arc::new(g);
+
+        let xs = g.intersects(g.clone(), &ray(point(0., 0., -5.), vector(0., 0., 1.)));
+
+        assert_eq!(xs.len(), 2);
+        for x in &xs {
+            assert_eq!(x.object.id(), child.id());
+            assert_eq!(x.object.name(), Some("ball"));
+        }
+    }
 }
@@ -0,0 +1,378 @@
+use crate::bounds::bound_empty;
+use crate::bounds::Bounds;
+use crate::intersections::Intersection;
+use crate::materials::Material;
+use crate::matrices::identity_matrix;
+use crate::matrices::Matrix;
+use crate::rays::Ray;
+use crate::shapes::shape_base;
+use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
+use crate::shapes::SyncShape;
+use crate::tuples::Tuple;
+use std::sync::Arc;
+
+// places shared geometry with its own transform and optional material, so a mesh can be
+// rendered many times without copying its triangles
+#[derive(Clone, Debug)]
+pub struct Instance {
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
+    geometry: Arc<SyncShape>,
+    pub material: Option<Material>,
+    // transforms at time 0 and 1, a ray cast in between sees the instance at the blend of the two
+    motion: Option<(Matrix, Matrix)>,
+    // the geometry is fixed once placed, so its bounds are only computed when the motion changes
+    bounds: Bounds,
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Instance) -> bool {
+        self.invtransform == other.invtransform
+            && self.material == other.material
+            && self.motion == other.motion
+            && self.geometry.eq(&other.geometry)
+    }
+}
+
+// poses of a moving instance between the two ends that its bounds are grown around
+const MOTION_SAMPLES: usize = 16;
+
+pub fn instance(geometry: Arc<SyncShape>) -> Instance {
+    let mut i = Instance {
+        invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
+        geometry,
+        material: None,
+        motion: None,
+        bounds: bound_empty(),
+    };
+    i.bounds = i.geometry_bounds();
+    i
+}
+
+// an instance that moves from the start to the end transform while the camera shutter is open
+pub fn moving_instance(geometry: Arc<SyncShape>, start: Matrix, end: Matrix) -> Instance {
+    let mut i = instance(geometry);
+    i.set_motion(start, end);
+    i
+}
+
+impl Instance {
+    // the instance rests at the start transform for rays that do not sample a time,
+    // a singular start is not a usable rest transform so the previous one is kept
+    pub fn set_motion(&mut self, start: Matrix, end: Matrix) {
+        self.set_transform(start.clone());
+        // a blend does not move in a straight line, e.g. when rotating, so the bounds are
+        // grown around poses sampled along the shutter, in the space of the rest transform
+        let rest = self.invtransform().clone();
+        let bounds = self.geometry_bounds();
+        self.bounds = (0..=MOTION_SAMPLES)
+            .map(|step| {
+                let pose = start.lerp_transform(&end, step as f64 / MOTION_SAMPLES as f64);
+                bounds.transform(&(&rest * &pose))
+            })
+            .fold(bound_empty(), |acc, b| acc + b);
+        self.motion = Some((start, end));
+    }
+
+    pub fn geometry(&self) -> &Arc<SyncShape> {
+        &self.geometry
+    }
+
+    // the bounds of the geometry at rest, in the space of the instance
+    fn geometry_bounds(&self) -> Bounds {
+        self.geometry
+            .local_bounds()
+            .transform(&self.geometry.invtransform().inverse())
+    }
+
+    // the hit is reported on a copy of the instance around the hit primitive, it carries the
+    // instance transform and material override the way groups wrap their children, and keeps
+    // the id and name of the primitive so the hit still reports what it was on
+    fn wrap(&self, hit: Arc<SyncShape>) -> Arc<SyncShape> {
+        self.wrap_with(
+            self.invtransform.clone(),
+            self.normal_transform.clone(),
+            hit,
+        )
+    }
+
+    fn wrap_with(
+        &self,
+        invtransform: Matrix,
+        normal_transform: Matrix,
+        hit: Arc<SyncShape>,
+    ) -> Arc<SyncShape> {
+        Arc::new(Instance {
+            invtransform,
+            normal_transform,
+            base: hit.base().clone(),
+            geometry: hit,
+            material: self.material.clone(),
+            motion: None,
+            // the hit primitive is somewhere inside the bounds of the whole geometry
+            bounds: self.bounds.clone(),
+        })
+    }
+}
+
+impl Shape for Instance {
+    fn local_bounds(&self) -> Bounds {
+        self.bounds.clone()
+    }
+    fn primitive_count(&self) -> usize {
+        self.geometry.primitive_count()
+    }
+    fn has_bounding_hierarchy(&self) -> bool {
+        self.geometry.has_bounding_hierarchy()
+    }
+    fn material(&self) -> &Material {
+        self.material
+            .as_ref()
+            .unwrap_or_else(|| self.geometry.material())
+    }
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
+        self.invtransform = invtransform;
+    }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
+    fn world_to_object(&self, world_point: &Tuple) -> Tuple {
+        self.geometry
+            .world_to_object(&(self.invtransform() * world_point))
+    }
+    fn intersects(&self, rc: Arc<SyncShape>, inray: &Ray) -> Vec<Intersection> {
+        let (start, end) = match &self.motion {
+            Some(motion) => motion,
+            None => return self.local_intersects(rc, inray.transform(self.invtransform())),
+        };
+        // the blend of two poses can be singular, e.g. half way through a mirroring
+        let invtransform = match start.lerp_transform(end, inray.time).try_inverse() {
+            Some(m) => m,
+            None => return vec![],
+        };
+        let normal_transform = invtransform.transpose();
+        self.geometry
+            .intersects(self.geometry.clone(), &inray.transform(&invtransform))
+            .into_iter()
+            .map(|mut i| {
+                i.object = self.wrap_with(invtransform.clone(), normal_transform.clone(), i.object);
+                i
+            })
+            .collect()
+    }
+    fn local_intersects(&self, _rc: Arc<SyncShape>, ray: Ray) -> Vec<Intersection> {
+        self.geometry
+            .intersects(self.geometry.clone(), &ray)
+            .into_iter()
+            .map(|mut i| {
+                i.object = self.wrap(i.object);
+                i
+            })
+            .collect()
+    }
+    fn local_normal_at(&self, local_point: Tuple) -> Tuple {
+        self.geometry.local_normal_at(local_point)
+    }
+    fn local_normal_at_hit(&self, local_point: Tuple, hit: &Intersection) -> Tuple {
+        self.geometry.local_normal_at_hit(local_point, hit)
+    }
+    fn uv_at(&self, hit: &Intersection) -> Option<(f64, f64)> {
+        self.geometry.uv_at(hit)
+    }
+    fn normal_to_world(&self, local_normal: Tuple) -> Tuple {
+        let mut normal = self.normal_transform() * &self.geometry.normal_to_world(local_normal);
+        normal.w = 0.;
+        normal.normalized()
+    }
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use crate::bounds::bound;
+    use crate::materials::material;
+    use crate::rays::ray;
+    use crate::spheres::sphere;
+    use crate::transformations::rotation_z;
+    use crate::transformations::scaling;
+    use crate::transformations::translation;
+    use crate::triangles::textured_triangle;
+    use crate::tuples::color;
+    use crate::tuples::point;
+    use crate::tuples::vector;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn instances_share_geometry_at_different_positions() {
+        let shared: Arc<SyncShape> = Arc::new(sphere());
+        let mut a = instance(shared.clone());
+        a.set_transform(translation(-3., 0., 0.));
+        let mut b = instance(shared.clone());
+        b.set_transform(translation(3., 0., 0.));
+        let (a, b) = (Arc::new(a), Arc::new(b));
+
+        let left = ray(point(-3., 0., -5.), vector(0., 0., 1.));
+        let right = ray(point(3., 0., -5.), vector(0., 0., 1.));
+        let between = ray(point(0., 0., -5.), vector(0., 0., 1.));
+
+        let xs = a.intersects(a.clone(), &left);
+        assert_eq!(xs.iter().map(|i| i.t).collect::<Vec<_>>(), vec![4., 6.]);
+        let xs = b.intersects(b.clone(), &right);
+        assert_eq!(xs.iter().map(|i| i.t).collect::<Vec<_>>(), vec![4., 6.]);
+        assert!(a.intersects(a.clone(), &between).is_empty());
+        assert!(b.intersects(b.clone(), &between).is_empty());
+        assert!(Arc::ptr_eq(&a.geometry, &b.geometry));
+    }
+
+    #[test]
+    fn the_normal_of_an_instance_uses_its_transform() {
+        let mut i = instance(Arc::new(sphere()));
+        i.set_transform(translation(0., 1., 0.));
+
+        let n = i.normal_at(&point(0., 2., 0.));
+
+        assert_eq!(n, vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn an_instance_overrides_the_material_of_its_hits() {
+        let mut m = material();
+        m.color = color(1., 0., 0.);
+        let mut i = instance(Arc::new(sphere()));
+        i.material = Some(m.clone());
+        let i = Arc::new(i);
+
+        let xs = i.intersects(i.clone(), &ray(point(0., 0., -5.), vector(0., 0., 1.)));
+
+        assert_eq!(xs[0].object.material(), &m);
+    }
+
+    #[test]
+    fn a_moving_instance_is_placed_by_the_time_of_the_ray() {
+        let i = Arc::new(moving_instance(
+            Arc::new(sphere()),
+            translation(0., 0., 0.),
+            translation(4., 0., 0.),
+        ));
+        let r = |x, time| ray(point(x, 0., -5.), vector(0., 0., 1.)).at_time(time);
+
+        assert_eq!(i.intersects(i.clone(), &r(0., 0.)).len(), 2);
+        assert!(i.intersects(i.clone(), &r(0., 1.)).is_empty());
+        assert_eq!(i.intersects(i.clone(), &r(4., 1.)).len(), 2);
+        let xs = i.intersects(i.clone(), &r(2., 0.5));
+        assert_eq!(xs.iter().map(|x| x.t).collect::<Vec<_>>(), vec![4., 6.]);
+        assert_eq!(
+            xs[0].object.normal_at_hit(&point(2., 0., -1.), &xs[0]),
+            vector(0., 0., -1.)
+        );
+    }
+
+    #[test]
+    fn a_moving_instance_is_missed_where_its_blended_pose_is_flat() {
+        let i = Arc::new(moving_instance(
+            Arc::new(sphere()),
+            scaling(1., 1., 1.),
+            scaling(-1., 1., 1.),
+        ));
+        let r = |time| ray(point(0., 0., -5.), vector(0., 0., 1.)).at_time(time);
+
+        assert!(i.intersects(i.clone(), &r(0.5)).is_empty());
+        assert_eq!(i.intersects(i.clone(), &r(0.)).len(), 2);
+    }
+
+    #[test]
+    fn an_instance_passes_on_the_texture_coordinates_of_its_geometry() {
+        let t = textured_triangle(
+            point(0., 1., 0.),
+            point(-1., 0., 0.),
+            point(1., 0., 0.),
+            (0.5, 1.),
+            (0., 0.),
+            (1., 0.),
+        );
+        let mut i = instance(Arc::new(t));
+        i.set_transform(translation(0., 0., 2.));
+        let i = Arc::new(i);
+
+        let xs = i.intersects(i.clone(), &ray(point(0., 0.5, -5.), vector(0., 0., 1.)));
+
+        assert_eq!(xs[0].object.uv_at(&xs[0]), Some((0.5, 0.5)));
+    }
+
+    #[test]
+    fn the_bounds_of_a_moving_instance_cover_its_path() {
+        let i = moving_instance(
+            Arc::new(sphere()),
+            translation(0., 0., 0.),
+            translation(4., 0., 0.),
+        );
+
+        let b = i.local_bounds();
+
+        assert_eq!(b, bound(point(-1., -1., -1.), point(5., 1., 1.)));
+    }
+
+    #[test]
+    fn the_bounds_of_a_turning_instance_cover_the_poses_between_its_ends() {
+        let mut s = sphere();
+        s.set_transform(translation(2., 0., 0.));
+        let i = moving_instance(Arc::new(s), identity_matrix(), rotation_z(5. * PI / 6.));
+        // half way through the turn the sphere passes above both of its end positions
+        let over = ray(point(0., 2.9, -5.), vector(0., 0., 1.));
+
+        assert!(i.local_bounds().intersects(&over));
+    }
+
+    #[test]
+    fn a_moving_instance_can_start_from_a_flat_pose() {
+        let i = Arc::new(moving_instance(
+            Arc::new(sphere()),
+            scaling(0., 1., 1.),
+            identity_matrix(),
+        ));
+        let r = |time| ray(point(0., 0., -5.), vector(0., 0., 1.)).at_time(time);
+
+        assert_eq!(
+            i.local_bounds(),
+            bound(point(-1., -1., -1.), point(1., 1., 1.))
+        );
+        assert!(i.intersects(i.clone(), &r(0.)).is_empty());
+        assert_eq!(i.intersects(i.clone(), &r(1.)).len(), 2);
+    }
+
+    #[test]
+    fn a_hit_on_an_instance_reports_the_id_and_name_of_the_primitive() {
+        let mut s = sphere();
+        s.set_name("ball");
+        let s: Arc<SyncShape> = Arc::new(s);
+        let mut i = instance(s.clone());
+        i.set_name("copy");
+        i.set_transform(translation(0., 0., 1.));
+        let i = Arc::new(i);
+
+        let xs = i.intersects(i.clone(), &ray(point(0., 0., -5.), vector(0., 0., 1.)));
+
+        assert_eq!(xs.len(), 2);
+        for x in &xs {
+            assert_eq!(x.object.id(), s.id());
+            assert_eq!(x.object.name(), Some("ball"));
+        }
+    }
+}
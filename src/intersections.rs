@@ -1,14 +1,32 @@
 use crate::rays::Ray;
 use crate::shapes::SyncShape;
 use crate::tuples::Tuple;
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::sync::Arc;
 
 pub const EPSILON: f64 = 1e-10;
 
+thread_local! {
+    // reused by every hit prepared on the thread instead of allocated each time
+    static CONTAINERS: RefCell<Vec<Arc<SyncShape>>> = const { RefCell::new(Vec::new()) };
+}
+
+// which piece of a shape was hit, shapes made of a single surface always report Surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Part {
+    Surface,
+    Side,
+    Cap,
+}
+
 #[derive(Debug)]
 pub struct Intersection {
     pub t: f64,
     pub object: Arc<SyncShape>,
+    pub u: f64,
+    pub v: f64,
+    pub part: Part,
 }
 
 impl PartialEq<Intersection> for Intersection {
@@ -18,17 +36,34 @@ impl PartialEq<Intersection> for Intersection {
 }
 
 pub fn intersection(t: f64, object: Arc<SyncShape>) -> Intersection {
-    Intersection { t, object }
+    intersection_with_uv(t, object, 0., 0.)
+}
+
+pub fn intersection_with_uv(t: f64, object: Arc<SyncShape>, u: f64, v: f64) -> Intersection {
+    Intersection {
+        t,
+        object,
+        u,
+        v,
+        part: Part::Surface,
+    }
 }
 
 pub fn intersections(a: Intersection, b: Intersection) -> Vec<Intersection> {
     vec![a, b]
 }
 
-pub fn hit<'a>(xs: &'a Vec<Intersection>) -> Option<&'a Intersection> {
+// orders distances with NaN after every number, a degenerate intersection sorts last instead of
+// panicking and never becomes the closest one
+pub fn compare_t(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+pub fn hit(xs: &[Intersection]) -> Option<&Intersection> {
     xs.iter()
         .filter(|x| x.t >= 0.)
-        .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap())
+        .min_by(|a, b| compare_t(a.t, b.t))
 }
 
 pub struct Comps {
@@ -37,12 +72,17 @@ pub struct Comps {
     pub normalv: Tuple,
     pub object: Arc<SyncShape>,
     pub over_point: Tuple,
+    pub part: Part,
     pub point: Tuple,
     pub under_point: Tuple,
     pub reflectv: Tuple,
     pub t: f64,
     pub n1: f64,
     pub n2: f64,
+    // the time of the ray, secondary rays are cast at the same moment
+    pub time: f64,
+    // texture coordinates of the surface, when the shape has them
+    pub uv: Option<(f64, f64)>,
 }
 
 impl Comps {
@@ -80,34 +120,23 @@ impl Comps {
 }
 
 impl Intersection {
+    // a ray enters a shape when it travels against the outward facing normal
+    // decided by the geometric normal, interpolated normals of smooth triangles can face either way
+    pub fn is_entering(&self, r: &Ray) -> bool {
+        let point = r.position(self.t);
+        self.object.normal_at(&point).dot(&r.direction) < 0.
+    }
+
     pub fn prepare_computations(self: &Self, r: &Ray, xs: &[Intersection]) -> Comps {
-        let mut n1 = 0.;
-        let mut n2 = 0.;
-        let mut containers: Vec<Arc<SyncShape>> = vec![];
-        for x in xs {
-            if self.eq(x) {
-                n1 = containers
-                    .last()
-                    .map_or(1., |o| o.material().refractive_index);
-            }
-            if containers.contains(&x.object) {
-                containers = containers
-                    .into_iter()
-                    .filter(|o| !o.eq(&x.object))
-                    .collect();
-            } else {
-                containers.push(x.object.clone());
-            }
-            if self.eq(x) {
-                n2 = containers
-                    .last()
-                    .map_or(1., |o| o.material().refractive_index);
-                break;
-            }
-        }
+        let (n1, n2) = CONTAINERS.with(|containers| {
+            let mut containers = containers.borrow_mut();
+            let refraction = refractive_indices(self, r, xs, &mut containers);
+            containers.clear();
+            refraction
+        });
 
         let point = r.position(self.t);
-        let normalv = self.object.normal_at(&point);
+        let normalv = self.object.normal_at_hit(&point, self);
         let eyev = -(&r.direction);
         let inside = normalv.dot(&eyev) < 0.;
         let normalv = if inside { -normalv } else { normalv };
@@ -121,25 +150,77 @@ impl Intersection {
             normalv,
             object: self.object.clone(),
             over_point,
+            part: self.part,
             point,
             under_point,
             reflectv,
             t: self.t,
             n1,
             n2,
+            time: r.time,
+            uv: self.object.uv_at(self),
+        }
+    }
+}
+
+// refractive indices on both sides of the hit, containers tracks the objects the ray is inside of
+fn refractive_indices(
+    hit: &Intersection,
+    r: &Ray,
+    xs: &[Intersection],
+    containers: &mut Vec<Arc<SyncShape>>,
+) -> (f64, f64) {
+    let mut n1 = 0.;
+    for x in xs {
+        if hit.eq(x) {
+            n1 = containers
+                .last()
+                .map_or(1., |o| o.material().refractive_index);
+        }
+        // the direction of every crossing decides between entering and leaving a medium, media
+        // are told apart by id as hits inside groups come wrapped in a new shape every time. A
+        // ray leaves a closed mesh through another face than it entered, so it leaves the latest
+        // medium of the same material when it has not entered that exact object. Open shapes
+        // like planes face one way only, leaving one that was never entered enters it instead
+        if x.is_entering(r) {
+            containers.push(x.object.clone());
+        } else {
+            match containers
+                .iter()
+                .rposition(|o| o.id() == x.object.id())
+                .or_else(|| {
+                    containers
+                        .iter()
+                        .rposition(|o| o.material() == x.object.material())
+                }) {
+                Some(i) => {
+                    containers.remove(i);
+                }
+                None => containers.push(x.object.clone()),
+            }
+        }
+        if hit.eq(x) {
+            let n2 = containers
+                .last()
+                .map_or(1., |o| o.material().refractive_index);
+            return (n1, n2);
         }
     }
+    (n1, 0.)
 }
 
 #[cfg(test)]
 mod spec {
     use super::*;
+    use crate::groups::group;
     use crate::planes::plane;
     use crate::rays::ray;
+    use crate::shapes::Shape;
     use crate::spheres::glass_sphere;
     use crate::spheres::sphere;
     use crate::transformations::scaling;
     use crate::transformations::translation;
+    use crate::triangles::triangle;
     use crate::tuples::point;
     use crate::tuples::vector;
     use hamcrest2::prelude::*;
@@ -152,6 +233,16 @@ mod spec {
         assert_eq!(*i.object, *s);
     }
 
+    #[test]
+    fn an_intersection_can_encapsulate_u_and_v() {
+        let s: Arc<SyncShape> = Arc::new(sphere());
+
+        let i = intersection_with_uv(3.5, s, 0.2, 0.4);
+
+        assert_eq!(i.u, 0.2);
+        assert_eq!(i.v, 0.4);
+    }
+
     #[test]
     fn aggreagating_intersections() {
         let s = Arc::new(sphere());
@@ -207,6 +298,19 @@ mod spec {
         assert_eq!(hit(&xs).unwrap(), &intersection(2., s));
     }
 
+    #[test]
+    fn the_hit_of_a_slice() {
+        let s = Arc::new(sphere());
+        let xs = [
+            intersection(2., s.clone()),
+            intersection(-1., s.clone()),
+            intersection(5., s.clone()),
+        ];
+
+        assert_eq!(hit(&xs[1..]).unwrap(), &intersection(5., s.clone()));
+        assert_eq!(hit(&xs[..2]).unwrap(), &intersection(2., s));
+    }
+
     #[test]
     fn precomputes_the_state_of_an_intersection() {
         let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
@@ -251,7 +355,7 @@ mod spec {
     fn the_hit_should_offset_the_point() {
         let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
         let mut shape = sphere();
-        shape.invtransform = translation(0., 0., 1.).inverse();
+        shape.set_transform(translation(0., 0., 1.));
         let i = intersection(5., Arc::new(shape));
 
         let comps = i.prepare_computations(&r, &[]);
@@ -275,15 +379,15 @@ mod spec {
     #[test]
     fn finding_n1_and_n2_at_various_intersections() {
         let mut a = glass_sphere();
-        a.invtransform = scaling(2., 2., 2.).inverse();
+        a.set_transform(scaling(2., 2., 2.));
         a.material.refractive_index = 1.5;
         let a = Arc::new(a);
         let mut b = glass_sphere();
-        b.invtransform = translation(0., 0., -0.25).inverse();
+        b.set_transform(translation(0., 0., -0.25));
         b.material.refractive_index = 2.0;
         let b = Arc::new(b);
         let mut c = glass_sphere();
-        c.invtransform = translation(0., 0., 0.25).inverse();
+        c.set_transform(translation(0., 0., 0.25));
         c.material.refractive_index = 2.5;
         let c = Arc::new(c);
         let r = ray(point(0., 0., -4.), vector(0., 0., 1.));
@@ -316,7 +420,7 @@ mod spec {
     fn the_under_point_is_offset_below_the_surface() {
         let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
         let mut shape = glass_sphere();
-        shape.invtransform = translation(0., 0., 1.).inverse();
+        shape.set_transform(translation(0., 0., 1.));
         let shape = Arc::new(shape);
         let i = intersection(5., shape.clone());
         let xs = vec![i];
@@ -369,4 +473,129 @@ mod spec {
 
         assert_that!(reflectance, close_to(0.48873, 1e-5));
     }
+
+    #[test]
+    fn a_ray_enters_and_exits_a_closed_mesh_once() {
+        let corners = [
+            point(1., 0., 0.),
+            point(-1., 0., 0.),
+            point(0., 1., 0.),
+            point(0., -1., 0.),
+            point(0., 0., 1.),
+            point(0., 0., -1.),
+        ];
+        let mut mesh = group();
+        for &(a, b, c) in &[
+            (0, 2, 4),
+            (0, 2, 5),
+            (0, 3, 4),
+            (0, 3, 5),
+            (1, 2, 4),
+            (1, 2, 5),
+            (1, 3, 4),
+            (1, 3, 5),
+        ] {
+            let (a, b, c) = (&corners[a], &corners[b], &corners[c]);
+            // wind every face of the octahedron so that its normal points outwards
+            let mut face = triangle(a.clone(), b.clone(), c.clone());
+            if face
+                .local_normal_at(a.clone())
+                .dot(&(a + b.clone() + c.clone()))
+                < 0.
+            {
+                face = triangle(a.clone(), c.clone(), b.clone());
+            }
+            face.material.transparency = 1.;
+            face.material.refractive_index = 1.5;
+            mesh.add_child(face);
+        }
+        let mesh: Arc<SyncShape> = Arc::new(mesh);
+        let r = ray(point(0.1, 0.2, -5.), vector(0., 0., 1.));
+
+        let xs = mesh.intersects(mesh.clone(), &r);
+        let comps: Vec<Comps> = xs.iter().map(|i| i.prepare_computations(&r, &xs)).collect();
+
+        assert_eq!(xs.len(), 2);
+        assert!(xs[0].is_entering(&r));
+        assert!(!xs[1].is_entering(&r));
+        assert_eq!((comps[0].n1, comps[0].n2), (1.0, 1.5));
+        assert_eq!((comps[1].n1, comps[1].n2), (1.5, 1.0));
+    }
+
+    #[test]
+    fn overlapping_spheres_of_the_same_material_are_left_separately() {
+        let a: Arc<SyncShape> = Arc::new(glass_sphere());
+        let mut b = glass_sphere();
+        b.set_transform(translation(0., 0., 1.));
+        let b: Arc<SyncShape> = Arc::new(b);
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+        let xs = vec![
+            intersection(4., a.clone()),
+            intersection(5., b.clone()),
+            intersection(6., a.clone()),
+            intersection(7., b.clone()),
+        ];
+        let mut containers = vec![];
+
+        let refraction = refractive_indices(&xs[2], &r, &xs, &mut containers);
+
+        assert_eq!(refraction, (1.5, 1.5));
+        assert_eq!(containers.len(), 1);
+        assert!(Arc::ptr_eq(&containers[0], &b));
+    }
+
+    #[test]
+    fn overlapping_spheres_in_a_group_are_left_separately() {
+        let mut g = group();
+        let a = g.add_child(glass_sphere());
+        let mut b = glass_sphere();
+        b.set_transform(translation(0., 0., 1.));
+        let b = g.add_child(b);
+        let g: Arc<SyncShape> = Arc::new(g);
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+        let xs = g.intersects(g.clone(), &r);
+        let mut containers = vec![];
+
+        refractive_indices(&xs[2], &r, &xs, &mut containers);
+
+        assert_eq!(xs[2].object.id(), a.id());
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].id(), b.id());
+    }
+
+    #[test]
+    fn a_ray_going_up_through_a_glass_plane_refracts() {
+        let mut p = plane();
+        p.material.transparency = 1.;
+        p.material.refractive_index = 1.5;
+        let p: Arc<SyncShape> = Arc::new(p);
+        let r = ray(point(0., -1., 0.), vector(0., 1., 0.));
+        let xs = p.intersects(p.clone(), &r);
+
+        let comps = xs[0].prepare_computations(&r, &xs);
+
+        assert_eq!((comps.n1, comps.n2), (1., 1.5));
+    }
+
+    #[test]
+    fn the_hit_skips_nan_distances() {
+        let s: Arc<SyncShape> = Arc::new(sphere());
+        let xs = vec![
+            intersection(f64::NAN, s.clone()),
+            intersection(2., s.clone()),
+            intersection(1., s.clone()),
+        ];
+
+        assert_eq!(hit(&xs).map(|i| i.t), Some(1.));
+    }
+
+    #[test]
+    fn nan_distances_sort_last() {
+        let mut ts = [3., f64::NAN, -1., 2.];
+
+        ts.sort_unstable_by(|a, b| compare_t(*a, *b));
+
+        assert_eq!(ts[..3], [-1., 2., 3.]);
+        assert!(ts[3].is_nan());
+    }
 }
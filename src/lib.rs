@@ -0,0 +1,38 @@
+pub mod ascii;
+pub mod background;
+pub mod bounds;
+pub mod camera;
+pub mod canvas;
+pub mod cones;
+pub mod csg;
+pub mod cubes;
+pub mod cylinders;
+pub mod disks;
+pub mod groups;
+pub mod instances;
+pub mod intersections;
+pub mod lights;
+pub mod materials;
+pub mod matrices;
+pub mod noise;
+pub mod obj_file;
+pub mod patterns;
+pub mod planes;
+#[cfg(feature = "png")]
+pub mod png;
+pub mod ppm;
+pub mod rays;
+#[cfg(feature = "scene")]
+pub mod scene;
+pub mod shapes;
+pub mod spheres;
+pub mod torus;
+pub mod transformations;
+pub mod triangles;
+pub mod tuples;
+pub mod uv;
+pub mod world;
+
+#[cfg(test)]
+#[macro_use]
+extern crate hamcrest2;
@@ -1,15 +1,274 @@
-use crate::tuples::{Color, Tuple};
+use crate::tuples::{vector, Color, Tuple};
+use crate::world::World;
+use std::f64::consts::PI;
 
 #[derive(Clone, Debug, PartialEq)]
+pub enum Light {
+    Point(PointLight),
+    Area(AreaLight),
+    Directional(DirectionalLight),
+    Spot(SpotLight),
+}
+
+// a light with a radius is a sphere sampled at several points, which softens shadow edges
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PointLight {
     pub intensity: Color,
+    // fill lights can leave out highlights
+    pub casts_specular: bool,
     pub position: Tuple,
+    pub radius: f64,
+    pub samples: usize,
 }
 
 pub fn point_light(position: Tuple, intensity: Color) -> PointLight {
     PointLight {
         intensity,
+        casts_specular: true,
+        position,
+        radius: 0.,
+        samples: 1,
+    }
+}
+
+impl PointLight {
+    // positions to cast shadow rays to, spread through the light sphere along a golden angle
+    // spiral so that renders stay deterministic
+    pub fn sample_points(&self) -> Vec<Tuple> {
+        if self.radius <= 0. || self.samples <= 1 {
+            return vec![self.position.clone()];
+        }
+        let golden_angle = PI * (3. - 5f64.sqrt());
+        let n = self.samples as f64;
+        (0..self.samples)
+            .map(|i| {
+                let f = (i as f64 + 0.5) / n;
+                let y = 1. - 2. * f;
+                let ring = (1. - y * y).sqrt();
+                let theta = golden_angle * i as f64;
+                let offset = vector(ring * theta.cos(), y, ring * theta.sin());
+                &self.position + offset * (self.radius * f.cbrt())
+            })
+            .collect()
+    }
+}
+
+// a rectangle of usteps by vsteps cells, shadows are tested against a point in every cell
+#[derive(Clone, Debug, PartialEq)]
+pub struct AreaLight {
+    pub corner: Tuple,
+    pub uvec: Tuple,
+    pub usteps: usize,
+    pub vvec: Tuple,
+    pub vsteps: usize,
+    pub intensity: Color,
+    pub casts_specular: bool,
+    pub position: Tuple,
+    // offsets within a cell in [0, 1), cycled through for u and v; empty uses cell centers
+    pub jitter: Vec<f64>,
+}
+
+pub fn area_light(
+    corner: Tuple,
+    full_uvec: Tuple,
+    usteps: usize,
+    full_vvec: Tuple,
+    vsteps: usize,
+    intensity: Color,
+) -> AreaLight {
+    let position = &corner + (&full_uvec + full_vvec.clone()) / 2.;
+    AreaLight {
+        uvec: full_uvec / usteps as f64,
+        usteps,
+        vvec: full_vvec / vsteps as f64,
+        vsteps,
+        corner,
+        intensity,
+        casts_specular: true,
+        position,
+        jitter: vec![],
+    }
+}
+
+impl AreaLight {
+    pub fn point_on_light(&self, u: usize, v: usize) -> Tuple {
+        let cell = u + v * self.usteps;
+        let offset = |i: usize| {
+            if self.jitter.is_empty() {
+                0.5
+            } else {
+                self.jitter[i % self.jitter.len()]
+            }
+        };
+        &self.corner
+            + &self.uvec * (u as f64 + offset(2 * cell))
+            + &self.vvec * (v as f64 + offset(2 * cell + 1))
+    }
+
+    pub fn sample_points(&self) -> Vec<Tuple> {
+        (0..self.vsteps)
+            .flat_map(|v| (0..self.usteps).map(move |u| (u, v)))
+            .map(|(u, v)| self.point_on_light(u, v))
+            .collect()
+    }
+}
+
+// parallel rays coming from infinitely far away like sunlight, direction is where light travels
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectionalLight {
+    pub direction: Tuple,
+    pub intensity: Color,
+    pub casts_specular: bool,
+}
+
+pub fn directional_light(direction: Tuple, intensity: Color) -> DirectionalLight {
+    DirectionalLight {
+        direction: direction.normalized(),
+        intensity,
+        casts_specular: true,
+    }
+}
+
+// a point light shining into a cone around its direction, angles are measured from the axis
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpotLight {
+    pub position: Tuple,
+    pub direction: Tuple,
+    pub intensity: Color,
+    pub casts_specular: bool,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+}
+
+pub fn spot_light(
+    position: Tuple,
+    direction: Tuple,
+    intensity: Color,
+    inner_angle: f64,
+    outer_angle: f64,
+) -> SpotLight {
+    SpotLight {
         position,
+        direction: direction.normalized(),
+        intensity,
+        casts_specular: true,
+        inner_angle,
+        outer_angle,
+    }
+}
+
+impl SpotLight {
+    // 1 inside the inner cone, 0 outside the outer cone and a smooth step in between
+    pub fn falloff_at(&self, point: &Tuple) -> f64 {
+        let cos_angle = (point - &self.position).normalized().dot(&self.direction);
+        let (cos_inner, cos_outer) = (self.inner_angle.cos(), self.outer_angle.cos());
+        if cos_angle >= cos_inner {
+            1.
+        } else if cos_angle <= cos_outer {
+            0.
+        } else {
+            let f = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+            f * f * (3. - 2. * f)
+        }
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Light {
+        Light::Point(light)
+    }
+}
+
+impl From<AreaLight> for Light {
+    fn from(light: AreaLight) -> Light {
+        Light::Area(light)
+    }
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Light {
+        Light::Directional(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Light {
+        Light::Spot(light)
+    }
+}
+
+impl Light {
+    pub fn intensity(&self) -> &Color {
+        match self {
+            Light::Point(l) => &l.intensity,
+            Light::Area(l) => &l.intensity,
+            Light::Directional(l) => &l.intensity,
+            Light::Spot(l) => &l.intensity,
+        }
+    }
+
+    pub fn casts_specular(&self) -> bool {
+        match self {
+            Light::Point(l) => l.casts_specular,
+            Light::Area(l) => l.casts_specular,
+            Light::Directional(l) => l.casts_specular,
+            Light::Spot(l) => l.casts_specular,
+        }
+    }
+
+    // share of the intensity reaching the point ignoring shadows
+    pub fn falloff_at(&self, point: &Tuple) -> f64 {
+        match self {
+            Light::Spot(l) => l.falloff_at(point),
+            _ => 1.,
+        }
+    }
+
+    // unit vector from the point towards the light
+    pub fn direction_from(&self, point: &Tuple) -> Tuple {
+        match self {
+            Light::Point(l) => (&l.position - point).normalized(),
+            Light::Area(l) => (&l.position - point).normalized(),
+            Light::Directional(l) => -&l.direction,
+            Light::Spot(l) => (&l.position - point).normalized(),
+        }
+    }
+
+    // direction and max_t of a shadow ray from the point towards every sample of the light,
+    // the direction spans the whole way to the sample so hits before the light have t < 1 and
+    // no sqrt is needed, callers that want a unit direction normalize it themselves
+    pub fn shadow_rays(&self, point: &Tuple) -> Vec<(Tuple, f64)> {
+        let samples = match self {
+            Light::Point(l) => l.sample_points(),
+            Light::Area(l) => l.sample_points(),
+            Light::Directional(l) => return vec![(-&l.direction, f64::INFINITY)],
+            Light::Spot(l) => vec![l.position.clone()],
+        };
+        samples
+            .into_iter()
+            .map(|position| (&position - point, 1.))
+            .collect()
+    }
+
+    // fraction of the light samples visible from the point, 0 is a full shadow
+    pub fn intensity_at(&self, point: &Tuple, world: &World) -> f64 {
+        let rays = self.shadow_rays(point);
+        let visible = rays
+            .iter()
+            .filter(|(direction, max_t)| !world.is_blocked(point, direction, *max_t))
+            .count();
+        visible as f64 / rays.len() as f64
+    }
+
+    // like intensity_at, but transparent occluders tint the light when the world allows it
+    pub fn attenuation_at(&self, point: &Tuple, time: f64, world: &World) -> Color {
+        let rays = self.shadow_rays(point);
+        let n = rays.len() as f64;
+        rays.iter()
+            .map(|(direction, max_t)| world.transmittance(point, direction, *max_t, time))
+            .fold(Color::BLACK, |acc, c| acc + c)
+            / n
     }
 }
 
@@ -17,6 +276,8 @@ pub fn point_light(position: Tuple, intensity: Color) -> PointLight {
 mod spec {
     use super::*;
     use crate::tuples::{color, point};
+    use crate::world::spec::default_world;
+    use hamcrest2::prelude::*;
 
     #[test]
     fn a_point_light_has_a_position_and_intensity() {
@@ -25,5 +286,191 @@ mod spec {
         let light = point_light(position.clone(), intensity.clone());
         assert_eq!(light.position, position);
         assert_eq!(light.intensity, intensity);
+        assert_eq!(light.radius, 0.);
+    }
+
+    #[test]
+    fn a_point_light_without_radius_has_a_single_sample() {
+        let mut light = point_light(point(1., 2., 3.), color(1., 1., 1.));
+        light.samples = 16;
+
+        assert_eq!(light.sample_points(), vec![point(1., 2., 3.)]);
+    }
+
+    #[test]
+    fn samples_of_a_light_with_radius_stay_within_its_sphere() {
+        let mut light = point_light(point(1., 2., 3.), color(1., 1., 1.));
+        light.radius = 0.5;
+        light.samples = 16;
+
+        let samples = light.sample_points();
+
+        assert_eq!(samples.len(), 16);
+        for s in samples {
+            assert!((&s - &light.position).magnitude_squared() <= 0.25 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn point_lights_evaluate_the_light_intensity_at_a_given_point() {
+        let w = default_world();
+        let light = &w.light_sources[0];
+        for (p, result) in &[
+            (point(0., 1.0001, 0.), 1.),
+            (point(-1.0001, 0., 0.), 1.),
+            (point(0., 0., -1.0001), 1.),
+            (point(0., 0., 1.0001), 0.),
+            (point(1.0001, 0., 0.), 0.),
+            (point(0., -1.0001, 0.), 0.),
+            (point(0., 0., 0.), 0.),
+        ] {
+            assert_eq!(light.intensity_at(p, &w), *result);
+        }
+    }
+
+    #[test]
+    fn creating_an_area_light() {
+        let corner = point(0., 0., 0.);
+        let v1 = vector(2., 0., 0.);
+        let v2 = vector(0., 0., 1.);
+
+        let light = area_light(corner.clone(), v1, 4, v2, 2, color(1., 1., 1.));
+
+        assert_eq!(light.corner, corner);
+        assert_eq!(light.uvec, vector(0.5, 0., 0.));
+        assert_eq!(light.usteps, 4);
+        assert_eq!(light.vvec, vector(0., 0., 0.5));
+        assert_eq!(light.vsteps, 2);
+        assert_eq!(light.sample_points().len(), 8);
+        assert_eq!(light.position, point(1., 0., 0.5));
+    }
+
+    #[test]
+    fn finding_a_single_point_on_an_area_light() {
+        let light = area_light(
+            point(0., 0., 0.),
+            vector(2., 0., 0.),
+            4,
+            vector(0., 0., 1.),
+            2,
+            color(1., 1., 1.),
+        );
+        for &(u, v, ref result) in &[
+            (0, 0, point(0.25, 0., 0.25)),
+            (1, 0, point(0.75, 0., 0.25)),
+            (0, 1, point(0.25, 0., 0.75)),
+            (2, 0, point(1.25, 0., 0.25)),
+            (3, 1, point(1.75, 0., 0.75)),
+        ] {
+            assert_eq!(light.point_on_light(u, v), *result);
+        }
+    }
+
+    #[test]
+    fn the_area_light_intensity_function() {
+        let w = default_world();
+        let light: Light = area_light(
+            point(-0.5, -0.5, -5.),
+            vector(1., 0., 0.),
+            2,
+            vector(0., 1., 0.),
+            2,
+            color(1., 1., 1.),
+        )
+        .into();
+        for (p, result) in &[
+            (point(0., 0., 2.), 0.0),
+            (point(1., -1., 2.), 0.25),
+            (point(1.5, 0., 2.), 0.5),
+            (point(1.25, 1.25, 3.), 0.75),
+            (point(0., 0., -2.), 1.0),
+        ] {
+            assert_eq!(light.intensity_at(p, &w), *result);
+        }
+    }
+
+    #[test]
+    fn a_fully_occluded_point_behind_a_4x4_area_light() {
+        let w = default_world();
+        let light: Light = area_light(
+            point(-0.5, -0.5, -5.),
+            vector(1., 0., 0.),
+            4,
+            vector(0., 1., 0.),
+            4,
+            color(1., 1., 1.),
+        )
+        .into();
+
+        assert_eq!(light.intensity_at(&point(0., 0., 2.), &w), 0.);
+        let edge = light.intensity_at(&point(1.5, 0., 2.), &w);
+        assert!(edge > 0. && edge < 1.);
+    }
+
+    #[test]
+    fn finding_a_single_point_on_a_jittered_area_light() {
+        let mut light = area_light(
+            point(0., 0., 0.),
+            vector(2., 0., 0.),
+            4,
+            vector(0., 0., 1.),
+            2,
+            color(1., 1., 1.),
+        );
+        light.jitter = vec![0.3, 0.7];
+        for &(u, v, ref result) in &[
+            (0, 0, point(0.15, 0., 0.35)),
+            (1, 0, point(0.65, 0., 0.35)),
+            (0, 1, point(0.15, 0., 0.85)),
+            (2, 0, point(1.15, 0., 0.35)),
+            (3, 1, point(1.65, 0., 0.85)),
+        ] {
+            assert_eq!(light.point_on_light(u, v), *result);
+        }
+    }
+
+    #[test]
+    fn a_directional_light_has_a_direction_and_intensity() {
+        let light: Light = directional_light(vector(0., -2., 0.), color(1., 1., 1.)).into();
+
+        assert_eq!(light.intensity(), &color(1., 1., 1.));
+        assert_eq!(
+            light.direction_from(&point(5., 0., -3.)),
+            vector(0., 1., 0.)
+        );
+        assert_eq!(
+            light.direction_from(&point(0., -50., 7.)),
+            vector(0., 1., 0.)
+        );
+    }
+
+    #[test]
+    fn a_spotlight_falls_off_between_its_inner_and_outer_angles() {
+        let light: Light = spot_light(
+            point(0., 10., 0.),
+            vector(0., -1., 0.),
+            color(1., 1., 1.),
+            PI / 8.,
+            PI / 4.,
+        )
+        .into();
+        let outside = 10. * (PI / 4. + 0.01).tan();
+        let penumbra = 10. * (3. * PI / 16.).tan();
+
+        assert_eq!(light.falloff_at(&point(0., 0., 0.)), 1.);
+        assert_eq!(light.falloff_at(&point(outside, 0., 0.)), 0.);
+        assert_eq!(light.falloff_at(&point(0., 20., 0.)), 0.);
+        let f = light.falloff_at(&point(penumbra, 0., 0.));
+        assert_that!(f, greater_than(0.));
+        assert_that!(f, less_than(1.));
+    }
+
+    #[test]
+    fn shadow_rays_span_the_whole_way_to_the_light() {
+        let light: Light = point_light(point(0., 10., 0.), color(1., 1., 1.)).into();
+
+        let rays = light.shadow_rays(&point(0., 2., 0.));
+
+        assert_eq!(rays, vec![(vector(0., 8., 0.), 1.)]);
     }
 }
@@ -1,76 +1,36 @@
-mod bounds;
-mod camera;
-mod canvas;
-mod cones;
-mod cubes;
-mod cylinders;
-mod groups;
-mod intersections;
-mod lights;
-mod materials;
-mod matrices;
-mod obj_file;
-mod patterns;
-mod planes;
-mod ppm;
-mod rays;
-mod shapes;
-mod spheres;
-mod transformations;
-mod triangles;
-mod tuples;
-mod world;
-
-#[cfg(test)]
-#[macro_use]
-extern crate hamcrest2;
-
-use crate::camera::camera;
-use crate::canvas::canvas;
-use crate::groups::Group;
-use crate::lights::point_light;
-use crate::obj_file::parse_obj;
-use crate::patterns::checkers_pattern;
-use crate::planes::plane;
-use crate::transformations::*;
-use crate::tuples::{color, f_u8, point, vector};
-use crate::world::world;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Point;
+use ray_tracer::camera::{camera, Camera};
+use ray_tracer::groups::Group;
+use ray_tracer::lights::point_light;
+use ray_tracer::obj_file::parse_obj_reader;
+use ray_tracer::patterns::checkers_pattern;
+use ray_tracer::planes::plane;
+use ray_tracer::shapes::Shape;
+use ray_tracer::transformations::*;
+use ray_tracer::tuples::{color, point, vector};
+use ray_tracer::world::{world, World};
 use std::f64::consts::PI;
-use std::fs;
 use std::fs::File;
-use std::io::prelude::*;
-use std::sync::mpsc::channel;
+use std::io::BufReader;
 use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
 
 fn read_teapot() -> std::io::Result<Group> {
-    let mut file = File::open("objs/teapot-low.obj")?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    Ok(parse_obj(&contents).to_group())
+    let file = File::open("objs/teapot-low.obj")?;
+    Ok(parse_obj_reader(BufReader::new(file))?.to_group())
 }
 
-fn main() {
-    let (pixel_sender, pixel_reciever) = channel::<(usize, usize, tuples::Color)>();
-    let (width, height) = (2000, 2000);
-
+fn scene(width: usize, height: usize) -> (World, Camera) {
     let waffle = checkers_pattern(color(1., 0.9, 0.1), color(0.9, 1.0, 0.1));
 
     let mut floor = plane();
-    floor.invtransform = rotation_x(PI / 2.).inverse();
+    floor.set_transform(rotation_x(PI / 2.));
     floor.material.reflective = 0.6;
-    floor.material.pattern = Some(Box::new(waffle));
+    floor.material.pattern = Some(Arc::new(waffle));
 
     let teapod = read_teapot().unwrap();
 
     let mut world = world();
     world.objects = vec![Arc::new(floor), Arc::new(teapod)];
-    world.light_sources = vec![point_light(point(30., -30., 30.), color(1., 1., 1.))];
+    world.light_sources = vec![point_light(point(30., -30., 30.), color(1., 1., 1.)).into()];
 
     let mut camera = camera(width, height, PI / 3.);
     camera.invtransform = view_transform(
@@ -80,68 +40,113 @@ fn main() {
     )
     .inverse();
 
-    let threads = 16;
-    let chunk_size = width * height / threads;
-    (0..threads).for_each(|i| {
-        let sender = pixel_sender.clone();
-        let c = camera.clone();
-        let w = world.clone();
-        thread::spawn(move || {
-            c.render_async(w, sender, chunk_size * i..chunk_size * (i + 1));
+    (world, camera)
+}
+
+#[cfg(feature = "preview")]
+fn main() {
+    let (width, height) = (2000, 2000);
+    let (world, camera) = scene(width, height);
+    preview::show(world, camera, width, height);
+}
+
+// without the preview window the image is rendered up front and written next to the binary
+#[cfg(not(feature = "preview"))]
+fn main() {
+    let (world, camera) = scene(2000, 2000);
+    let canvas = camera.render_parallel(world);
+    std::fs::write("./canvas.ppm", canvas.to_ppm_srgb()).expect("Unable to write file");
+    #[cfg(feature = "png")]
+    std::fs::write("./canvas.png", canvas.to_png()).expect("Unable to write file");
+}
+
+#[cfg(feature = "preview")]
+mod preview {
+    use ray_tracer::camera::Camera;
+    use ray_tracer::canvas::canvas;
+    use ray_tracer::world::World;
+    use sdl2::event::Event;
+    use sdl2::keyboard::Keycode;
+    use sdl2::pixels::Color;
+    use sdl2::rect::Point;
+    use std::fs;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    // renders on background threads and draws pixels into a window as they arrive
+    pub fn show(world: World, camera: Camera, width: usize, height: usize) {
+        let (pixel_sender, pixel_reciever) = channel::<(usize, usize, ray_tracer::tuples::Color)>();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let threads = 16;
+        let chunk_size = width * height / threads;
+        (0..threads).for_each(|i| {
+            let sender = pixel_sender.clone();
+            let c = camera.clone();
+            let w = world.clone();
+            let stop = cancel.clone();
+            thread::spawn(move || {
+                c.render_async(w, sender, chunk_size * i..chunk_size * (i + 1), stop);
+            });
         });
-    });
-
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("render preview", width as u32, height as u32)
-        .position_centered()
-        .resizable()
-        .build()
-        .unwrap();
-    let mut view = window.into_canvas().build().unwrap();
-    view.set_logical_size(width as u32, height as u32).unwrap();
-
-    let mut canvas = canvas(width, height);
-
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    'running: loop {
-        // store newly rendered pixels
-        while let Ok((x, y, c)) = pixel_reciever.try_recv() {
-            canvas.write_pixel(x, y, c);
-        }
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'running,
-                Event::KeyDown {
-                    keycode: Some(Keycode::S),
-                    ..
-                } => {
-                    fs::write("./canvas.ppm", canvas.to_ppm()).expect("Unable to write file");
+
+        let sdl_context = sdl2::init().unwrap();
+        let video_subsystem = sdl_context.video().unwrap();
+        let window = video_subsystem
+            .window("render preview", width as u32, height as u32)
+            .position_centered()
+            .resizable()
+            .build()
+            .unwrap();
+        let mut view = window.into_canvas().build().unwrap();
+        view.set_logical_size(width as u32, height as u32).unwrap();
+
+        let mut canvas = canvas(width, height);
+
+        let mut event_pump = sdl_context.event_pump().unwrap();
+        'running: loop {
+            // store newly rendered pixels
+            while let Ok((x, y, c)) = pixel_reciever.try_recv() {
+                canvas.write_pixel(x, y, c);
+            }
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => {
+                        cancel.store(true, Ordering::Relaxed);
+                        break 'running;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::S),
+                        ..
+                    } => {
+                        fs::write("./canvas.ppm", canvas.to_ppm_srgb())
+                            .expect("Unable to write file");
+                        #[cfg(feature = "png")]
+                        fs::write("./canvas.png", canvas.to_png()).expect("Unable to write file");
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
-        }
 
-        view.set_draw_color(Color::RGB(204, 204, 204));
-        view.clear();
-        for x in 0..canvas.width {
-            for y in 0..canvas.height {
-                let color = canvas.pixel_at(x, y);
-                view.set_draw_color(Color::RGB(
-                    f_u8(color.red),
-                    f_u8(color.green),
-                    f_u8(color.blue),
-                ));
-                view.draw_point(Point::new(x as i32, y as i32)).unwrap();
+            view.set_draw_color(Color::RGB(204, 204, 204));
+            view.clear();
+            for x in 0..canvas.width {
+                for y in 0..canvas.height {
+                    let (r, g, b) = canvas.pixel_at(x, y).to_rgb8();
+                    view.set_draw_color(Color::RGB(r, g, b));
+                    view.draw_point(Point::new(x as i32, y as i32)).unwrap();
+                }
             }
-        }
 
-        view.present();
-        thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
+            view.present();
+            thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
+        }
     }
 }
@@ -1,32 +1,46 @@
-use crate::lights::PointLight;
+use crate::lights::Light;
 use crate::patterns::SyncPattern;
 use crate::shapes::SyncShape;
-use crate::tuples::{color, Color, Tuple};
+use crate::tuples::{Color, Tuple};
 use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Material {
     pub ambient: f64,
+    pub casts_shadow: bool,
     pub color: Color,
     pub diffuse: f64,
-    pub pattern: Option<Box<SyncPattern>>,
+    pub emission: Color,
+    // weight the surface light by what reflection and transparency leave instead of adding them
+    // on top of it as the book does
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub energy_conserving: bool,
+    // the built-in patterns are saved by kind, a custom pattern is saved as none
+    #[cfg_attr(feature = "serde", serde(default, with = "crate::patterns::saved"))]
+    pub pattern: Option<Arc<SyncPattern>>,
     pub refractive_index: f64,
     pub reflective: f64,
     pub shininess: f64,
     pub specular: f64,
+    pub specular_color: Option<Color>,
     pub transparency: f64,
 }
 
 pub fn material() -> Material {
     Material {
         ambient: 0.1,
-        color: color(1., 1., 1.),
+        casts_shadow: true,
+        color: Color::WHITE,
         diffuse: 0.9,
+        emission: Color::BLACK,
+        energy_conserving: false,
         pattern: None,
         refractive_index: 1.0,
         reflective: 0.0,
         shininess: 200.,
         specular: 0.9,
+        specular_color: None,
         transparency: 0.,
     }
 }
@@ -35,56 +49,99 @@ impl Material {
     pub fn lighting(
         &self,
         object: Arc<SyncShape>,
-        light: &PointLight,
+        light: &Light,
         position: &Tuple,
         eye: &Tuple,
         normal: &Tuple,
-        in_shadow: bool,
+        light_intensity: &Color,
     ) -> Color {
-        let pos = self.pattern.as_ref().map(|p| p.at_shape(object, position));
-        let surface_color = pos.as_ref().unwrap_or(&self.color);
+        let surface = self.color_at(object, position, None);
+        self.ambient_lighting(&surface, light.intensity())
+            + self.direct_lighting(&surface, light, position, eye, normal, light_intensity)
+            + self.emission.clone()
+    }
+
+    // the flat ambient term, added once per hit however many lights there are
+    pub fn ambient_lighting(&self, surface: &Color, intensity: &Color) -> Color {
+        surface * intensity * self.ambient
+    }
 
+    // diffuse and specular light from a single source, without ambient and the surface's own
+    // emission so that it can be summed over several lights
+    pub fn direct_lighting(
+        &self,
+        surface: &Color,
+        light: &Light,
+        position: &Tuple,
+        eye: &Tuple,
+        normal: &Tuple,
+        light_intensity: &Color,
+    ) -> Color {
         // combine the surface color with the light's color/intensity
-        let effective_color = surface_color * &light.intensity;
+        let effective_color = surface * light.intensity();
+        // what is left of the light after shadows and the falloff of the light
+        let direct = light_intensity * light.falloff_at(position);
 
         // find the direction to the light sourse
-        let lightv = (&light.position - position).normalized();
-
-        //compute the ambient contribution
-        let ambient = &effective_color * self.ambient;
+        let lightv = light.direction_from(position);
 
         //light dot normal represents the cosine of the angle between the light vector and the
         //normal vector. A negative number means the light is on the other side of the surface.
         let light_dot_normal = lightv.dot(&normal);
-        let black = color(0.0, 0.0, 0.0);
-        let diffuse = if light_dot_normal < 0. || in_shadow {
+        let black = Color::BLACK;
+        let diffuse = if light_dot_normal < 0. {
             black.clone()
         } else {
-            effective_color * self.diffuse * light_dot_normal
+            &effective_color * &direct * (self.diffuse * light_dot_normal)
         };
         let reflectv = (-lightv).reflect(&normal);
         //relfect dot eye represents the cosine of the angle between the reflectin vector and the
         //eye vector. A negative number means the light reflects away from the eye.
         let reflect_dot_eye = reflectv.dot(&eye);
-        let specular = if reflect_dot_eye <= 0. || in_shadow {
+        let specular = if reflect_dot_eye <= 0. || !light.casts_specular() {
             black
         } else {
             let factor = reflect_dot_eye.powf(self.shininess);
-            &light.intensity * self.specular * factor
+            // highlight takes the light color unless the material tints it (e.g. metals)
+            let highlight = match &self.specular_color {
+                Some(c) => light.intensity() * c,
+                None => light.intensity().clone(),
+            };
+            &highlight * &direct * (self.specular * factor)
         };
 
-        ambient + diffuse + specular
+        diffuse + specular
+    }
+
+    // the pattern at the point or the plain color of the material
+    pub fn color_at(
+        &self,
+        object: Arc<SyncShape>,
+        position: &Tuple,
+        uv: Option<(f64, f64)>,
+    ) -> Color {
+        match &self.pattern {
+            Some(p) => p.at_surface(object, position, uv),
+            None => self.color.clone(),
+        }
     }
 }
 
 #[cfg(test)]
 mod spec {
     use super::*;
-    use crate::lights::point_light;
+    use crate::lights::{point_light, spot_light};
     use crate::patterns::stripe_pattern;
+    #[cfg(feature = "serde")]
+    use crate::patterns::{gradient_pattern, ring_pattern, stripe_pattern_nested, Pattern};
     use crate::spheres::sphere;
+    #[cfg(feature = "serde")]
+    use crate::transformations::{scaling, translation};
     use crate::tuples::{color, point, vector};
+    #[cfg(feature = "serde")]
+    use crate::uv::{spherical_uv, texture_map, uv_checkers};
     use hamcrest2::prelude::*;
+    use std::f64::consts::PI;
 
     #[test]
     fn the_default_material() {
@@ -94,6 +151,7 @@ mod spec {
         assert_eq!(m.diffuse, 0.9);
         assert_eq!(m.specular, 0.9);
         assert_eq!(m.shininess, 200.);
+        assert_eq!(m.specular_color, None);
     }
 
     #[test]
@@ -103,8 +161,15 @@ mod spec {
         let position = point(0., 0., 0.);
         let eyev = vector(0., 0., -1.);
         let normalv = vector(0., 0., -1.);
-        let light = point_light(point(0., 0., -10.), color(1., 1., 1.));
-        let result = m.lighting(object, &light, &position, &eyev, &normalv, false);
+        let light = point_light(point(0., 0., -10.), color(1., 1., 1.)).into();
+        let result = m.lighting(
+            object,
+            &light,
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
         assert_eq!(result, color(1.9, 1.9, 1.9));
     }
 
@@ -116,8 +181,15 @@ mod spec {
         let a = 2_f64.sqrt() / 2.;
         let eyev = vector(0., a, -a);
         let normalv = vector(0., 0., -1.);
-        let light = point_light(point(0., 0., -10.), color(1., 1., 1.));
-        let result = m.lighting(object, &light, &position, &eyev, &normalv, false);
+        let light = point_light(point(0., 0., -10.), color(1., 1., 1.)).into();
+        let result = m.lighting(
+            object,
+            &light,
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
         assert_eq!(result, color(1., 1., 1.));
     }
 
@@ -128,8 +200,15 @@ mod spec {
         let position = point(0., 0., 0.);
         let eyev = vector(0., 0., -1.);
         let normalv = vector(0., 0., -1.);
-        let light = point_light(point(0., 10., -10.), color(1., 1., 1.));
-        let result = m.lighting(object, &light, &position, &eyev, &normalv, false);
+        let light = point_light(point(0., 10., -10.), color(1., 1., 1.)).into();
+        let result = m.lighting(
+            object,
+            &light,
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
         assert_eq!(result, color(0.7364, 0.7364, 0.7364));
     }
 
@@ -141,11 +220,49 @@ mod spec {
         let a = 2_f64.sqrt() / 2.;
         let eyev = vector(0., -a, -a);
         let normalv = vector(0., 0., -1.);
-        let light = point_light(point(0., 10., -10.), color(1., 1., 1.));
-        let result = m.lighting(object, &light, &position, &eyev, &normalv, false);
+        let light = point_light(point(0., 10., -10.), color(1., 1., 1.)).into();
+        let result = m.lighting(
+            object,
+            &light,
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
         assert_eq!(result, color(1.6364, 1.6364, 1.6364));
     }
 
+    #[test]
+    fn lighting_with_a_tinted_specular_color() {
+        let mut m = material();
+        m.specular_color = Some(color(1., 0.5, 0.));
+        let object = Arc::new(sphere());
+        let position = point(0., 0., 0.);
+        let a = 2_f64.sqrt() / 2.;
+        let normalv = vector(0., 0., -1.);
+        let light = point_light(point(0., 10., -10.), color(1., 1., 1.)).into();
+
+        let highlight = m.lighting(
+            object.clone(),
+            &light,
+            &position,
+            &vector(0., -a, -a),
+            &normalv,
+            &color(1., 1., 1.),
+        );
+        let no_highlight = m.lighting(
+            object.clone(),
+            &light,
+            &position,
+            &vector(0., 0., -1.),
+            &normalv,
+            &color(1., 1., 1.),
+        );
+
+        assert_eq!(highlight, color(1.6364, 1.1864, 0.7364));
+        assert_eq!(no_highlight, color(0.7364, 0.7364, 0.7364));
+    }
+
     #[test]
     fn lighting_with_the_light_behind_the_surface() {
         let m = material();
@@ -153,8 +270,15 @@ mod spec {
         let position = point(0., 0., 0.);
         let eyev = vector(0., 0., -1.);
         let normalv = vector(0., 0., -1.);
-        let light = point_light(point(0., 0., 10.), color(1., 1., 1.));
-        let result = m.lighting(object, &light, &position, &eyev, &normalv, false);
+        let light = point_light(point(0., 0., 10.), color(1., 1., 1.)).into();
+        let result = m.lighting(
+            object,
+            &light,
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
         assert_eq!(result, color(0.1, 0.1, 0.1));
     }
 
@@ -165,19 +289,103 @@ mod spec {
         let position = point(0., 0., 0.);
         let eyev = vector(0., 0., -1.);
         let normalv = vector(0., 0., -1.);
-        let light = point_light(point(0., 0., -10.), color(1., 1., 1.));
-        let in_shadow = true;
+        let light = point_light(point(0., 0., -10.), color(1., 1., 1.)).into();
+        let light_intensity = color(0., 0., 0.);
 
-        let result = m.lighting(object, &light, &position, &eyev, &normalv, in_shadow);
+        let result = m.lighting(object, &light, &position, &eyev, &normalv, &light_intensity);
 
         assert_that!(result, eq(color(0.1, 0.1, 0.1)));
     }
 
+    #[test]
+    fn lighting_adds_the_emission_of_a_surface_in_shadow() {
+        let mut m = material();
+        m.emission = color(0.5, 0., 0.);
+        let object = Arc::new(sphere());
+        let position = point(0., 0., 0.);
+        let eyev = vector(0., 0., -1.);
+        let normalv = vector(0., 0., -1.);
+        let light = point_light(point(0., 0., -10.), color(1., 1., 1.)).into();
+        let light_intensity = color(0., 0., 0.);
+
+        let result = m.lighting(object, &light, &position, &eyev, &normalv, &light_intensity);
+
+        assert_that!(result, eq(color(0.6, 0.1, 0.1)));
+    }
+
+    #[test]
+    fn lighting_outside_of_a_spotlight_cone_leaves_only_ambient() {
+        let m = material();
+        let object = Arc::new(sphere());
+        let eyev = vector(0., 0., -1.);
+        let normalv = vector(0., 0., -1.);
+        let light = spot_light(
+            point(0., 0., -10.),
+            vector(0., 0., 1.),
+            color(1., 1., 1.),
+            PI / 16.,
+            PI / 8.,
+        )
+        .into();
+
+        let lit = m.lighting(
+            object.clone(),
+            &light,
+            &point(0., 0., 0.),
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
+        let dark = m.lighting(
+            object,
+            &light,
+            &point(0., 10., 0.),
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
+
+        assert_that!(lit, eq(color(1.9, 1.9, 1.9)));
+        assert_that!(dark, eq(color(0.1, 0.1, 0.1)));
+    }
+
+    #[test]
+    fn a_fill_light_adds_diffuse_but_no_specular() {
+        let m = material();
+        let object = Arc::new(sphere());
+        let position = point(0., 0., 0.);
+        let eyev = vector(0., 0., -1.);
+        let normalv = vector(0., 0., -1.);
+        let mut fill = point_light(point(0., 0., -10.), color(1., 1., 1.));
+        fill.casts_specular = false;
+        let key = point_light(point(0., 0., -10.), color(1., 1., 1.));
+
+        let with = m.lighting(
+            object.clone(),
+            &key.into(),
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
+        let without = m.lighting(
+            object,
+            &fill.into(),
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
+
+        assert_that!(with, eq(color(1.9, 1.9, 1.9)));
+        assert_that!(without, eq(color(1.0, 1.0, 1.0)));
+    }
+
     #[test]
     fn lighting_with_a_pattern_applied() {
         let mut m = material();
         let object = Arc::new(sphere());
-        m.pattern = Some(Box::new(stripe_pattern(
+        m.pattern = Some(Arc::new(stripe_pattern(
             color(1., 1., 1.),
             color(0., 0., 0.),
         )));
@@ -186,8 +394,8 @@ mod spec {
         m.specular = 0.;
         let eyev = vector(0., 0., -1.);
         let normalv = vector(0., 0., -1.);
-        let light = point_light(point(0., 0., -10.), color(1., 1., 1.));
-        let in_shadow = false;
+        let light = point_light(point(0., 0., -10.), color(1., 1., 1.)).into();
+        let light_intensity = color(1., 1., 1.);
 
         let c1 = m.lighting(
             object.clone(),
@@ -195,7 +403,7 @@ mod spec {
             &point(0.9, 0., 0.),
             &eyev,
             &normalv,
-            in_shadow,
+            &light_intensity,
         );
         let c2 = m.lighting(
             object.clone(),
@@ -203,13 +411,89 @@ mod spec {
             &point(1.1, 0., 0.),
             &eyev,
             &normalv,
-            in_shadow,
+            &light_intensity,
         );
 
         assert_that!(c1, eq(color(1., 1., 1.)));
         assert_that!(c2, eq(color(0., 0., 0.)));
     }
 
+    #[test]
+    fn materials_can_share_a_pattern() {
+        let pattern: Arc<SyncPattern> =
+            Arc::new(stripe_pattern(color(1., 1., 1.), color(0., 0., 0.)));
+        let mut m1 = material();
+        m1.pattern = Some(pattern.clone());
+        let mut m2 = material();
+        m2.pattern = Some(pattern.clone());
+        let object = Arc::new(sphere());
+        let position = point(1.1, 0., 0.);
+        let eyev = vector(0., 0., -1.);
+        let normalv = vector(0., 0., -1.);
+        let light = point_light(point(0., 0., -10.), color(1., 1., 1.)).into();
+
+        let c1 = m1.lighting(
+            object.clone(),
+            &light,
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
+        let c2 = m2.lighting(
+            object.clone(),
+            &light,
+            &position,
+            &eyev,
+            &normalv,
+            &color(1., 1., 1.),
+        );
+
+        assert!(Arc::ptr_eq(
+            m1.pattern.as_ref().unwrap(),
+            m2.pattern.as_ref().unwrap()
+        ));
+        assert_eq!(c1, c2);
+    }
+
+    #[test]
+    fn a_cloned_material_shades_like_the_original() {
+        let mut m = material();
+        m.pattern = Some(Arc::new(stripe_pattern(
+            color(1., 1., 1.),
+            color(0., 0., 0.),
+        )));
+        let object = Arc::new(sphere());
+        let eyev = vector(0., 0., -1.);
+        let normalv = vector(0., 0., -1.);
+        let light = point_light(point(0., 0., -10.), color(1., 1., 1.)).into();
+
+        let clone = m.clone();
+
+        assert_eq!(clone, m);
+        for x in &[0.5, 1.5] {
+            let position = point(*x, 0., 0.);
+            assert_eq!(
+                clone.lighting(
+                    object.clone(),
+                    &light,
+                    &position,
+                    &eyev,
+                    &normalv,
+                    &color(1., 1., 1.)
+                ),
+                m.lighting(
+                    object.clone(),
+                    &light,
+                    &position,
+                    &eyev,
+                    &normalv,
+                    &color(1., 1., 1.)
+                )
+            );
+        }
+    }
+
     #[test]
     fn reflectivity_for_the_default_material() {
         let m = material();
@@ -223,4 +507,61 @@ mod spec {
         assert_eq!(m.transparency, 0.);
         assert_eq!(m.refractive_index, 1.);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn a_material_survives_a_json_round_trip() {
+        let mut m = material();
+        m.color = color(0.5, 0.25, 1.);
+        m.specular_color = Some(color(1., 0., 0.));
+        m.casts_shadow = false;
+
+        let json = serde_json::to_string(&m).unwrap();
+        let loaded: Material = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded, m);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn a_material_keeps_its_pattern_through_a_json_round_trip() {
+        let mut inner = ring_pattern(color(0., 0., 1.), color(0., 1., 0.));
+        inner.set_invtransform(scaling(0.5, 0.5, 0.5));
+        let mut stripes = stripe_pattern_nested(
+            Box::new(inner),
+            Box::new(gradient_pattern(color(1., 0., 0.), color(0., 0., 0.))),
+        );
+        stripes.set_invtransform(translation(0.25, 0., 0.));
+        let mut m = material();
+        m.pattern = Some(Arc::new(stripes));
+        let points = [point(0., 0., 0.), point(0.5, 0., 0.), point(0.8, 0., 1.2)];
+
+        let json = serde_json::to_string(&m).unwrap();
+        let loaded: Material = serde_json::from_str(&json).unwrap();
+
+        assert!(json.contains(r#""type":"stripe""#));
+        assert_eq!(loaded, m);
+        let (a, b) = (m.pattern.unwrap(), loaded.pattern.unwrap());
+        for p in &points {
+            assert_eq!(b.at(&(b.invtransform() * p)), a.at(&(a.invtransform() * p)));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn a_uv_checkers_texture_map_survives_a_json_round_trip() {
+        let pattern = texture_map(
+            Box::new(uv_checkers(16., 8., color(0., 0., 0.), color(1., 1., 1.))),
+            spherical_uv,
+        );
+        let mut m = material();
+        m.pattern = Some(Arc::new(pattern));
+
+        let json = serde_json::to_string(&m).unwrap();
+        let loaded: Material = serde_json::from_str(&json).unwrap();
+
+        let p = point(0.4315, 0.467, 0.7719);
+        assert!(json.contains(r#""mapping":"spherical""#));
+        assert_eq!(loaded.pattern.unwrap().at(&p), color(1., 1., 1.));
+    }
 }
@@ -1,18 +1,23 @@
 use crate::tuples::{tuple, Tuple};
+use std::convert::TryFrom;
+use std::ops::Add;
 use std::ops::Index;
 use std::ops::Mul;
 
+// up to 4x4 values stored inline, rows and cols tell how much of the array is in use
 #[derive(Clone, Debug)]
 pub struct Matrix {
-    pub data: Vec<Vec<f64>>,
+    pub data: [[f64; 4]; 4],
+    rows: usize,
+    cols: usize,
 }
 
 impl PartialEq for Matrix {
     fn eq(&self, other: &Matrix) -> bool {
-        self.data.len() == other.data.len()
-            && self.data.iter().zip(other.data.iter()).all(|(l, r)| {
-                l.len() == r.len() && l.iter().zip(r.iter()).all(|(a, b)| close(*a, *b))
-            })
+        self.rows == other.rows
+            && self.cols == other.cols
+            && (0..self.rows)
+                .all(|i| (0..self.cols).all(|j| close(self.data[i][j], other.data[i][j])))
     }
 }
 
@@ -20,21 +25,126 @@ fn close(a: f64, b: f64) -> bool {
     (a - b).abs() <= 1e-5
 }
 
+fn zeros(rows: usize, cols: usize) -> Matrix {
+    Matrix {
+        data: [[0.; 4]; 4],
+        rows,
+        cols,
+    }
+}
+
 pub fn matrix(args: &[&[f64]]) -> Matrix {
-    let data = args
-        .iter()
-        .map(|row| Vec::from(*row))
-        .collect::<Vec<Vec<f64>>>();
-    Matrix { data }
+    let cols = args.first().map_or(0, |row| row.len());
+    assert!(args.len() <= 4 && cols <= 4, "matrices are at most 4x4");
+    let mut m = zeros(args.len(), cols);
+    for (i, row) in args.iter().enumerate() {
+        assert_eq!(row.len(), cols, "matrix rows have the same length");
+        m.data[i][..cols].copy_from_slice(row);
+    }
+    m
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MatrixShapeError {
+    Empty,
+    Ragged {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    TooLarge {
+        rows: usize,
+        cols: usize,
+    },
+}
+
+impl TryFrom<Vec<Vec<f64>>> for Matrix {
+    type Error = MatrixShapeError;
+    fn try_from(data: Vec<Vec<f64>>) -> Result<Matrix, MatrixShapeError> {
+        let expected = data.first().map_or(0, |row| row.len());
+        if expected == 0 {
+            return Err(MatrixShapeError::Empty);
+        }
+        if let Some(row) = data.iter().position(|row| row.len() != expected) {
+            return Err(MatrixShapeError::Ragged {
+                row,
+                expected,
+                found: data[row].len(),
+            });
+        }
+        if data.len() > 4 || expected > 4 {
+            return Err(MatrixShapeError::TooLarge {
+                rows: data.len(),
+                cols: expected,
+            });
+        }
+        let rows: Vec<&[f64]> = data.iter().map(|row| row.as_slice()).collect();
+        Ok(matrix(&rows))
+    }
+}
+
+impl From<[[f64; 4]; 4]> for Matrix {
+    fn from(data: [[f64; 4]; 4]) -> Matrix {
+        Matrix {
+            data,
+            rows: 4,
+            cols: 4,
+        }
+    }
 }
 
 impl Index<(usize, usize)> for Matrix {
     type Output = f64;
     fn index(&self, pair: (usize, usize)) -> &f64 {
+        debug_assert!(pair.0 < self.rows && pair.1 < self.cols);
         &self.data[pair.0][pair.1]
     }
 }
 
+impl Add for Matrix {
+    type Output = Matrix;
+    fn add(self, other: Matrix) -> Matrix {
+        &self + &other
+    }
+}
+
+impl Add for &Matrix {
+    type Output = Matrix;
+    fn add(self, other: &Matrix) -> Matrix {
+        assert!(
+            self.rows == other.rows && self.cols == other.cols,
+            "added matrices have the same size"
+        );
+        let mut m = zeros(self.rows, self.cols);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                m.data[i][j] = self.data[i][j] + other.data[i][j];
+            }
+        }
+        m
+    }
+}
+
+impl Mul<f64> for Matrix {
+    type Output = Matrix;
+    fn mul(self, other: f64) -> Matrix {
+        &self * other
+    }
+}
+
+impl Mul<f64> for &Matrix {
+    type Output = Matrix;
+    fn mul(self, other: f64) -> Matrix {
+        let mut m = zeros(self.rows, self.cols);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                m.data[i][j] = self.data[i][j] * other;
+            }
+        }
+        m
+    }
+}
+
 impl Mul for Matrix {
     type Output = Matrix;
     fn mul(self, other: Matrix) -> Matrix {
@@ -45,15 +155,15 @@ impl Mul for Matrix {
 impl<'a> Mul for &'a Matrix {
     type Output = Matrix;
     fn mul(self, other: &'a Matrix) -> Matrix {
-        let rows = &self.data;
-        let cols = other.transpose().data;
-        let mut data = vec![vec![0.; cols.len()]; rows.len()];
-        for i in 0..rows.len() {
-            for j in 0..cols.len() {
-                data[i][j] = dot(&rows[i], &cols[j]);
+        let mut m = zeros(self.rows, other.cols);
+        for i in 0..self.rows {
+            for j in 0..other.cols {
+                m.data[i][j] = (0..self.cols)
+                    .map(|k| self.data[i][k] * other.data[k][j])
+                    .sum();
             }
         }
-        Matrix { data }
+        m
     }
 }
 
@@ -67,57 +177,87 @@ impl Mul<Tuple> for Matrix {
 impl<'a> Mul<&'a Tuple> for &'a Matrix {
     type Output = Tuple;
     fn mul(self, other: &'a Tuple) -> Tuple {
-        let rows = &self.data;
-        let cols = matrix(&[&[other.x, other.y, other.z, other.w]]).data;
-        let mut tuple = tuple(0., 0., 0., 0.);
-        tuple.x = dot(&rows[0], &cols[0]);
-        tuple.y = dot(&rows[1], &cols[0]);
-        tuple.z = dot(&rows[2], &cols[0]);
-        tuple.w = dot(&rows[3], &cols[0]);
-        tuple
+        let row = |r: &[f64; 4]| r[0] * other.x + r[1] * other.y + r[2] * other.z + r[3] * other.w;
+        tuple(
+            row(&self.data[0]),
+            row(&self.data[1]),
+            row(&self.data[2]),
+            row(&self.data[3]),
+        )
     }
 }
 
-fn dot(a: &Vec<f64>, b: &Vec<f64>) -> f64 {
-    a.iter()
-        .zip(b.iter())
-        .map(|(l, r)| l * r)
-        .fold(0., |acc, f| acc + f)
-}
-
 impl Matrix {
     pub fn transpose(&self) -> Matrix {
-        let w = self.data[0].len();
-        let h = self.data.len();
-        let mut data = vec![vec![0.; h]; w];
-        for i in 0..w {
-            for j in 0..h {
-                data[i][j] = self.data[j][i];
+        let mut m = zeros(self.cols, self.rows);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                m.data[j][i] = self.data[i][j];
             }
         }
-        Matrix { data }
+        m
     }
 
     pub fn inverse(&self) -> Matrix {
+        self.try_inverse().expect("matrix is not invertible")
+    }
+
+    // None for singular matrices instead of filling the inverse with inf and NaN
+    pub fn try_inverse(&self) -> Option<Matrix> {
         let det = self.determinant();
-        let ct = self.cofactors().transpose();
-        let h = ct.data[0].len();
-        let w = ct.data.len();
-        let mut data = vec![vec![0.; h]; w];
-        for i in 0..w {
-            for j in 0..h {
-                data[i][j] = ct.data[i][j] / det;
+        if det == 0. {
+            return None;
+        }
+        let mut m = zeros(self.cols, self.rows);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                m.data[j][i] = self.cofactor(i, j) / det;
+            }
+        }
+        Some(m)
+    }
+
+    // repeated multiplication, negative powers repeat the inverse
+    pub fn powi(&self, n: i32) -> Matrix {
+        let base = if n < 0 { self.inverse() } else { self.clone() };
+        (0..n.abs()).fold(identity_matrix(), |acc, _| &acc * &base)
+    }
+
+    // element-wise blend, t 0 gives self and t 1 gives other. Only valid for transforms that
+    // rotate by small deltas, halfway between two rotations far apart the object also shrinks
+    pub fn lerp(&self, other: &Matrix, t: f64) -> Matrix {
+        self * (1. - t) + other * t
+    }
+
+    // blends two affine transforms so the translation moves in a straight line while the axes of
+    // the linear part keep a length between the ones of the two transforms, a blend of rotations
+    // about the same axis stays a rotation
+    pub fn lerp_transform(&self, other: &Matrix, t: f64) -> Matrix {
+        let mut m = self.lerp(other, t);
+        let length = |m: &Matrix, col: usize| {
+            (0..3)
+                .map(|row| m.data[row][col] * m.data[row][col])
+                .sum::<f64>()
+                .sqrt()
+        };
+        for col in 0..3 {
+            let current = length(&m, col);
+            if current > 0. {
+                let scale = (length(self, col) * (1. - t) + length(other, col) * t) / current;
+                for row in 0..3 {
+                    m.data[row][col] *= scale;
+                }
             }
         }
-        Matrix { data }
+        m
     }
 
     fn determinant(&self) -> f64 {
-        if self.data.len() == 2 {
+        if self.rows == 2 {
             self[(0, 0)] * self[(1, 1)] - self[(0, 1)] * self[(1, 0)]
         } else {
             let mut det = 0.;
-            for column in 0..self.data.len() {
+            for column in 0..self.cols {
                 det += self.data[0][column] * self.cofactor(0, column);
             }
             det
@@ -125,12 +265,13 @@ impl Matrix {
     }
 
     fn submatrix(&self, row: usize, col: usize) -> Matrix {
-        let mut data = self.data.clone();
-        data.remove(row);
-        for r in &mut data {
-            r.remove(col);
+        let mut m = zeros(self.rows - 1, self.cols - 1);
+        for (i, r) in (0..self.rows).filter(|&r| r != row).enumerate() {
+            for (j, c) in (0..self.cols).filter(|&c| c != col).enumerate() {
+                m.data[i][j] = self.data[r][c];
+            }
         }
-        Matrix { data: data }
+        m
     }
 
     fn minor(&self, row: usize, col: usize) -> f64 {
@@ -142,36 +283,154 @@ impl Matrix {
         sign * self.minor(row, col)
     }
 
-    fn is_invertible(&self) -> bool {
+    pub fn is_invertible(&self) -> bool {
         self.determinant() != 0.
     }
-
-    fn cofactors(&self) -> Matrix {
-        let h = self.data[0].len();
-        let w = self.data.len();
-        let mut data = vec![vec![0.; h]; w];
-        for i in 0..w {
-            for j in 0..h {
-                data[i][j] = self.cofactor(i, j);
-            }
-        }
-        Matrix { data }
-    }
 }
 
 pub fn identity_matrix() -> Matrix {
-    matrix(&[
-        &[1., 0., 0., 0.],
-        &[0., 1., 0., 0.],
-        &[0., 0., 1., 0.],
-        &[0., 0., 0., 1.],
+    Matrix::from([
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.],
     ])
 }
 
+// saved as a nested array of rows
+#[cfg(feature = "serde")]
+impl serde::Serialize for Matrix {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<&[f64]> = self.data[..self.rows]
+            .iter()
+            .map(|row| &row[..self.cols])
+            .collect();
+        rows.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Matrix {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Matrix, D::Error> {
+        let rows = Vec::<Vec<f64>>::deserialize(deserializer)?;
+        Matrix::try_from(rows).map_err(|e| serde::de::Error::custom(format!("{:?}", e)))
+    }
+}
+
 #[cfg(test)]
 mod spec {
     use super::*;
+    use crate::transformations::rotation_z;
+    use crate::transformations::scaling;
+    use crate::transformations::translation;
     use crate::tuples::tuple;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn adding_the_zero_matrix() {
+        let a = matrix(&[&[1., 2., 3.], &[4., 5., 6.]]);
+
+        assert_eq!(&a + &zeros(2, 3), a);
+        assert_eq!(a.clone() + a, matrix(&[&[2., 4., 6.], &[8., 10., 12.]]));
+    }
+
+    #[test]
+    #[should_panic(expected = "added matrices have the same size")]
+    fn adding_matrices_of_different_sizes() {
+        let _ = zeros(2, 3) + zeros(3, 2);
+    }
+
+    #[test]
+    fn multiplying_a_matrix_by_a_scalar() {
+        let m = identity_matrix() * 2.;
+
+        assert_eq!(
+            m,
+            matrix(&[
+                &[2., 0., 0., 0.],
+                &[0., 2., 0., 0.],
+                &[0., 0., 2., 0.],
+                &[0., 0., 0., 2.],
+            ])
+        );
+    }
+
+    #[test]
+    fn interpolating_between_matrices() {
+        let a = matrix(&[&[1., 2.], &[3., 4.]]);
+        let b = matrix(&[&[3., 6.], &[-3., 0.]]);
+
+        assert_eq!(a.lerp(&b, 0.), a);
+        assert_eq!(a.lerp(&b, 1.), b);
+        assert_eq!(a.lerp(&b, 0.5), matrix(&[&[2., 4.], &[0., 2.]]));
+    }
+
+    #[test]
+    fn interpolating_between_rotations_keeps_the_scale() {
+        let a = translation(0., 0., 0.) * rotation_z(0.);
+        let b = translation(4., 2., 0.) * rotation_z(PI / 2.);
+
+        let halfway = a.lerp_transform(&b, 0.5);
+
+        assert_eq!(halfway, translation(2., 1., 0.) * rotation_z(PI / 4.));
+        assert_eq!(a.lerp_transform(&b, 0.), a);
+        assert_eq!(a.lerp_transform(&b, 1.), b);
+    }
+
+    #[test]
+    fn converting_rectangular_rows_into_a_matrix() {
+        let m = Matrix::try_from(vec![vec![1., 2., 3.], vec![4., 5., 6.]]);
+
+        assert_eq!(m, Ok(matrix(&[&[1., 2., 3.], &[4., 5., 6.]])));
+    }
+
+    #[test]
+    fn converting_ragged_rows_into_a_matrix_fails() {
+        let m = Matrix::try_from(vec![vec![1., 2.], vec![3., 4.], vec![5.]]);
+
+        assert_eq!(
+            m,
+            Err(MatrixShapeError::Ragged {
+                row: 2,
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(Matrix::try_from(vec![]), Err(MatrixShapeError::Empty));
+        assert_eq!(Matrix::try_from(vec![vec![]]), Err(MatrixShapeError::Empty));
+    }
+
+    #[test]
+    fn converting_rows_larger_than_4x4_fails() {
+        let m = Matrix::try_from(vec![vec![1.; 5]; 2]);
+
+        assert_eq!(m, Err(MatrixShapeError::TooLarge { rows: 2, cols: 5 }));
+    }
+
+    #[test]
+    fn multiplying_matrices_a_million_times_stays_on_the_stack() {
+        let a = rotation_z(PI / 7.);
+        let mut m = identity_matrix();
+        for _ in 0..1_000_000 {
+            m = &m * &a;
+        }
+
+        // nothing to drop means no heap memory is owned by a matrix
+        assert!(!std::mem::needs_drop::<Matrix>());
+        assert_eq!(m, a.powi(1_000_000 % 14));
+    }
+
+    #[test]
+    fn converting_a_4x4_array_into_a_matrix() {
+        let m = Matrix::from([
+            [1., 0., 0., 0.],
+            [0., 1., 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ]);
+
+        assert_eq!(m, identity_matrix());
+    }
 
     #[test]
     fn constructing_and_inspecting_a_4x4_matrix() {
@@ -411,6 +670,25 @@ mod spec {
         assert!(!a.is_invertible());
     }
 
+    #[test]
+    fn inverting_a_non_invertible_matrix_gives_nothing() {
+        let a = matrix(&[
+            &[-4., 2., -2., -3.],
+            &[9., 6., 2., 6.],
+            &[0., -5., 1., -5.],
+            &[0., 0., 0., 0.],
+        ]);
+
+        assert_eq!(a.try_inverse(), None);
+        assert!(identity_matrix().try_inverse().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "matrix is not invertible")]
+    fn inverse_panics_on_a_non_invertible_matrix() {
+        scaling(1., 0., 1.).inverse();
+    }
+
     #[test]
     fn calculating_the_inverse_of_a_matrix() {
         let a = matrix(&[
@@ -490,4 +768,26 @@ mod spec {
         ]);
         assert_eq!((&a * &b) * b.inverse(), a);
     }
+
+    #[test]
+    fn raising_a_matrix_to_a_power() {
+        let a = rotation_z(PI / 4.);
+
+        assert_eq!(a.powi(0), identity_matrix());
+        assert_eq!(a.powi(1), a);
+        assert_eq!(a.powi(2), rotation_z(PI / 2.));
+        assert_eq!(a.powi(-1), a.inverse());
+        assert_eq!(a.powi(-2), rotation_z(-PI / 2.));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn a_matrix_is_saved_as_rows() {
+        let m = matrix(&[&[1., 2.], &[3., 4.]]);
+
+        let json = serde_json::to_string(&m).unwrap();
+
+        assert_eq!(json, "[[1.0,2.0],[3.0,4.0]]");
+        assert_eq!(serde_json::from_str::<Matrix>(&json).unwrap(), m);
+    }
 }
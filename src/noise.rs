@@ -0,0 +1,128 @@
+use crate::tuples::vector;
+use crate::tuples::Tuple;
+
+// improved Perlin noise over a permutation table shuffled from a seed
+#[derive(Debug, PartialEq, Clone)]
+pub struct Noise {
+    permutation: Vec<usize>,
+}
+
+pub fn noise(seed: u32) -> Noise {
+    let mut table: Vec<usize> = (0..256).collect();
+    // xorshift keeps the shuffle reproducible without pulling in a rng crate
+    let mut state = seed.wrapping_mul(2_654_435_761) | 1;
+    for i in (1..table.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        table.swap(i, state as usize % (i + 1));
+    }
+    let permutation = table.iter().chain(table.iter()).cloned().collect();
+    Noise { permutation }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+impl Noise {
+    // noise value in range -1..1, zero at the integer lattice
+    pub fn at(&self, point: &Tuple) -> f64 {
+        let p = &self.permutation;
+        let (xf, yf, zf) = (point.x.floor(), point.y.floor(), point.z.floor());
+        let xi = (xf as i64 & 255) as usize;
+        let yi = (yf as i64 & 255) as usize;
+        let zi = (zf as i64 & 255) as usize;
+        let (x, y, z) = (point.x - xf, point.y - yf, point.z - zf);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let a = p[xi] + yi;
+        let aa = p[a] + zi;
+        let ab = p[a + 1] + zi;
+        let b = p[xi + 1] + yi;
+        let ba = p[b] + zi;
+        let bb = p[b + 1] + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1., y, z)),
+                lerp(u, grad(p[ab], x, y - 1., z), grad(p[bb], x - 1., y - 1., z)),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.),
+                    grad(p[ba + 1], x - 1., y, z - 1.),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1., z - 1.),
+                    grad(p[bb + 1], x - 1., y - 1., z - 1.),
+                ),
+            ),
+        )
+    }
+
+    // three decorrelated noise samples, used to jitter lookup points
+    pub fn vector_at(&self, point: &Tuple) -> Tuple {
+        vector(
+            self.at(point),
+            self.at(&(point + vector(31.416, 47.853, 12.679))),
+            self.at(&(point + vector(-27.183, 19.371, 71.093))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use crate::tuples::point;
+    use hamcrest2::prelude::*;
+
+    #[test]
+    fn noise_vanishes_on_the_integer_lattice() {
+        let n = noise(7);
+
+        assert_that!(n.at(&point(0., 0., 0.)), eq(0.));
+        assert_that!(n.at(&point(3., -2., 5.)), eq(0.));
+    }
+
+    #[test]
+    fn noise_stays_within_unit_range() {
+        let n = noise(7);
+
+        for i in 0..1000 {
+            let t = i as f64 * 0.137;
+            let value = n.at(&point(t, t * 0.7 - 3., 11. - t * 1.3));
+            assert_that!(value, is(greater_than_or_equal_to(-1.)));
+            assert_that!(value, is(less_than_or_equal_to(1.)));
+        }
+    }
+
+    #[test]
+    fn different_seeds_shuffle_different_tables() {
+        assert_that!(noise(1), not(eq(noise(2))));
+    }
+}
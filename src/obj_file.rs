@@ -1,105 +1,362 @@
 use crate::groups::group_with_children;
 use crate::groups::Group;
+use crate::materials::{material, Material};
 use crate::shapes::SyncShape;
 use crate::triangles::triangle;
 use crate::triangles::Triangle;
 use crate::tuples::point;
-use crate::tuples::Tuple;
+use crate::tuples::{color, Tuple};
 use std::collections::HashMap;
-use std::num::ParseFloatError;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Cursor};
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
 use std::sync::Arc;
 
 pub struct Parsed {
     vertices: Vec<Tuple>,
-    groups: HashMap<String, Arc<Group>>,
+    tex_coords: Vec<(f64, f64)>,
+    // every triangle in the order of the file, shared with the groups
+    faces: Vec<Arc<Triangle>>,
+    // named groups in the order the file declares them, the unnamed default group first
+    groups: Vec<(String, Arc<Group>)>,
 }
 
 impl Parsed {
+    #[cfg(test)]
     fn default_group(&self) -> Arc<Group> {
-        self.groups.get("").unwrap().clone()
+        self.group("").unwrap().clone()
+    }
+    pub fn faces(&self) -> &[Arc<Triangle>] {
+        &self.faces
+    }
+    pub fn group(&self, name: &str) -> Option<&Arc<Group>> {
+        self.groups.iter().find(|(n, _)| n == name).map(|(_, g)| g)
     }
     pub fn to_group(&self) -> Group {
         group_with_children(
             self.groups
-                .values()
-                .map(|v| {
+                .iter()
+                .map(|(_, v)| {
                     let s: Arc<SyncShape> = v.clone();
                     s
                 })
                 .collect(),
         )
     }
-    fn add_group(mut self, n: &str, g: Group) -> Parsed {
-        self.groups.insert(n.to_string(), Arc::new(g));
-        self
+    // a name declared again replaces its group but keeps the earlier position
+    fn add_group(&mut self, n: &str, g: Group) {
+        let g = Arc::new(g);
+        match self.groups.iter_mut().find(|(name, _)| name == n) {
+            Some(entry) => entry.1 = g,
+            None => self.groups.push((n.to_string(), g)),
+        }
     }
 }
 
 pub fn parse_obj(text: &str) -> Parsed {
-    let empty_result = Parsed {
-        vertices: vec![],
-        groups: HashMap::default(),
-    };
-    let (parsed, last_name, last_group) = text.lines().fold(
-        (empty_result, "", vec![]),
-        |(mut parsed, mut last_name, mut last_group), line| {
-            if let Ok(vertex) = parse_vertex(line) {
-                parsed.vertices.push(vertex);
-            } else if let Some(polygon) = parse_polygon(line) {
-                for triangle in fan_triangulation(&polygon, &parsed.vertices) {
-                    let t: Arc<SyncShape> = Arc::new(triangle);
-                    last_group.push(t);
+    parse_obj_reader(Cursor::new(text)).expect("text in memory is always readable")
+}
+
+// reads one line at a time so only the parsed mesh is kept in memory
+pub fn parse_obj_reader<R: BufRead>(reader: R) -> io::Result<Parsed> {
+    parse_obj_with_materials(reader, &Materials::new())
+}
+
+// faces after `usemtl <name>` get the named material, unknown names leave the default one
+pub fn parse_obj_with_materials<R: BufRead>(
+    reader: R,
+    materials: &Materials,
+) -> io::Result<Parsed> {
+    let mut builder = Builder::new(materials);
+    for (i, line) in reader.lines().enumerate() {
+        // records that cannot be read are skipped
+        builder.read_line(&line?, i + 1).ok();
+    }
+    Ok(builder.finish())
+}
+
+// the state between the lines of a file, faces are collected in the last group until the next
+// `g` record
+struct Builder<'a> {
+    parsed: Parsed,
+    last_name: String,
+    last_group: Vec<Arc<SyncShape>>,
+    materials: &'a Materials,
+    current_material: Option<&'a Material>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(materials: &'a Materials) -> Builder<'a> {
+        Builder {
+            parsed: Parsed {
+                vertices: vec![],
+                tex_coords: vec![],
+                faces: vec![],
+                groups: vec![],
+            },
+            last_name: String::new(),
+            last_group: vec![],
+            materials,
+            current_material: None,
+        }
+    }
+
+    // reads whatever the line gets right and reports what it had to skip
+    fn read_line(&mut self, text: &str, line: usize) -> Result<(), ObjError> {
+        let parsed = &mut self.parsed;
+        if let Some(vertex) = parse_vertex(text) {
+            parsed.vertices.push(vertex);
+        } else if record(text, "v").is_some() {
+            return Err(ObjError::BadFloat { line });
+        } else if let Some(uv) = parse_tex_coord(text) {
+            parsed.tex_coords.push(uv);
+        } else if let Some(polygon) =
+            parse_polygon(text, parsed.vertices.len(), parsed.tex_coords.len())
+        {
+            for mut triangle in fan_triangulation(&polygon, &parsed.vertices, &parsed.tex_coords) {
+                if let Some(m) = self.current_material {
+                    triangle.material = m.clone();
                 }
-            } else if let Some(group_name) = parse_group(line) {
-                parsed = parsed.add_group(last_name, group_with_children(last_group));
-                last_group = vec![];
-                last_name = group_name;
+                let triangle = Arc::new(triangle);
+                parsed.faces.push(triangle.clone());
+                self.last_group.push(triangle);
+            }
+            let references = record(text, "f").map_or(0, Iterator::count);
+            if references < 3 {
+                return Err(ObjError::TooFewVertices { line });
+            }
+            if polygon.len() < references {
+                return Err(ObjError::IndexOutOfRange { line });
             }
-            (parsed, last_name, last_group)
-        },
-    );
-    parsed.add_group(last_name, group_with_children(last_group))
+        } else if let Some(group_name) = parse_group(text) {
+            let group = group_with_children(std::mem::take(&mut self.last_group));
+            let last_name = std::mem::replace(&mut self.last_name, group_name);
+            parsed.add_group(&last_name, group);
+        } else if let Some(mut name) = record(text, "usemtl") {
+            self.current_material = name.next().and_then(|n| self.materials.get(n));
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Parsed {
+        let last_group = group_with_children(self.last_group);
+        self.parsed.add_group(&self.last_name, last_group);
+        self.parsed
+    }
 }
 
-fn parse_vertex(line: &str) -> Result<Tuple, ParseFloatError> {
-    let nums: Vec<&str> = line.trim_start_matches("v ").trim().split(' ').collect();
-    let x = nums[0].parse::<f64>()?;
-    let y = nums[1].parse::<f64>()?;
-    let z = nums[2].parse::<f64>()?;
-    Ok(point(x, y, z))
+pub type Materials = HashMap<String, Material>;
+
+// the materials of a .mtl library by their `newmtl` names, colors map to the book's material
+// model with strengths taken from the brightest channel
+pub fn parse_mtl(text: &str) -> Materials {
+    let mut materials = Materials::new();
+    let mut current: Option<(String, Material)> = None;
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        let keyword = words.next();
+        let values: Vec<f64> = words.clone().filter_map(|n| n.parse().ok()).collect();
+        if keyword == Some("newmtl") {
+            materials.extend(current.take());
+            current = Some((words.collect::<Vec<_>>().join(" "), material()));
+            continue;
+        }
+        let m = match &mut current {
+            Some((_, m)) => m,
+            None => continue,
+        };
+        match (keyword, values.as_slice()) {
+            (Some("Kd"), [r, g, b]) => m.color = color(*r, *g, *b),
+            (Some("Ka"), [r, g, b]) => m.ambient = r.max(*g).max(*b),
+            (Some("Ks"), [r, g, b]) => {
+                m.specular = r.max(*g).max(*b);
+                if r != g || g != b {
+                    m.specular_color = Some(color(*r, *g, *b) / m.specular);
+                }
+            }
+            (Some("Ns"), [n]) => m.shininess = *n,
+            (Some("d"), [d]) => m.transparency = 1. - d,
+            (Some("Ni"), [n]) => m.refractive_index = *n,
+            _ => {}
+        }
+    }
+    materials.extend(current);
+    materials
 }
 
-fn parse_group(line: &str) -> Option<&str> {
-    if !line.starts_with("g ") {
-        None
-    } else {
-        Some(line.trim_start_matches("g "))
+// the libraries named by `mtllib` lines, already resolved against the file that named them
+fn load_material_libraries(paths: &[PathBuf]) -> Result<Materials, IncludeError> {
+    let mut materials = Materials::new();
+    for path in paths {
+        let library = fs::read_to_string(path).map_err(|e| IncludeError::Io(path.clone(), e))?;
+        materials.extend(parse_mtl(&library));
+    }
+    Ok(materials)
+}
+
+// the 1-based line of the first record that could not be read
+#[derive(Debug, PartialEq)]
+pub enum ObjError {
+    BadFloat { line: usize },
+    IndexOutOfRange { line: usize },
+    TooFewVertices { line: usize },
+}
+
+// same as parse_obj but fails on vertices and faces that parse_obj would skip or misread
+pub fn parse_obj_checked(text: &str) -> Result<Parsed, ObjError> {
+    let materials = Materials::new();
+    let mut builder = Builder::new(&materials);
+    for (i, line) in text.lines().enumerate() {
+        builder.read_line(line, i + 1)?;
+    }
+    Ok(builder.finish())
+}
+
+#[derive(Debug)]
+pub enum IncludeError {
+    Io(PathBuf, io::Error),
+    Cycle(PathBuf),
+}
+
+// reads the file and everything it includes with `call <file>` statements, paths are relative to
+// the including file, included text is spliced in place so indices keep counting across files
+pub fn resolve_includes(path: &Path) -> Result<String, IncludeError> {
+    resolve_includes_from(path, &mut vec![], &mut vec![])
+}
+
+pub fn load_obj(path: &Path) -> Result<Parsed, IncludeError> {
+    let mut libraries = vec![];
+    let text = resolve_includes_from(path, &mut vec![], &mut libraries)?;
+    let materials = load_material_libraries(&libraries)?;
+    parse_obj_with_materials(Cursor::new(text), &materials)
+        .map_err(|e| IncludeError::Io(path.to_path_buf(), e))
+}
+
+// also collects the material libraries of every file, relative to the file that names them
+fn resolve_includes_from(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    libraries: &mut Vec<PathBuf>,
+) -> Result<String, IncludeError> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| IncludeError::Io(path.to_path_buf(), e))?;
+    if including.contains(&canonical) {
+        return Err(IncludeError::Cycle(path.to_path_buf()));
+    }
+    let text =
+        fs::read_to_string(&canonical).map_err(|e| IncludeError::Io(path.to_path_buf(), e))?;
+    including.push(canonical);
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut merged = String::new();
+    for line in text.lines() {
+        if let Some(file) = record(line, "call") {
+            let included = base.join(file.collect::<Vec<_>>().join(" "));
+            merged.push_str(&resolve_includes_from(&included, including, libraries)?);
+            continue;
+        }
+        if let Some(files) = record(line, "mtllib") {
+            libraries.extend(files.map(|file| base.join(file)));
+        }
+        merged.push_str(line);
+        merged.push('\n');
     }
+    including.pop();
+    Ok(merged)
 }
 
-fn parse_polygon(line: &str) -> Option<Vec<usize>> {
-    if !line.starts_with("f ") {
+// the words after the keyword of a `keyword ...` record, any whitespace separates them so tabs
+// and the `\r` of windows line endings are handled too
+fn record<'a>(line: &'a str, keyword: &str) -> Option<SplitWhitespace<'a>> {
+    let mut words = line.split_whitespace();
+    if words.next()? == keyword {
+        Some(words)
+    } else {
         None
+    }
+}
+
+fn parse_vertex(line: &str) -> Option<Tuple> {
+    let nums = record(line, "v")?
+        .take(3)
+        .map(|n| n.parse::<f64>().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    match nums[..] {
+        [x, y, z] => Some(point(x, y, z)),
+        _ => None,
+    }
+}
+
+fn parse_group(line: &str) -> Option<String> {
+    Some(record(line, "g")?.collect::<Vec<_>>().join(" "))
+}
+
+// `vt u v`, an optional third coordinate is ignored
+fn parse_tex_coord(line: &str) -> Option<(f64, f64)> {
+    let mut nums = record(line, "vt")?;
+    let u = nums.next()?.parse().ok()?;
+    let v = nums.next()?.parse().ok()?;
+    Some((u, v))
+}
+
+// each corner is a vertex index and, for `v/vt` references, a texture coordinate index
+fn parse_polygon(
+    line: &str,
+    vertex_count: usize,
+    tex_coord_count: usize,
+) -> Option<Vec<(usize, Option<usize>)>> {
+    let corners = record(line, "f")?
+        .filter_map(|n| {
+            let mut refs = n.split('/');
+            let vertex =
+                resolve_index(refs.next().unwrap(), vertex_count).filter(|&n| n <= vertex_count)?;
+            let tex_coord = refs.next().and_then(|r| resolve_index(r, tex_coord_count));
+            Some((vertex, tex_coord))
+        })
+        .collect();
+    Some(corners)
+}
+
+// turns a reference into 1-based index, negative references count back from the last of count
+// elements defined so far
+fn resolve_index(reference: &str, count: usize) -> Option<usize> {
+    let n = reference.parse::<isize>().ok()?;
+    if n < 0 {
+        let back = n.unsigned_abs();
+        if back <= count {
+            Some(count + 1 - back)
+        } else {
+            None
+        }
+    } else if n > 0 {
+        Some(n as usize)
     } else {
-        Some(
-            line.trim_start_matches("f ")
-                .split(' ')
-                .filter_map(|n| n.split('/').next().unwrap().parse::<usize>().ok())
-                .collect(),
-        )
+        None
     }
 }
 
-fn fan_triangulation(polygon: &[usize], vertices: &[Tuple]) -> Vec<Triangle> {
+fn fan_triangulation(
+    polygon: &[(usize, Option<usize>)],
+    vertices: &[Tuple],
+    tex_coords: &[(f64, f64)],
+) -> Vec<Triangle> {
     let mut pairs = polygon.windows(2);
-    if let &[a, _b] = pairs.next().unwrap() {
+    if let Some(&[a, _b]) = pairs.next() {
         pairs
             .map(|bc| {
-                triangle(
-                    vertices[a - 1].clone(),
-                    vertices[bc[0] - 1].clone(),
-                    vertices[bc[1] - 1].clone(),
-                )
+                let (b, c) = (bc[0], bc[1]);
+                let mut t = triangle(
+                    vertices[a.0 - 1].clone(),
+                    vertices[b.0 - 1].clone(),
+                    vertices[c.0 - 1].clone(),
+                );
+                let uv = |i: Option<usize>| i.and_then(|i| tex_coords.get(i - 1)).cloned();
+                if let (Some(uv1), Some(uv2), Some(uv3)) = (uv(a.1), uv(b.1), uv(c.1)) {
+                    t.set_uvs([uv1, uv2, uv3]);
+                }
+                t
             })
             .collect()
     } else {
@@ -110,6 +367,8 @@ fn fan_triangulation(polygon: &[usize], vertices: &[Tuple]) -> Vec<Triangle> {
 #[cfg(test)]
 mod spec {
     use super::*;
+    use crate::intersections::{intersection_with_uv, EPSILON};
+    use crate::shapes::Shape;
     use hamcrest2::prelude::*;
 
     #[test]
@@ -253,8 +512,8 @@ f 1 3 4
         "#;
 
         let parsed = parse_obj(file);
-        let g1 = parsed.groups.get("FirstGroup").unwrap();
-        let g2 = parsed.groups.get("SecondGroup").unwrap();
+        let g1 = parsed.group("FirstGroup").unwrap();
+        let g2 = parsed.group("SecondGroup").unwrap();
 
         let ex1: Arc<SyncShape> = Arc::new(triangle(
             parsed.vertices[0].clone(),
@@ -285,8 +544,8 @@ f 1 3 4
         "#;
 
         let parsed = parse_obj(file);
-        let g1 = parsed.groups.get("FirstGroup").unwrap();
-        let g2 = parsed.groups.get("SecondGroup").unwrap();
+        let g1 = parsed.group("FirstGroup").unwrap();
+        let g2 = parsed.group("SecondGroup").unwrap();
         let g = parsed.to_group();
 
         let ex1: Arc<SyncShape> = g1.clone();
@@ -295,4 +554,336 @@ f 1 3 4
         assert_that!(&g.children[0..], contains(ex1));
         assert_that!(&g.children[0..], contains(ex2));
     }
+
+    #[test]
+    fn faces_with_negative_indices() {
+        let file = r#"
+v -1 1 0
+v -1 0 0
+v 1 0 0
+f -3 -2 -1
+v 1 1 0
+f -4/1/1 -2//2 -1
+        "#;
+
+        let parsed = parse_obj(file);
+        let g = parsed.default_group();
+
+        let ex1: Arc<SyncShape> = Arc::new(triangle(
+            parsed.vertices[0].clone(),
+            parsed.vertices[1].clone(),
+            parsed.vertices[2].clone(),
+        ));
+        let ex2: Arc<SyncShape> = Arc::new(triangle(
+            parsed.vertices[0].clone(),
+            parsed.vertices[2].clone(),
+            parsed.vertices[3].clone(),
+        ));
+
+        assert_eq!(g.children.len(), 2);
+        assert_that!(&g.children[0..], contains(ex1));
+        assert_that!(&g.children[0..], contains(ex2));
+    }
+
+    #[test]
+    fn groups_keep_the_order_of_the_file() {
+        let file = r#"
+v -1 1 0
+v -1 0 0
+v 1 0 0
+v 2 1 0
+v 3 1 0
+
+g A
+f 1 2 3
+g B
+f 4 2 3
+g C
+f 5 2 3
+        "#;
+
+        let parsed = parse_obj(file);
+        let g = parsed.to_group();
+
+        let names: Vec<&str> = parsed.groups.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["", "A", "B", "C"]);
+        for (i, name) in ["A", "B", "C"].iter().enumerate() {
+            let expected: Arc<SyncShape> = parsed.group(name).unwrap().clone();
+            assert!(Arc::ptr_eq(&g.children[i + 1], &expected));
+            let face: Arc<SyncShape> = parsed.faces()[i].clone();
+            assert!(Arc::ptr_eq(&parsed.group(name).unwrap().children[0], &face));
+            assert_eq!(parsed.faces()[i].p1(), &parsed.vertices[[0, 3, 4][i]]);
+        }
+    }
+
+    #[test]
+    fn checked_parsing_accepts_a_valid_file() {
+        let file = "v -1 1 0\nv -1 0 0\nv 1 0 0\nf 1 2 -1\n";
+
+        let parsed = parse_obj_checked(file).unwrap();
+
+        assert_eq!(parsed.vertices.len(), 3);
+        assert_eq!(parsed.default_group().children.len(), 1);
+    }
+
+    #[test]
+    fn checked_parsing_reports_faces_past_the_vertices() {
+        let file = "v -1 1 0\nv -1 0 0\nv 1 0 0\n\nf 1 2 99\n";
+
+        assert_eq!(
+            parse_obj_checked(file).err(),
+            Some(ObjError::IndexOutOfRange { line: 5 })
+        );
+    }
+
+    #[test]
+    fn references_past_the_vertices_are_skipped() {
+        let file = "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 99 3 4\n";
+
+        let parsed = parse_obj(file);
+
+        assert_eq!(parsed.faces().len(), 2);
+        assert_eq!(parsed.faces()[1].p3(), &point(1., 1., 0.));
+    }
+
+    #[test]
+    fn checked_parsing_reports_malformed_records() {
+        assert_eq!(
+            parse_obj_checked("v 1 x 0\n").err(),
+            Some(ObjError::BadFloat { line: 1 })
+        );
+        assert_eq!(
+            parse_obj_checked("v 1 2\n").err(),
+            Some(ObjError::BadFloat { line: 1 })
+        );
+        assert_eq!(
+            parse_obj_checked("v 1 1 0\nv 1 0 0\nf 1 2\n").err(),
+            Some(ObjError::TooFewVertices { line: 3 })
+        );
+    }
+
+    #[test]
+    fn parsing_from_a_reader() {
+        let file = "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\ng A\nf 1 2 3\ng B\nf 1 3 4\n";
+
+        let from_text = parse_obj(file);
+        let from_reader = parse_obj_reader(io::BufReader::new(file.as_bytes())).unwrap();
+
+        assert_eq!(from_reader.vertices, from_text.vertices);
+        assert_eq!(from_reader.faces.len(), 2);
+        assert_eq!(from_reader.faces.len(), from_text.faces.len());
+        for (x, y) in from_reader.faces.iter().zip(from_text.faces.iter()) {
+            assert_eq!((x.p1(), x.p2(), x.p3()), (y.p1(), y.p2(), y.p3()));
+        }
+        let names = |p: &Parsed| {
+            p.groups
+                .iter()
+                .map(|(n, g)| (n.clone(), g.children.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&from_reader), names(&from_text));
+    }
+
+    #[test]
+    fn a_reader_error_is_reported() {
+        let bytes: &[u8] = b"v 1 0 0\n\xff\n";
+
+        let result = parse_obj_reader(bytes);
+
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+    }
+
+    #[test]
+    fn records_separated_by_tabs_and_windows_line_endings() {
+        let file = "v\t-1  0.5\t2\r\nv 1 0 0\r\nv 0 1 0\r\ng\tFirst\r\nf\t1 2  3\r\n";
+
+        let parsed = parse_obj(file);
+
+        assert_eq!(parsed.vertices[0], point(-1., 0.5, 2.));
+        assert_eq!(parsed.vertices.len(), 3);
+        assert_eq!(parsed.group("First").unwrap().children.len(), 1);
+        let lone_cr = parse_obj("v 1 2 3\r");
+        assert_eq!(lone_cr.vertices, vec![point(1., 2., 3.)]);
+    }
+
+    #[test]
+    fn texture_coordinate_records() {
+        let file = "vt 0 0\nvt 0.5 1 0\nvt 1 0.25\n";
+
+        let parsed = parse_obj(file);
+
+        assert_eq!(parsed.tex_coords, vec![(0., 0.), (0.5, 1.), (1., 0.25)]);
+    }
+
+    #[test]
+    fn faces_carry_their_texture_coordinates() {
+        let file = r#"
+v 0 1 0
+v -1 0 0
+v 1 0 0
+vt 0.5 1
+vt 0 0
+vt 1 0
+        "#;
+
+        let parsed = parse_obj(file);
+        let polygon = parse_polygon("f 1/1 2/2/1 3/3", 3, 3).unwrap();
+        let t =
+            Arc::new(fan_triangulation(&polygon, &parsed.vertices, &parsed.tex_coords).remove(0));
+        let i = intersection_with_uv(1., t.clone(), 1. / 3., 1. / 3.);
+
+        let (u, v) = t.uv_at(&i).unwrap();
+        assert!((u - 0.5).abs() < EPSILON);
+        assert!((v - 1. / 3.).abs() < EPSILON);
+    }
+
+    const MATERIALS: &str = "
+newmtl red
+Kd 1 0 0
+Ka 0.2 0.2 0.2
+Ks 0.5 0.5 0.5
+Ns 50
+
+newmtl glass
+Kd 0.9 0.9 1
+d 0.25
+Ni 1.5
+";
+
+    #[test]
+    fn reading_a_material_library() {
+        let materials = parse_mtl(MATERIALS);
+
+        let red = &materials["red"];
+        assert_eq!(red.color, color(1., 0., 0.));
+        assert_eq!(red.ambient, 0.2);
+        assert_eq!(red.specular, 0.5);
+        assert_eq!(red.specular_color, None);
+        assert_eq!(red.shininess, 50.);
+        let glass = &materials["glass"];
+        assert_eq!(glass.color, color(0.9, 0.9, 1.));
+        assert_eq!(glass.transparency, 0.75);
+        assert_eq!(glass.refractive_index, 1.5);
+    }
+
+    #[test]
+    fn faces_use_the_last_named_material() {
+        let file = r#"
+mtllib scene.mtl
+v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+
+f 1 2 3
+g Red
+usemtl red
+f 1 2 3
+g Glass
+usemtl glass
+f 1 3 4
+f 1 2 4
+        "#;
+
+        let parsed = parse_obj_with_materials(file.as_bytes(), &parse_mtl(MATERIALS)).unwrap();
+
+        let colors = |name| {
+            let g: &Arc<Group> = parsed.group(name).unwrap();
+            g.children
+                .iter()
+                .map(|t| t.material().color.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(colors(""), vec![material().color]);
+        assert_eq!(colors("Red"), vec![color(1., 0., 0.)]);
+        assert_eq!(
+            colors("Glass"),
+            vec![color(0.9, 0.9, 1.), color(0.9, 0.9, 1.)]
+        );
+    }
+
+    #[test]
+    fn loading_the_material_libraries_of_a_file() {
+        let dir = scratch_dir("mtllib");
+        fs::write(dir.join("scene.mtl"), MATERIALS).unwrap();
+        fs::write(
+            dir.join("scene.obj"),
+            "mtllib scene.mtl\nv -1 1 0\nv -1 0 0\nv 1 0 0\nusemtl red\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let parsed = load_obj(&dir.join("scene.obj")).unwrap();
+
+        let t = &parsed.default_group().children[0];
+        assert_eq!(t.material().color, color(1., 0., 0.));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn an_included_file_names_materials_next_to_itself() {
+        let dir = scratch_dir("nested-mtllib");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("mesh.mtl"), MATERIALS).unwrap();
+        fs::write(
+            dir.join("sub").join("mesh.obj"),
+            "mtllib mesh.mtl\nv -1 1 0\nv -1 0 0\nv 1 0 0\nusemtl red\nf 1 2 3\n",
+        )
+        .unwrap();
+        fs::write(dir.join("scene.obj"), "\tcall sub/mesh.obj\n").unwrap();
+
+        let parsed = load_obj(&dir.join("scene.obj")).unwrap();
+
+        let t = &parsed.default_group().children[0];
+        assert_eq!(t.material().color, color(1., 0., 0.));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obj_file-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn including_a_second_file() {
+        let dir = scratch_dir("include");
+        fs::write(dir.join("leg.obj"), "v 1 1 0\nf -3 -1 -2\n").unwrap();
+        fs::write(
+            dir.join("scene.obj"),
+            "v -1 1 0\nv -1 0 0\nv 1 0 0\nf 1 2 3\ncall leg.obj\n",
+        )
+        .unwrap();
+
+        let parsed = load_obj(&dir.join("scene.obj")).unwrap();
+        let g = parsed.default_group();
+
+        assert_eq!(parsed.vertices.len(), 4);
+        assert_eq!(g.children.len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn including_itself_is_a_cycle() {
+        let dir = scratch_dir("cycle");
+        fs::write(dir.join("loop.obj"), "v 0 0 0\ncall loop.obj\n").unwrap();
+
+        let result = resolve_includes(&dir.join("loop.obj"));
+
+        assert!(matches!(result, Err(IncludeError::Cycle(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn including_a_missing_file() {
+        let dir = scratch_dir("missing");
+        fs::write(dir.join("scene.obj"), "call nowhere.obj\n").unwrap();
+
+        let result = resolve_includes(&dir.join("scene.obj"));
+
+        assert!(matches!(result, Err(IncludeError::Io(_, _))));
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
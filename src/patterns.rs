@@ -1,8 +1,12 @@
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
+use crate::noise::noise;
+use crate::noise::Noise;
 use crate::shapes::SyncShape;
 use crate::tuples::Color;
 use crate::tuples::Tuple;
+#[cfg(feature = "serde")]
+use crate::uv::{texture_map, UvCheckers, UvMapping};
 use std::sync::Arc;
 
 pub type SyncPattern = dyn Pattern + Sync + Send;
@@ -12,11 +16,25 @@ pub trait Pattern {
     fn set_invtransform(&mut self, invtransform: Matrix);
 
     fn at(&self, point: &Tuple) -> Color;
+    // the built-in patterns describe themselves for saving, custom ones are left out
+    #[cfg(feature = "serde")]
+    fn data(&self) -> Option<PatternData> {
+        None
+    }
     fn at_shape(&self, shape: Arc<SyncShape>, world_point: &Tuple) -> Color {
         let shape_point = shape.world_to_object(world_point);
         let pattern_point = self.invtransform() * &shape_point;
         self.at(&pattern_point)
     }
+    // uv patterns use the texture coordinates of the surface instead of mapping the point
+    fn at_surface(
+        &self,
+        shape: Arc<SyncShape>,
+        world_point: &Tuple,
+        _uv: Option<(f64, f64)>,
+    ) -> Color {
+        self.at_shape(shape, world_point)
+    }
 }
 
 impl std::fmt::Debug for SyncPattern {
@@ -31,10 +49,54 @@ impl PartialEq<SyncPattern> for SyncPattern {
     }
 }
 
+// one side of a two-tone pattern, either a plain color or another pattern
+#[derive(Clone, Debug)]
+pub enum Paint {
+    Color(Color),
+    Pattern(Arc<SyncPattern>),
+}
+
+impl Paint {
+    // the point is in the parent pattern space, a nested pattern applies its own transformation
+    fn at(&self, point: &Tuple) -> Color {
+        match self {
+            Paint::Color(c) => c.clone(),
+            Paint::Pattern(p) => p.at(&(p.invtransform() * point)),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn data(&self) -> Option<PaintData> {
+        match self {
+            Paint::Color(c) => Some(PaintData::Color(c.clone())),
+            Paint::Pattern(p) => p.data().map(|d| PaintData::Pattern(Box::new(d))),
+        }
+    }
+}
+
+impl PartialEq for Paint {
+    fn eq(&self, other: &Paint) -> bool {
+        match (self, other) {
+            (Paint::Color(a), Paint::Color(b)) => a == b,
+            (Paint::Pattern(a), Paint::Pattern(b)) => a.eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<Color> for Paint {
+    fn eq(&self, other: &Color) -> bool {
+        match self {
+            Paint::Color(c) => c == other,
+            Paint::Pattern(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Stripe {
-    a: Color,
-    b: Color,
+    a: Paint,
+    b: Paint,
     invtransform: Matrix,
 }
 
@@ -43,20 +105,35 @@ impl Pattern for Stripe {
         &self.invtransform
     }
 
+    #[cfg(feature = "serde")]
+    fn data(&self) -> Option<PatternData> {
+        Some(PatternData::Stripe {
+            a: self.a.data()?,
+            b: self.b.data()?,
+            invtransform: self.invtransform.clone(),
+        })
+    }
+
     fn set_invtransform(&mut self, invtransform: Matrix) {
         self.invtransform = invtransform;
     }
 
     fn at(&self, point: &Tuple) -> Color {
         if point.x.floor() as i32 % 2 == 0 {
-            self.a.clone()
+            self.a.at(point)
         } else {
-            self.b.clone()
+            self.b.at(point)
         }
     }
 }
 pub fn stripe_pattern(a: Color, b: Color) -> Stripe {
     let invtransform = identity_matrix();
+    let (a, b) = (Paint::Color(a), Paint::Color(b));
+    Stripe { a, b, invtransform }
+}
+pub fn stripe_pattern_nested(a: Box<SyncPattern>, b: Box<SyncPattern>) -> Stripe {
+    let invtransform = identity_matrix();
+    let (a, b) = (Paint::Pattern(Arc::from(a)), Paint::Pattern(Arc::from(b)));
     Stripe { a, b, invtransform }
 }
 
@@ -71,6 +148,15 @@ impl Pattern for Gradient {
         &self.invtransform
     }
 
+    #[cfg(feature = "serde")]
+    fn data(&self) -> Option<PatternData> {
+        Some(PatternData::Gradient {
+            a: self.a.clone(),
+            b: self.b.clone(),
+            invtransform: self.invtransform.clone(),
+        })
+    }
+
     fn set_invtransform(&mut self, invtransform: Matrix) {
         self.invtransform = invtransform;
     }
@@ -97,6 +183,15 @@ impl Pattern for Ring {
         &self.invtransform
     }
 
+    #[cfg(feature = "serde")]
+    fn data(&self) -> Option<PatternData> {
+        Some(PatternData::Ring {
+            a: self.a.clone(),
+            b: self.b.clone(),
+            invtransform: self.invtransform.clone(),
+        })
+    }
+
     fn set_invtransform(&mut self, invtransform: Matrix) {
         self.invtransform = invtransform;
     }
@@ -116,8 +211,8 @@ pub fn ring_pattern(a: Color, b: Color) -> Ring {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Checkers {
-    a: Color,
-    b: Color,
+    a: Paint,
+    b: Paint,
     invtransform: Matrix,
 }
 impl Pattern for Checkers {
@@ -125,32 +220,274 @@ impl Pattern for Checkers {
         &self.invtransform
     }
 
+    #[cfg(feature = "serde")]
+    fn data(&self) -> Option<PatternData> {
+        Some(PatternData::Checkers {
+            a: self.a.data()?,
+            b: self.b.data()?,
+            invtransform: self.invtransform.clone(),
+        })
+    }
+
     fn set_invtransform(&mut self, invtransform: Matrix) {
         self.invtransform = invtransform;
     }
 
     fn at(&self, point: &Tuple) -> Color {
         if (point.x.floor() + point.y.floor() + point.z.floor()) as i32 % 2 == 0 {
-            self.a.clone()
+            self.a.at(point)
         } else {
-            self.b.clone()
+            self.b.at(point)
         }
     }
 }
 pub fn checkers_pattern(a: Color, b: Color) -> Checkers {
     let invtransform = identity_matrix();
+    let (a, b) = (Paint::Color(a), Paint::Color(b));
+    Checkers { a, b, invtransform }
+}
+pub fn checkers_pattern_nested(a: Box<SyncPattern>, b: Box<SyncPattern>) -> Checkers {
+    let invtransform = identity_matrix();
+    let (a, b) = (Paint::Pattern(Arc::from(a)), Paint::Pattern(Arc::from(b)));
     Checkers { a, b, invtransform }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Perlin {
+    a: Color,
+    b: Color,
+    noise: Noise,
+    invtransform: Matrix,
+}
+impl Pattern for Perlin {
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.invtransform = invtransform;
+    }
+
+    fn at(&self, point: &Tuple) -> Color {
+        let distance = &self.b - &self.a;
+        let fraction = (self.noise.at(point) + 1.) / 2.;
+        &self.a + &(distance * fraction)
+    }
+}
+pub fn perlin_pattern(a: Color, b: Color, seed: u32) -> Perlin {
+    let invtransform = identity_matrix();
+    let noise = noise(seed);
+    Perlin {
+        a,
+        b,
+        noise,
+        invtransform,
+    }
+}
+
+#[derive(Debug)]
+pub struct Blend {
+    a: Box<SyncPattern>,
+    b: Box<SyncPattern>,
+    invtransform: Matrix,
+}
+impl PartialEq for Blend {
+    fn eq(&self, other: &Blend) -> bool {
+        self.invtransform == other.invtransform && self.a.eq(&other.a) && self.b.eq(&other.b)
+    }
+}
+impl Pattern for Blend {
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.invtransform = invtransform;
+    }
+
+    // the point is in blend space, every child applies its own transformation on top
+    fn at(&self, point: &Tuple) -> Color {
+        let a = self.a.at(&(self.a.invtransform() * point));
+        let b = self.b.at(&(self.b.invtransform() * point));
+        (a + b) * 0.5
+    }
+}
+pub fn blend_pattern(a: Box<SyncPattern>, b: Box<SyncPattern>) -> Blend {
+    let invtransform = identity_matrix();
+    Blend { a, b, invtransform }
+}
+
+// offsets the lookups of the wrapped pattern by noise to break up straight edges
+#[derive(Debug)]
+pub struct Perturbed {
+    pattern: Box<SyncPattern>,
+    scale: f64,
+    noise: Noise,
+    invtransform: Matrix,
+}
+impl PartialEq for Perturbed {
+    fn eq(&self, other: &Perturbed) -> bool {
+        self.invtransform == other.invtransform
+            && self.scale == other.scale
+            && self.pattern.eq(&other.pattern)
+    }
+}
+impl Pattern for Perturbed {
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.invtransform = invtransform;
+    }
+
+    fn at(&self, point: &Tuple) -> Color {
+        let jittered = point + self.noise.vector_at(point) * self.scale;
+        self.pattern.at(&(self.pattern.invtransform() * &jittered))
+    }
+}
+pub fn perturb(pattern: Box<SyncPattern>, scale: f64) -> Perturbed {
+    let invtransform = identity_matrix();
+    let noise = noise(0);
+    Perturbed {
+        pattern,
+        scale,
+        noise,
+        invtransform,
+    }
+}
+
+// the built-in patterns as plain data, tagged by their kind when saved
+#[cfg(feature = "serde")]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PatternData {
+    Stripe {
+        a: PaintData,
+        b: PaintData,
+        invtransform: Matrix,
+    },
+    Gradient {
+        a: Color,
+        b: Color,
+        invtransform: Matrix,
+    },
+    Ring {
+        a: Color,
+        b: Color,
+        invtransform: Matrix,
+    },
+    Checkers {
+        a: PaintData,
+        b: PaintData,
+        invtransform: Matrix,
+    },
+    // uv checkers mapped onto the surface
+    Uv {
+        mapping: UvMapping,
+        checkers: UvCheckers,
+        invtransform: Matrix,
+    },
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum PaintData {
+    Color(Color),
+    Pattern(Box<PatternData>),
+}
+
+#[cfg(feature = "serde")]
+impl PaintData {
+    fn build(self) -> Paint {
+        match self {
+            PaintData::Color(c) => Paint::Color(c),
+            PaintData::Pattern(p) => Paint::Pattern(Arc::from(p.build())),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl PatternData {
+    pub fn build(self) -> Box<SyncPattern> {
+        let (mut pattern, invtransform): (Box<SyncPattern>, Matrix) = match self {
+            PatternData::Stripe { a, b, invtransform } => {
+                let (a, b) = (a.build(), b.build());
+                let stripe = Stripe {
+                    a,
+                    b,
+                    invtransform: identity_matrix(),
+                };
+                (Box::new(stripe), invtransform)
+            }
+            PatternData::Gradient { a, b, invtransform } => {
+                (Box::new(gradient_pattern(a, b)), invtransform)
+            }
+            PatternData::Ring { a, b, invtransform } => {
+                (Box::new(ring_pattern(a, b)), invtransform)
+            }
+            PatternData::Checkers { a, b, invtransform } => {
+                let (a, b) = (a.build(), b.build());
+                let checkers = Checkers {
+                    a,
+                    b,
+                    invtransform: identity_matrix(),
+                };
+                (Box::new(checkers), invtransform)
+            }
+            PatternData::Uv {
+                mapping,
+                checkers,
+                invtransform,
+            } => (
+                Box::new(texture_map(Box::new(checkers), mapping.uv_fn())),
+                invtransform,
+            ),
+        };
+        pattern.set_invtransform(invtransform);
+        pattern
+    }
+}
+
+// saves the pattern of a material through its data, a custom pattern is saved as none
+#[cfg(feature = "serde")]
+pub(crate) mod saved {
+    use super::{PatternData, SyncPattern};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::Arc;
+
+    pub fn serialize<S: Serializer>(
+        pattern: &Option<Arc<SyncPattern>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        pattern
+            .as_ref()
+            .and_then(|p| p.data())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Arc<SyncPattern>>, D::Error> {
+        let data = Option::<PatternData>::deserialize(deserializer)?;
+        Ok(data.map(|d| Arc::from(d.build())))
+    }
+}
+
 #[cfg(test)]
 pub mod spec {
     use super::*;
+    use crate::shapes::Shape;
     use crate::spheres::sphere;
+    use crate::transformations::rotation_y;
     use crate::transformations::scaling;
     use crate::transformations::translation;
     use crate::tuples::color;
     use crate::tuples::point;
+    use crate::tuples::vector;
     use crate::tuples::Color;
+    use std::f64::consts::PI;
 
     fn black() -> Color {
         color(0., 0., 0.)
@@ -220,7 +557,7 @@ pub mod spec {
     #[test]
     fn a_pattern_with_an_object_transformation() {
         let mut object = sphere();
-        object.invtransform = scaling(2., 2., 2.).inverse();
+        object.set_transform(scaling(2., 2., 2.));
         let pattern = test_pattern();
 
         let c = pattern.at_shape(Arc::new(object), &point(2., 3., 4.));
@@ -242,7 +579,7 @@ pub mod spec {
     #[test]
     fn a_pattern_with_both_an_object_and_a_pattern_transformation() {
         let mut object = sphere();
-        object.invtransform = scaling(2., 2., 2.).inverse();
+        object.set_transform(scaling(2., 2., 2.));
         let mut pattern = test_pattern();
         pattern.set_invtransform(translation(0.5, 1., 1.5).inverse());
 
@@ -297,4 +634,115 @@ pub mod spec {
         assert_eq!(pattern.at(&point(0., 0., 0.99)), white());
         assert_eq!(pattern.at(&point(0., 0., 1.01)), black());
     }
+
+    #[test]
+    fn perlin_noise_is_continuous() {
+        let pattern = perlin_pattern(white(), black(), 42);
+
+        for i in 0..100 {
+            let t = i as f64 * 0.31;
+            let p = point(t, 0.5 * t, 1.7 - t);
+            let a = pattern.at(&p);
+            let b = pattern.at(&(&p + vector(0.001, 0.001, 0.001)));
+            assert!((a.red - b.red).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn perlin_noise_is_deterministic_for_a_seed() {
+        let p1 = perlin_pattern(white(), black(), 42);
+        let p2 = perlin_pattern(white(), black(), 42);
+
+        for i in 0..100 {
+            let t = i as f64 * 0.29;
+            let p = point(t, 2. - t, 0.3 * t);
+            assert_eq!(p1.at(&p), p2.at(&p));
+        }
+    }
+
+    #[test]
+    fn blending_two_solid_colors_yields_their_midpoint() {
+        let pattern = blend_pattern(
+            Box::new(stripe_pattern(white(), white())),
+            Box::new(stripe_pattern(color(1., 0., 0.), color(1., 0., 0.))),
+        );
+
+        assert_eq!(pattern.at(&point(0., 0., 0.)), color(1., 0.5, 0.5));
+        assert_eq!(pattern.at(&point(1.5, 2., 3.)), color(1., 0.5, 0.5));
+    }
+
+    #[test]
+    fn blending_two_stripes_averages_their_selected_colors() {
+        let mut across = stripe_pattern(white(), black());
+        across.set_invtransform(rotation_y(PI / 2.).inverse());
+        let pattern = blend_pattern(Box::new(stripe_pattern(white(), black())), Box::new(across));
+
+        assert_eq!(pattern.at(&point(0.5, 0., -0.5)), white());
+        assert_eq!(pattern.at(&point(1.5, 0., -0.5)), color(0.5, 0.5, 0.5));
+        assert_eq!(pattern.at(&point(1.5, 0., 0.5)), black());
+    }
+
+    #[test]
+    fn checkers_select_between_nested_patterns() {
+        let mut stripes = stripe_pattern(white(), black());
+        stripes.set_invtransform(scaling(0.25, 0.25, 0.25).inverse());
+        let solid = stripe_pattern(color(1., 0., 0.), color(1., 0., 0.));
+        let pattern = checkers_pattern_nested(Box::new(stripes), Box::new(solid));
+
+        assert_eq!(pattern.at(&point(0.1, 0., 0.)), white());
+        assert_eq!(pattern.at(&point(0.3, 0., 0.)), black());
+        assert_eq!(pattern.at(&point(1.1, 0., 0.)), color(1., 0., 0.));
+        assert_eq!(pattern.at(&point(1.3, 0., 0.)), color(1., 0., 0.));
+    }
+
+    #[test]
+    fn stripes_select_between_nested_patterns() {
+        let pattern = stripe_pattern_nested(
+            Box::new(gradient_pattern(white(), black())),
+            Box::new(ring_pattern(white(), black())),
+        );
+
+        assert_eq!(pattern.at(&point(0.5, 0., 0.)), color(0.5, 0.5, 0.5));
+        assert_eq!(pattern.at(&point(1.5, 0., 0.)), black());
+    }
+
+    #[test]
+    fn a_copy_of_a_nested_pattern_shares_its_children() {
+        let pattern = checkers_pattern_nested(
+            Box::new(stripe_pattern(white(), black())),
+            Box::new(ring_pattern(white(), black())),
+        );
+
+        let copy = pattern.clone();
+
+        assert_eq!(copy, pattern);
+        assert_eq!(
+            copy.at(&point(1.5, 0., 0.)),
+            pattern.at(&point(1.5, 0., 0.))
+        );
+    }
+
+    #[test]
+    fn perturbing_by_zero_keeps_the_pattern() {
+        let pattern = perturb(Box::new(stripe_pattern(white(), black())), 0.);
+        let stripes = stripe_pattern(white(), black());
+
+        for i in 0..100 {
+            let t = i as f64 * 0.173;
+            let p = point(t - 8., 0.4 * t, 3. - t);
+            assert_eq!(pattern.at(&p), stripes.at(&p));
+        }
+    }
+
+    #[test]
+    fn perturbing_can_flip_colors_near_a_stripe_boundary() {
+        let pattern = perturb(Box::new(stripe_pattern(white(), black())), 0.5);
+        let stripes = stripe_pattern(white(), black());
+
+        let flipped = (0..100)
+            .map(|i| point(0.95, i as f64 * 0.37, i as f64 * 0.11))
+            .any(|p| pattern.at(&p) != stripes.at(&p));
+
+        assert!(flipped);
+    }
 }
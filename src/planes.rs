@@ -8,7 +8,9 @@ use crate::materials::Material;
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
 use crate::rays::Ray;
+use crate::shapes::shape_base;
 use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
 use crate::shapes::SyncShape;
 use crate::tuples::point;
 use crate::tuples::vector;
@@ -19,9 +21,11 @@ use std::sync::Arc;
 
 // a xz plane with normal pointing in the positive y direction
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Plane {
-    pub invtransform: Matrix,
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
     pub material: Material,
     bounds: Bounds,
 }
@@ -40,8 +44,18 @@ impl Shape for Plane {
         &self.invtransform
     }
     fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
         self.invtransform = invtransform;
     }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
     fn local_normal_at(&self, _local_point: Tuple) -> Tuple {
         vector(0., 1., 0.)
     }
@@ -59,6 +73,8 @@ pub fn plane() -> Plane {
     Plane {
         material: material(),
         invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         bounds: bound(
             point(NEG_INFINITY, NEG_INFINITY, 0.),
             point(INFINITY, INFINITY, 0.),
@@ -0,0 +1,50 @@
+use crate::canvas::Canvas;
+use image::codecs::png::PngEncoder;
+use image::ColorType;
+use image::ImageEncoder;
+
+impl Canvas {
+    // rows top to bottom, pixels left to right, the same layout and clamping as to_ppm
+    pub fn to_png(&self) -> Vec<u8> {
+        let rgb: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|c| {
+                let (r, g, b) = c.to_rgb8();
+                vec![r, g, b]
+            })
+            .collect();
+        let mut png = vec![];
+        PngEncoder::new(&mut png)
+            .write_image(&rgb, self.width as u32, self.height as u32, ColorType::Rgb8)
+            .expect("encoding into memory does not fail");
+        png
+    }
+
+    pub fn to_png_with_gamma(&self, gamma: f64) -> Vec<u8> {
+        self.with_gamma(gamma).to_png()
+    }
+}
+
+#[cfg(test)]
+mod spec {
+    use crate::canvas::canvas;
+    use crate::tuples::color;
+
+    #[test]
+    fn decoding_a_png_gives_back_the_pixels() {
+        let mut c = canvas(5, 3);
+        c.write_pixel(0, 0, color(1.5, 0.0, 0.0));
+        c.write_pixel(2, 1, color(0.0, 0.5, 0.0));
+        c.write_pixel(4, 2, color(-0.5, 0.0, 1.0));
+
+        let decoded = image::load_from_memory(&c.to_png()).unwrap().to_rgb8();
+
+        assert_eq!(decoded.dimensions(), (5, 3));
+        for &(x, y) in &[(0, 0), (2, 1), (4, 2), (1, 1)] {
+            let (r, g, b) = c.pixel_at(x, y).to_rgb8();
+            let expected = [r, g, b];
+            assert_eq!(decoded.get_pixel(x as u32, y as u32).0, expected);
+        }
+    }
+}
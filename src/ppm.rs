@@ -1,22 +1,102 @@
+use crate::canvas::canvas;
 use crate::canvas::Canvas;
-use crate::tuples::f_u8;
+use crate::tuples::color;
 use crate::tuples::Color;
+use std::io;
+use std::io::Write;
+
+#[derive(Debug, PartialEq)]
+pub enum PpmError {
+    Magic,
+    Header,
+    Sample(String),
+    MissingSamples,
+}
+
+// samples may wrap across lines in any way, everything after a # up to the end of line is a comment
+pub fn canvas_from_ppm(text: &str) -> Result<Canvas, PpmError> {
+    let mut words = text
+        .lines()
+        .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace());
+    if words.next() != Some("P3") {
+        return Err(PpmError::Magic);
+    }
+    let mut header = || {
+        words
+            .next()
+            .and_then(|w| w.parse::<usize>().ok())
+            .ok_or(PpmError::Header)
+    };
+    let (width, height, maxval) = (header()?, header()?, header()?);
+    if maxval == 0 {
+        return Err(PpmError::Header);
+    }
+
+    let samples = words
+        .map(|w| {
+            w.parse::<f64>()
+                .map_err(|_| PpmError::Sample(w.to_string()))
+        })
+        .collect::<Result<Vec<f64>, PpmError>>()?;
+    if samples.len() < width * height * 3 {
+        return Err(PpmError::MissingSamples);
+    }
+
+    let mut c = canvas(width, height);
+    let scale = maxval as f64;
+    for (i, rgb) in samples.chunks(3).take(width * height).enumerate() {
+        c.write_pixel(
+            i % width,
+            i / width,
+            color(rgb[0] / scale, rgb[1] / scale, rgb[2] / scale),
+        );
+    }
+    Ok(c)
+}
 
 impl Canvas {
     pub fn to_ppm(&self) -> String {
-        self.ppm_header() + "\n" + &self.ppm_pixels() + "\n"
+        let mut bytes = vec![];
+        self.write_ppm(&mut bytes)
+            .expect("writing into memory does not fail");
+        String::from_utf8(bytes).expect("ppm is plain ascii")
     }
 
-    fn ppm_header(&self) -> String {
-        format!("P3\n{} {}\n255", self.width, self.height).to_string()
+    // streams the image row by row instead of building the whole file in memory
+    pub fn write_ppm<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", self.ppm_header())?;
+        for row in self.pixels.chunks(self.width) {
+            for line in wrap(row.iter().flat_map(|pixel| colors(pixel)), 70) {
+                writeln!(w, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_ppm_with_gamma(&self, gamma: f64) -> String {
+        self.with_gamma(gamma).to_ppm()
+    }
+
+    pub fn to_ppm_srgb(&self) -> String {
+        let encoded = Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|c| c.to_srgb()).collect(),
+        };
+        encoded.to_ppm()
     }
 
-    fn ppm_pixels(&self) -> String {
-        let rows = self.pixels.chunks(self.width);
-        let lines = rows
-            .map(|row| row.iter().flat_map(|pixel| colors(pixel)))
-            .flat_map(|row| wrap(row, 70));
-        lines.collect::<Vec<String>>().join("\n")
+    pub fn to_ppm_binary(&self) -> Vec<u8> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for c in &self.pixels {
+            let (r, g, b) = c.to_rgb8();
+            bytes.extend_from_slice(&[r, g, b]);
+        }
+        bytes
+    }
+
+    fn ppm_header(&self) -> String {
+        format!("P3\n{} {}\n255", self.width, self.height).to_string()
     }
 }
 
@@ -40,16 +120,13 @@ fn wrap(words: impl Iterator<Item = String>, max_len: usize) -> Vec<String> {
 }
 
 fn colors(c: &Color) -> Vec<String> {
-    vec![c.red, c.green, c.blue]
-        .into_iter()
-        .map(|f| f_u8(f).to_string())
-        .collect()
+    let (r, g, b) = c.to_rgb8();
+    vec![r.to_string(), g.to_string(), b.to_string()]
 }
 
 #[cfg(test)]
 mod spec {
-    use crate::canvas::canvas;
-    use crate::tuples::color;
+    use super::*;
 
     #[test]
     fn constructing_the_ppm_header() {
@@ -90,12 +167,203 @@ mod spec {
         assert_eq!(unlines(ppm.lines().skip(3).take(4).collect()), pixels);
     }
 
+    #[test]
+    fn constructing_srgb_encoded_ppm_pixel_data() {
+        let mut c = canvas(3, 1);
+        c.write_pixel(0, 0, color(0.5, 0.5, 0.5));
+        c.write_pixel(2, 0, color(1., 0., 2.));
+        let linear = c.to_ppm();
+        let srgb = c.to_ppm_srgb();
+
+        assert_eq!(linear.lines().nth(3), Some("128 128 128 0 0 0 255 0 255"));
+        assert_eq!(srgb.lines().nth(3), Some("188 188 188 0 0 0 255 0 255"));
+    }
+
+    #[test]
+    fn constructing_a_binary_ppm() {
+        let mut c = canvas(2, 2);
+        c.write_pixel(0, 0, color(1.5, 0.0, 0.0));
+        c.write_pixel(1, 0, color(0.0, 0.5, 0.0));
+        c.write_pixel(1, 1, color(-0.5, 0.0, 1.0));
+
+        let ppm = c.to_ppm_binary();
+
+        assert_eq!(&ppm[..11], b"P6\n2 2\n255\n");
+        assert_eq!(&ppm[11..], &[255, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 255][..]);
+    }
+
+    #[test]
+    fn gamma_brightens_mid_gray() {
+        let mut c = canvas(2, 1);
+        c.write_pixel(0, 0, color(0.5, 0.5, 0.5));
+        c.write_pixel(1, 0, color(-0.5, 1.5, 1.));
+
+        assert_eq!(
+            c.to_ppm_with_gamma(1.).lines().nth(3),
+            Some("128 128 128 0 255 255")
+        );
+        assert_eq!(
+            c.to_ppm_with_gamma(2.2).lines().nth(3),
+            Some("187 187 187 0 255 255")
+        );
+    }
+
+    #[test]
+    fn streaming_a_ppm_writes_the_same_bytes() {
+        let mut c = canvas(5, 3);
+        c.write_pixel(0, 0, color(1.5, 0.0, 0.0));
+        c.write_pixel(2, 1, color(0.0, 0.5, 0.0));
+        c.write_pixel(4, 2, color(-0.5, 0.0, 1.0));
+        let mut bytes = vec![];
+
+        c.write_ppm(&mut bytes).unwrap();
+
+        let expected = unlines(vec![
+            "P3",
+            "5 3",
+            "255",
+            "255 0 0 0 0 0 0 0 0 0 0 0 0 0 0",
+            "0 0 0 0 0 0 0 128 0 0 0 0 0 0 0",
+            "0 0 0 0 0 0 0 0 0 0 0 0 0 0 255",
+            "",
+        ]);
+        assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+    }
+
     #[test]
     fn ppm_files_are_terminated_by_a_newline() {
         let mut ppm = canvas(5, 3).to_ppm();
         assert_eq!(ppm.pop(), Some('\n'));
     }
 
+    #[test]
+    fn reading_back_a_written_ppm() {
+        let mut c = canvas(2, 2);
+        c.write_pixel(0, 0, color(1., 0., 0.));
+        c.write_pixel(1, 1, color(0., 0., 1.));
+
+        let read = canvas_from_ppm(&c.to_ppm()).unwrap();
+
+        assert_eq!((read.width, read.height), (2, 2));
+        assert_eq!(read.pixel_at(0, 0), &color(1., 0., 0.));
+        assert_eq!(read.pixel_at(1, 0), &color(0., 0., 0.));
+        assert_eq!(read.pixel_at(1, 1), &color(0., 0., 1.));
+    }
+
+    #[test]
+    fn reading_a_file_with_the_wrong_magic_number() {
+        let ppm = "P32\n1 1\n255\n0 0 0\n";
+
+        assert_eq!(canvas_from_ppm(ppm).err(), Some(PpmError::Magic));
+    }
+
+    #[test]
+    fn reading_a_file_with_a_malformed_header() {
+        assert_eq!(
+            canvas_from_ppm("P3\n10 x\n255\n").err(),
+            Some(PpmError::Header)
+        );
+        assert_eq!(canvas_from_ppm("P3\n10 2\n").err(), Some(PpmError::Header));
+        assert_eq!(
+            canvas_from_ppm("P3\n1 1\n255\n0 0\n").err(),
+            Some(PpmError::MissingSamples)
+        );
+    }
+
+    #[test]
+    fn reading_a_ppm_returns_a_canvas_of_the_right_size() {
+        let ppm = unlines(vec![
+            "P3",
+            "10 2",
+            "255",
+            "0 0 0  0 0 0  0 0 0  0 0 0  0 0 0",
+            "0 0 0  0 0 0  0 0 0  0 0 0  0 0 0",
+            "0 0 0  0 0 0  0 0 0  0 0 0  0 0 0",
+            "0 0 0  0 0 0  0 0 0  0 0 0  0 0 0",
+        ]);
+
+        let c = canvas_from_ppm(&ppm).unwrap();
+
+        assert_eq!((c.width, c.height), (10, 2));
+    }
+
+    #[test]
+    fn reading_pixel_data_from_a_ppm_file() {
+        let ppm = unlines(vec![
+            "P3",
+            "4 3",
+            "255",
+            "255 127 0  0 127 255  127 255 0  255 255 255",
+            "0 0 0  255 0 0  0 255 0  0 0 255",
+            "255 255 0  0 255 255  255 0 255  127 127 127",
+        ]);
+        let half = 127. / 255.;
+
+        let c = canvas_from_ppm(&ppm).unwrap();
+
+        let examples = [
+            (0, 0, color(1., half, 0.)),
+            (1, 0, color(0., half, 1.)),
+            (2, 0, color(half, 1., 0.)),
+            (3, 0, color(1., 1., 1.)),
+            (0, 1, color(0., 0., 0.)),
+            (1, 1, color(1., 0., 0.)),
+            (2, 1, color(0., 1., 0.)),
+            (3, 1, color(0., 0., 1.)),
+            (0, 2, color(1., 1., 0.)),
+            (1, 2, color(0., 1., 1.)),
+            (2, 2, color(1., 0., 1.)),
+            (3, 2, color(half, half, half)),
+        ];
+        for (x, y, expected) in examples.iter() {
+            assert_eq!(c.pixel_at(*x, *y), expected);
+        }
+    }
+
+    #[test]
+    fn ppm_parsing_ignores_comment_lines() {
+        let ppm = unlines(vec![
+            "P3",
+            "# this is a comment",
+            "2 1",
+            "# this, too",
+            "255",
+            "# another comment",
+            "255 255 255",
+            "# oh, no, comments in the pixel data!",
+            "255 0 255",
+        ]);
+
+        let c = canvas_from_ppm(&ppm).unwrap();
+
+        assert_eq!(c.pixel_at(0, 0), &color(1., 1., 1.));
+        assert_eq!(c.pixel_at(1, 0), &color(1., 0., 1.));
+    }
+
+    #[test]
+    fn ppm_parsing_allows_an_rgb_triple_to_span_lines() {
+        let ppm = unlines(vec!["P3", "1 1", "255", "51", "153", "", "204"]);
+
+        let c = canvas_from_ppm(&ppm).unwrap();
+
+        assert_eq!(c.pixel_at(0, 0), &color(0.2, 0.6, 0.8));
+    }
+
+    #[test]
+    fn ppm_parsing_respects_the_scale_setting() {
+        let ppm = unlines(vec![
+            "P3",
+            "2 2",
+            "100",
+            "100 100 100  50 50 50",
+            "75 50 25  0 0 0",
+        ]);
+
+        let c = canvas_from_ppm(&ppm).unwrap();
+
+        assert_eq!(c.pixel_at(0, 1), &color(0.75, 0.5, 0.25));
+    }
+
     fn unlines(s: Vec<&str>) -> String {
         s.join("\n")
     }
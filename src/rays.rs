@@ -4,6 +4,8 @@ use crate::tuples::Tuple;
 pub struct Ray {
     pub origin: Tuple,
     pub direction: Tuple,
+    // when the ray is cast while the camera shutter is open, moving objects are placed at it
+    pub time: f64,
 }
 
 impl Ray {
@@ -12,12 +14,20 @@ impl Ray {
     }
 
     pub fn transform(&self, m: &Matrix) -> Ray {
-        ray(m * &self.origin, m * &self.direction)
+        ray(m * &self.origin, m * &self.direction).at_time(self.time)
+    }
+
+    pub fn at_time(self, time: f64) -> Ray {
+        Ray { time, ..self }
     }
 }
 
 pub fn ray(origin: Tuple, direction: Tuple) -> Ray {
-    Ray { origin, direction }
+    Ray {
+        origin,
+        direction,
+        time: 0.,
+    }
 }
 
 #[cfg(test)]
@@ -67,4 +77,14 @@ mod spec {
         assert_eq!(r2.origin, point(2., 6., 12.));
         assert_eq!(r2.direction, vector(0., 3., 0.));
     }
+
+    #[test]
+    fn transforming_a_ray_keeps_its_time() {
+        let r = ray(point(1., 2., 3.), vector(0., 1., 0.)).at_time(0.25);
+
+        let r2 = r.transform(&translation(3., 4., 5.));
+
+        assert_eq!(r2.time, 0.25);
+        assert_eq!(ray(point(1., 2., 3.), vector(0., 1., 0.)).time, 0.);
+    }
 }
@@ -0,0 +1,440 @@
+use crate::camera::{camera, Camera};
+use crate::cones::cone;
+use crate::cubes::cube;
+use crate::cylinders::cylinder;
+use crate::groups::group;
+use crate::lights::point_light;
+use crate::materials::{material, Material};
+use crate::matrices::{identity_matrix, Matrix};
+use crate::planes::plane;
+use crate::shapes::{Shape, SyncShape};
+use crate::spheres::sphere;
+use crate::transformations::*;
+use crate::tuples::{color, point, vector};
+use crate::world::{world, World};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+pub enum SceneError {
+    Yaml(String),
+    MissingCamera,
+    // the key of an entry that is missing or has an unexpected value
+    Entry(String),
+    Undefined(String),
+    // a define that expands back into itself
+    Recursive(String),
+}
+
+type Defines = HashMap<String, Value>;
+
+// the book's scene description, a list of `add` entries for the camera, lights and shapes and
+// `define` entries that name materials and transforms for reuse by the entries after them
+pub fn scene_from_yaml(yaml: &str) -> Result<(Camera, World), SceneError> {
+    let entries: Vec<Value> =
+        serde_yaml::from_str(yaml).map_err(|e| SceneError::Yaml(e.to_string()))?;
+    let mut defines = Defines::new();
+    let mut view = None;
+    let mut w = world();
+    for entry in &entries {
+        if let Some(name) = entry.get("define").and_then(Value::as_str) {
+            let value = field(entry, "value")?.clone();
+            let value = match entry.get("extend").and_then(Value::as_str) {
+                Some(parent) => extend(lookup(&defines, parent)?, value),
+                None => value,
+            };
+            defines.insert(name.to_string(), value);
+            continue;
+        }
+        match text(entry, "add")? {
+            "camera" => view = Some(parse_camera(entry)?),
+            "light" => w.add_light(point_light(
+                triple(entry, "at", point)?,
+                triple(entry, "intensity", color)?,
+            )),
+            _ => w.objects.push(parse_shape(entry, &defines)?),
+        }
+    }
+    view.map(|c| (c, w)).ok_or(SceneError::MissingCamera)
+}
+
+fn field<'a>(entry: &'a Value, key: &str) -> Result<&'a Value, SceneError> {
+    entry
+        .get(key)
+        .ok_or_else(|| SceneError::Entry(key.to_string()))
+}
+
+fn text<'a>(entry: &'a Value, key: &str) -> Result<&'a str, SceneError> {
+    field(entry, key)?
+        .as_str()
+        .ok_or_else(|| SceneError::Entry(key.to_string()))
+}
+
+fn number(value: &Value, key: &str) -> Result<f64, SceneError> {
+    value
+        .as_f64()
+        .ok_or_else(|| SceneError::Entry(key.to_string()))
+}
+
+fn numbers(values: &[Value], key: &str) -> Result<Vec<f64>, SceneError> {
+    values.iter().map(|v| number(v, key)).collect()
+}
+
+// a [x, y, z] list turned into a point, vector or color
+fn triple<T>(entry: &Value, key: &str, f: fn(f64, f64, f64) -> T) -> Result<T, SceneError> {
+    let values = field(entry, key)?
+        .as_sequence()
+        .map(|s| numbers(s, key))
+        .transpose()?;
+    match values.as_deref() {
+        Some([x, y, z]) => Ok(f(*x, *y, *z)),
+        _ => Err(SceneError::Entry(key.to_string())),
+    }
+}
+
+fn lookup<'a>(defines: &'a Defines, name: &str) -> Result<&'a Value, SceneError> {
+    defines
+        .get(name)
+        .ok_or_else(|| SceneError::Undefined(name.to_string()))
+}
+
+// the keys of the child replace the ones of the parent
+fn extend(parent: &Value, child: Value) -> Value {
+    match (parent, child) {
+        (Value::Mapping(parent), Value::Mapping(child)) => {
+            let mut merged = parent.clone();
+            merged.extend(child);
+            Value::Mapping(merged)
+        }
+        (_, child) => child,
+    }
+}
+
+fn parse_camera(entry: &Value) -> Result<Camera, SceneError> {
+    let size = |key| {
+        field(entry, key)?
+            .as_u64()
+            .map(|n| n as usize)
+            .ok_or_else(|| SceneError::Entry(key.to_string()))
+    };
+    let mut c = camera(
+        size("width")?,
+        size("height")?,
+        number(field(entry, "field-of-view")?, "field-of-view")?,
+    );
+    c.invtransform = view_transform(
+        &triple(entry, "from", point)?,
+        &triple(entry, "to", point)?,
+        &triple(entry, "up", vector)?,
+    )
+    .try_inverse()
+    .ok_or_else(|| SceneError::Entry("up".to_string()))?;
+    Ok(c)
+}
+
+fn parse_shape(entry: &Value, defines: &Defines) -> Result<Arc<SyncShape>, SceneError> {
+    match text(entry, "add")? {
+        "sphere" => configure(sphere(), entry, defines),
+        "plane" => configure(plane(), entry, defines),
+        "cube" => configure(cube(), entry, defines),
+        "cylinder" => {
+            let mut c = cylinder();
+            c.minimum = optional_number(entry, "min", c.minimum)?;
+            c.maximum = optional_number(entry, "max", c.maximum)?;
+            c.closed = optional_bool(entry, "closed", c.closed)?;
+            configure(c, entry, defines)
+        }
+        "cone" => {
+            let mut c = cone();
+            c.minimum = optional_number(entry, "min", c.minimum)?;
+            c.maximum = optional_number(entry, "max", c.maximum)?;
+            c.closed = optional_bool(entry, "closed", c.closed)?;
+            configure(c, entry, defines)
+        }
+        "group" => {
+            let mut g = group();
+            let children = field(entry, "children")?
+                .as_sequence()
+                .ok_or_else(|| SceneError::Entry("children".to_string()))?;
+            for child in children {
+                g.add_child_rc(parse_shape(child, defines)?);
+            }
+            configure(g, entry, defines)
+        }
+        kind => Err(SceneError::Undefined(kind.to_string())),
+    }
+}
+
+fn optional_number(entry: &Value, key: &str, default: f64) -> Result<f64, SceneError> {
+    entry.get(key).map_or(Ok(default), |v| number(v, key))
+}
+
+fn optional_bool(entry: &Value, key: &str, default: bool) -> Result<bool, SceneError> {
+    entry.get(key).map_or(Ok(default), |v| {
+        v.as_bool()
+            .ok_or_else(|| SceneError::Entry(key.to_string()))
+    })
+}
+
+fn configure<T>(
+    mut shape: T,
+    entry: &Value,
+    defines: &Defines,
+) -> Result<Arc<SyncShape>, SceneError>
+where
+    T: Shape + Sync + Send + 'static,
+{
+    if let Some(t) = entry.get("transform") {
+        if !shape.set_transform(parse_transform(t, defines)?) {
+            return Err(SceneError::Entry("transform".to_string()));
+        }
+    }
+    if let Some(m) = entry.get("material") {
+        shape.set_material(parse_material(m, defines)?);
+    }
+    Ok(Arc::new(shape))
+}
+
+// steps are applied in the order they are listed, a name expands to the steps it defines
+fn parse_transform(value: &Value, defines: &Defines) -> Result<Matrix, SceneError> {
+    expand_transform(value, defines, &mut vec![])
+}
+
+// expanding holds the names being expanded, a name inside its own expansion would never end
+fn expand_transform(
+    value: &Value,
+    defines: &Defines,
+    expanding: &mut Vec<String>,
+) -> Result<Matrix, SceneError> {
+    let steps = value
+        .as_sequence()
+        .ok_or_else(|| SceneError::Entry("transform".to_string()))?;
+    let mut m = identity_matrix();
+    for step in steps {
+        let next = match step {
+            Value::String(name) => {
+                if expanding.contains(name) {
+                    return Err(SceneError::Recursive(name.to_string()));
+                }
+                expanding.push(name.to_string());
+                let m = expand_transform(lookup(defines, name)?, defines, expanding)?;
+                expanding.pop();
+                m
+            }
+            Value::Sequence(op) => parse_step(op)?,
+            _ => return Err(SceneError::Entry("transform".to_string())),
+        };
+        m = next * m;
+    }
+    Ok(m)
+}
+
+fn parse_step(op: &[Value]) -> Result<Matrix, SceneError> {
+    let name = op
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| SceneError::Entry("transform".to_string()))?;
+    let args = numbers(&op[1..], name)?;
+    match (name, args.as_slice()) {
+        ("translate", [x, y, z]) => Ok(translation(*x, *y, *z)),
+        ("scale", [x, y, z]) => Ok(scaling(*x, *y, *z)),
+        ("rotate-x", [r]) => Ok(rotation_x(*r)),
+        ("rotate-y", [r]) => Ok(rotation_y(*r)),
+        ("rotate-z", [r]) => Ok(rotation_z(*r)),
+        ("shear", [xy, xz, yx, yz, zx, zy]) => Ok(shearing(*xy, *xz, *yx, *yz, *zx, *zy)),
+        _ => Err(SceneError::Entry(name.to_string())),
+    }
+}
+
+fn parse_material(value: &Value, defines: &Defines) -> Result<Material, SceneError> {
+    let value = match value {
+        Value::String(name) => lookup(defines, name)?,
+        v => v,
+    };
+    let fields = value
+        .as_mapping()
+        .ok_or_else(|| SceneError::Entry("material".to_string()))?;
+    let mut m = material();
+    for (key, v) in fields {
+        let key = key
+            .as_str()
+            .ok_or_else(|| SceneError::Entry("material".to_string()))?;
+        match key {
+            "color" => m.color = triple(value, key, color)?,
+            "ambient" => m.ambient = number(v, key)?,
+            "diffuse" => m.diffuse = number(v, key)?,
+            "specular" => m.specular = number(v, key)?,
+            "shininess" => m.shininess = number(v, key)?,
+            "reflective" => m.reflective = number(v, key)?,
+            "transparency" => m.transparency = number(v, key)?,
+            "refractive-index" => m.refractive_index = number(v, key)?,
+            _ => return Err(SceneError::Entry(key.to_string())),
+        }
+    }
+    Ok(m)
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const SCENE: &str = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [0, 1.5, -5]
+  to: [0, 1, 0]
+  up: [0, 1, 0]
+
+- add: light
+  at: [-10, 10, -10]
+  intensity: [1, 1, 1]
+
+- define: white-material
+  value:
+    color: [1, 1, 1]
+    diffuse: 0.7
+
+- define: blue-material
+  extend: white-material
+  value:
+    color: [0.537, 0.831, 0.914]
+
+- define: standard-transform
+  value:
+    - [translate, 1, -1, 1]
+    - [scale, 0.5, 0.5, 0.5]
+
+- add: plane
+  transform:
+    - [rotate-x, 1.5707963267948966]
+
+- add: sphere
+  material: blue-material
+  transform:
+    - standard-transform
+    - [translate, 0, 2, 0]
+
+- add: group
+  children:
+    - add: cube
+    - add: cylinder
+      min: 0
+      max: 1
+      closed: true
+";
+
+    #[test]
+    fn loading_a_scene_description() {
+        let (c, w) = scene_from_yaml(SCENE).unwrap();
+
+        assert_eq!(w.objects.len(), 3);
+        assert_eq!(
+            w.light_sources,
+            vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()]
+        );
+        let mut expected = camera(100, 50, 0.785);
+        expected.invtransform = view_transform(
+            &point(0., 1.5, -5.),
+            &point(0., 1., 0.),
+            &vector(0., 1., 0.),
+        )
+        .inverse();
+        let corners = c.frustum_corners();
+        for (a, b) in corners.iter().zip(expected.frustum_corners().iter()) {
+            assert_eq!(a.direction, b.direction);
+        }
+    }
+
+    #[test]
+    fn materials_extend_their_definitions() {
+        let (_, w) = scene_from_yaml(SCENE).unwrap();
+        let m = w.objects[1].material();
+
+        assert_eq!(m.color, color(0.537, 0.831, 0.914));
+        assert_eq!(m.diffuse, 0.7);
+    }
+
+    #[test]
+    fn transforms_apply_their_steps_in_order() {
+        let (_, w) = scene_from_yaml(SCENE).unwrap();
+
+        let expected = translation(0., 2., 0.) * scaling(0.5, 0.5, 0.5) * translation(1., -1., 1.);
+        assert_eq!(w.objects[1].invtransform(), &expected.inverse());
+        assert_eq!(w.objects[0].invtransform(), &rotation_x(PI / 2.).inverse());
+    }
+
+    #[test]
+    fn a_scene_needs_a_camera() {
+        assert_eq!(
+            scene_from_yaml("- add: sphere").err(),
+            Some(SceneError::MissingCamera)
+        );
+    }
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let yaml = "- add: sphere\n  material: missing";
+
+        assert_eq!(
+            scene_from_yaml(yaml).err(),
+            Some(SceneError::Undefined("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn a_camera_looking_along_its_up_vector_is_an_error() {
+        let yaml = "- add: camera\n  width: 10\n  height: 10\n  field-of-view: 1\n  from: [0, 0, 0]\n  to: [0, 1, 0]\n  up: [0, 1, 0]";
+
+        assert_eq!(
+            scene_from_yaml(yaml).err(),
+            Some(SceneError::Entry("up".to_string()))
+        );
+    }
+
+    #[test]
+    fn a_singular_transform_is_an_error() {
+        let yaml = "- add: sphere\n  transform:\n    - [scale, 1, 0, 1]";
+
+        assert_eq!(
+            scene_from_yaml(yaml).err(),
+            Some(SceneError::Entry("transform".to_string()))
+        );
+    }
+
+    #[test]
+    fn transforms_defined_in_terms_of_themselves_are_an_error() {
+        let direct = "
+- define: spin
+  value:
+    - spin
+- add: sphere
+  transform:
+    - spin
+";
+        let indirect = "
+- define: a
+  value:
+    - b
+- define: b
+  value:
+    - [translate, 1, 0, 0]
+    - a
+- add: sphere
+  transform:
+    - a
+";
+
+        assert_eq!(
+            scene_from_yaml(direct).err(),
+            Some(SceneError::Recursive("spin".to_string()))
+        );
+        assert_eq!(
+            scene_from_yaml(indirect).err(),
+            Some(SceneError::Recursive("a".to_string()))
+        );
+    }
+}
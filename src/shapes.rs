@@ -4,23 +4,77 @@ use crate::materials::Material;
 use crate::matrices::Matrix;
 use crate::rays::Ray;
 use crate::tuples::Tuple;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 pub type SyncShape = dyn Shape + Sync + Send;
 
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// identity of a shape for tools and logs, clones keep the id of the shape they copy
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeBase {
+    id: usize,
+    name: Option<String>,
+}
+
+pub fn shape_base() -> ShapeBase {
+    ShapeBase {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        name: None,
+    }
+}
+
 pub trait Shape {
     fn material(&self) -> &Material;
     fn set_material(&mut self, material: Material);
 
     fn invtransform(&self) -> &Matrix;
+    // also caches the transposed inverse used to transform normals
     fn set_invtransform(&mut self, invtransform: Matrix);
+    // a singular transform would flatten the shape, it keeps its transform and reports false
+    fn set_transform(&mut self, transform: Matrix) -> bool {
+        match transform.try_inverse() {
+            Some(invtransform) => {
+                self.set_invtransform(invtransform);
+                true
+            }
+            None => false,
+        }
+    }
+    fn normal_transform(&self) -> &Matrix;
+
+    // name of the concrete type, primitives with the same material and transform still differ
+    fn shape_kind(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn base(&self) -> &ShapeBase;
+    fn base_mut(&mut self) -> &mut ShapeBase;
+    fn id(&self) -> usize {
+        self.base().id
+    }
+    fn name(&self) -> Option<&str> {
+        self.base().name.as_deref()
+    }
+    fn set_name(&mut self, name: &str) {
+        self.base_mut().name = Some(name.to_string());
+    }
 
     fn local_normal_at(&self, local_point: Tuple) -> Tuple;
+    // shapes that interpolate normals across the surface need to know where exactly it was hit
+    fn local_normal_at_hit(&self, local_point: Tuple, _hit: &Intersection) -> Tuple {
+        self.local_normal_at(local_point)
+    }
+    // texture coordinates of the surface at the hit, for shapes that carry them
+    fn uv_at(&self, _hit: &Intersection) -> Option<(f64, f64)> {
+        None
+    }
     fn world_to_object(&self, world_point: &Tuple) -> Tuple {
         self.invtransform() * world_point
     }
     fn normal_to_world(&self, local_normal: Tuple) -> Tuple {
-        let mut world_normal = self.invtransform().transpose() * local_normal;
+        let mut world_normal = self.normal_transform() * &local_normal;
         world_normal.w = 0.;
         world_normal.normalized()
     }
@@ -29,6 +83,11 @@ pub trait Shape {
         let local_normal = self.local_normal_at(local_point);
         self.normal_to_world(local_normal)
     }
+    fn normal_at_hit(&self, world_point: &Tuple, hit: &Intersection) -> Tuple {
+        let local_point = self.world_to_object(world_point);
+        let local_normal = self.local_normal_at_hit(local_point, hit);
+        self.normal_to_world(local_normal)
+    }
 
     fn local_intersects(&self, rc: Arc<SyncShape>, local_ray: Ray) -> Vec<Intersection>;
     fn intersects(&self, rc: Arc<SyncShape>, inray: &Ray) -> Vec<Intersection> {
@@ -37,13 +96,24 @@ pub trait Shape {
     }
 
     fn local_bounds(&self) -> Bounds;
+
+    // number of primitive shapes this shape is made of, composite shapes count their leaves
+    fn primitive_count(&self) -> usize {
+        1
+    }
+    // whether intersections are pruned by a hierarchy of bounding boxes
+    fn has_bounding_hierarchy(&self) -> bool {
+        false
+    }
 }
 
 impl std::fmt::Debug for Shape {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "Shape ({:?}, {:?})",
+            "Shape #{} {:?} ({:?}, {:?})",
+            self.id(),
+            self.name().unwrap_or(""),
             self.material(),
             self.invtransform()
         )
@@ -53,7 +123,9 @@ impl std::fmt::Debug for SyncShape {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "Shape ({:?}, {:?})",
+            "Shape #{} {:?} ({:?}, {:?})",
+            self.id(),
+            self.name().unwrap_or(""),
             self.material(),
             self.invtransform()
         )
@@ -62,12 +134,16 @@ impl std::fmt::Debug for SyncShape {
 
 impl PartialEq<SyncShape> for SyncShape {
     fn eq(&self, shape: &SyncShape) -> bool {
-        self.material().eq(shape.material()) && self.invtransform().eq(shape.invtransform())
+        self.shape_kind() == shape.shape_kind()
+            && self.material().eq(shape.material())
+            && self.invtransform().eq(shape.invtransform())
     }
 }
 impl PartialEq<Shape> for Shape {
     fn eq(&self, shape: &Shape) -> bool {
-        self.material().eq(shape.material()) && self.invtransform().eq(shape.invtransform())
+        self.shape_kind() == shape.shape_kind()
+            && self.material().eq(shape.material())
+            && self.invtransform().eq(shape.invtransform())
     }
 }
 
@@ -75,7 +151,11 @@ impl PartialEq<Shape> for Shape {
 pub mod spec {
     use super::*;
     use crate::bounds::bound_single;
+    use crate::csg::csg;
+    use crate::csg::CsgOp;
+    use crate::cubes::cube;
     use crate::groups::group;
+    use crate::instances::instance;
     use crate::materials::material;
     use crate::materials::Material;
     use crate::matrices::identity_matrix;
@@ -93,6 +173,8 @@ pub mod spec {
     #[derive(Debug, PartialEq)]
     pub struct TestShape {
         invtransform: Matrix,
+        normal_transform: Matrix,
+        base: ShapeBase,
         material: Material,
     }
     impl Shape for TestShape {
@@ -109,8 +191,18 @@ pub mod spec {
             &self.invtransform
         }
         fn set_invtransform(&mut self, invtransform: Matrix) {
+            self.normal_transform = invtransform.transpose();
             self.invtransform = invtransform;
         }
+        fn normal_transform(&self) -> &Matrix {
+            &self.normal_transform
+        }
+        fn base(&self) -> &ShapeBase {
+            &self.base
+        }
+        fn base_mut(&mut self) -> &mut ShapeBase {
+            &mut self.base
+        }
         fn local_intersects(&self, _rc: Arc<SyncShape>, _local_ray: Ray) -> Vec<Intersection> {
             vec![]
         }
@@ -121,6 +213,8 @@ pub mod spec {
     pub fn test_shape() -> TestShape {
         TestShape {
             invtransform: identity_matrix(),
+            normal_transform: identity_matrix(),
+            base: shape_base(),
             material: material(),
         }
     }
@@ -141,6 +235,15 @@ pub mod spec {
         assert_eq!(s.invtransform(), &translation(2., 3., 4.));
     }
 
+    #[test]
+    fn a_singular_transformation_is_refused() {
+        let mut s = test_shape();
+        s.set_transform(translation(2., 3., 4.));
+
+        assert!(!s.set_transform(scaling(1., 0., 1.)));
+        assert_eq!(s.invtransform(), &translation(-2., -3., -4.));
+    }
+
     #[test]
     fn the_default_material() {
         let s = test_shape();
@@ -164,7 +267,7 @@ pub mod spec {
     #[test]
     fn computing_the_normal_on_a_translated_shape() {
         let mut s = test_shape();
-        s.invtransform = translation(0., 1., 0.).inverse();
+        s.set_transform(translation(0., 1., 0.));
 
         let n = s.normal_at(&point(0., 1.70711, -0.70711));
 
@@ -174,7 +277,7 @@ pub mod spec {
     #[test]
     fn computing_the_normal_on_a_transformed_sphere() {
         let mut s = test_shape();
-        s.invtransform = (scaling(1., 0.5, 1.) * rotation_z(PI / 5.)).inverse();
+        s.set_transform(scaling(1., 0.5, 1.) * rotation_z(PI / 5.));
 
         let a = 2_f64.sqrt() / 2.;
         let n = s.normal_at(&point(0., a, -a));
@@ -182,14 +285,39 @@ pub mod spec {
         assert_eq!(n, vector(0., 0.97014, -0.24254));
     }
 
+    #[test]
+    fn the_cached_normal_transform_follows_every_change_of_transform() {
+        let shapes: Vec<Box<SyncShape>> = vec![
+            Box::new(test_shape()),
+            Box::new(sphere()),
+            Box::new(cube()),
+            Box::new(group()),
+            Box::new(csg(CsgOp::Union, Arc::new(sphere()), Arc::new(cube()))),
+            Box::new(instance(Arc::new(sphere()))),
+        ];
+        let m = scaling(1., 0.5, 1.) * rotation_z(PI / 5.);
+        let inv = rotation_y(PI / 3.) * translation(1., 2., 3.);
+
+        for mut s in shapes {
+            s.set_transform(m.clone());
+            assert_eq!(s.normal_transform(), &m.inverse().transpose());
+
+            s.set_invtransform(inv.clone());
+            assert_eq!(s.normal_transform(), &inv.transpose());
+
+            s.set_transform(identity_matrix());
+            assert_eq!(s.normal_transform(), &identity_matrix());
+        }
+    }
+
     #[test]
     fn converting_a_point_from_world_to_object_space() {
         let mut g1 = group();
-        g1.invtransform = rotation_y(PI / 2.).inverse();
+        g1.set_transform(rotation_y(PI / 2.));
         let mut g2 = group();
-        g2.invtransform = scaling(2., 2., 2.).inverse();
+        g2.set_transform(scaling(2., 2., 2.));
         let mut s = sphere();
-        s.invtransform = translation(5., 0., 0.).inverse();
+        s.set_transform(translation(5., 0., 0.));
         g2.add_child(s);
         g1.add_child(g2);
 
@@ -201,11 +329,11 @@ pub mod spec {
     #[test]
     fn converting_a_normal_from_object_to_world_space() {
         let mut g1 = group();
-        g1.invtransform = rotation_y(PI / 2.).inverse();
+        g1.set_transform(rotation_y(PI / 2.));
         let mut g2 = group();
-        g2.invtransform = scaling(1., 2., 3.).inverse();
+        g2.set_transform(scaling(1., 2., 3.));
         let mut s = sphere();
-        s.invtransform = translation(5., 0., 0.).inverse();
+        s.set_transform(translation(5., 0., 0.));
         g2.add_child(s);
         g1.add_child(g2);
         let sq3 = 3.0_f64.sqrt();
@@ -218,11 +346,11 @@ pub mod spec {
     #[test]
     fn finding_the_normal_on_a_child_object() {
         let mut g1 = group();
-        g1.invtransform = rotation_y(PI / 2.).inverse();
+        g1.set_transform(rotation_y(PI / 2.));
         let mut g2 = group();
-        g2.invtransform = scaling(1., 2., 3.).inverse();
+        g2.set_transform(scaling(1., 2., 3.));
         let mut s = sphere();
-        s.invtransform = translation(5., 0., 0.).inverse();
+        s.set_transform(translation(5., 0., 0.));
         g2.add_child(s);
         g1.add_child(g2);
 
@@ -230,4 +358,42 @@ pub mod spec {
 
         assert_eq!(n, vector(0.28570, 0.42854, -0.85716));
     }
+
+    #[test]
+    fn every_shape_gets_its_own_id() {
+        let a = sphere();
+        let b = sphere();
+
+        assert_ne!(a.id(), b.id());
+        assert_eq!(a.clone().id(), a.id());
+    }
+
+    #[test]
+    fn naming_a_shape() {
+        let mut s = test_shape();
+        assert_eq!(s.name(), None);
+
+        s.set_name("floor");
+
+        assert_eq!(s.name(), Some("floor"));
+    }
+
+    #[test]
+    fn shapes_of_different_kinds_are_not_equal() {
+        let s: Arc<SyncShape> = Arc::new(sphere());
+        let c: Arc<SyncShape> = Arc::new(cube());
+
+        assert_ne!(*s, *c);
+    }
+
+    #[test]
+    fn spheres_with_the_same_transform_and_material_are_equal() {
+        let mut a = sphere();
+        a.set_transform(translation(1., 2., 3.));
+        let mut b = sphere();
+        b.set_transform(translation(1., 2., 3.));
+        let (a, b): (Arc<SyncShape>, Arc<SyncShape>) = (Arc::new(a), Arc::new(b));
+
+        assert_eq!(*a, *b);
+    }
 }
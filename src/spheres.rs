@@ -6,24 +6,35 @@ use crate::intersections::Intersection;
 use crate::materials::{material, Material};
 use crate::matrices::{identity_matrix, Matrix};
 use crate::rays::Ray;
+use crate::shapes::shape_base;
 use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
 use crate::shapes::SyncShape;
+use crate::tuples::origin;
 use crate::tuples::point;
 use crate::tuples::Tuple;
 use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Sphere {
-    pub invtransform: Matrix,
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
     pub material: Material,
-    bounds: Bounds,
+    pub radius: f64,
 }
 
 pub fn sphere() -> Sphere {
+    sphere_with_radius(1.)
+}
+
+pub fn sphere_with_radius(radius: f64) -> Sphere {
     Sphere {
         invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         material: material(),
-        bounds: bound(point(-1., -1., -1.), point(1., 1., 1.)),
+        radius,
     }
 }
 
@@ -36,7 +47,8 @@ pub fn glass_sphere() -> Sphere {
 
 impl Shape for Sphere {
     fn local_bounds(&self) -> Bounds {
-        self.bounds.clone()
+        let r = self.radius;
+        bound(point(-r, -r, -r), point(r, r, r))
     }
 
     fn material(&self) -> &Material {
@@ -49,17 +61,27 @@ impl Shape for Sphere {
         &self.invtransform
     }
     fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
         self.invtransform = invtransform;
     }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
     fn local_normal_at(&self, local_point: Tuple) -> Tuple {
-        local_point - point(0., 0., 0.)
+        (local_point - origin()) / self.radius
     }
     fn local_intersects(&self, rc: Arc<SyncShape>, local_ray: Ray) -> Vec<Intersection> {
-        let shape_to_ray = local_ray.origin - point(0., 0., 0.);
+        let shape_to_ray = local_ray.origin - origin();
 
         let a = local_ray.direction.dot(&local_ray.direction);
         let b = 2. * local_ray.direction.dot(&shape_to_ray);
-        let c = shape_to_ray.dot(&shape_to_ray) - 1.;
+        let c = shape_to_ray.dot(&shape_to_ray) - self.radius.powi(2);
         let discriminant = b.powi(2) - 4. * a * c;
 
         if discriminant < 0. {
@@ -154,7 +176,7 @@ mod spec {
     fn intersection_a_scaled_sphere_with_a_ray() {
         let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
         let mut s = sphere();
-        s.invtransform = scaling(2., 2., 2.).inverse();
+        s.set_transform(scaling(2., 2., 2.));
         let rc = Arc::new(s);
 
         let xs = rc.intersects(rc.clone(), &r);
@@ -168,7 +190,7 @@ mod spec {
     fn intersection_a_translated_sphere_with_a_ray() {
         let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
         let mut s = sphere();
-        s.invtransform = translation(5., 0., 0.).inverse();
+        s.set_transform(translation(5., 0., 0.));
         let rc = Arc::new(s);
 
         let xs = rc.intersects(rc.clone(), &r);
@@ -231,4 +253,27 @@ mod spec {
             bound(point(-1., -1., -1.), point(1., 1., 1.))
         );
     }
+
+    #[test]
+    fn a_ray_intersects_a_sphere_with_radius() {
+        let s = Arc::new(sphere_with_radius(2.));
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+
+        let xs = s.local_intersects(s.clone(), r);
+
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 3.);
+        assert_eq!(xs[1].t, 7.);
+    }
+
+    #[test]
+    fn the_normal_and_bounds_of_a_sphere_with_radius() {
+        let s = sphere_with_radius(2.);
+
+        assert_eq!(s.local_normal_at(point(0., 2., 0.)), vector(0., 1., 0.));
+        assert_eq!(
+            s.local_bounds(),
+            bound(point(-2., -2., -2.), point(2., 2., 2.))
+        );
+    }
 }
@@ -0,0 +1,278 @@
+use crate::bounds::bound;
+use crate::bounds::Bounds;
+use crate::intersections::compare_t;
+use crate::intersections::intersection;
+use crate::intersections::Intersection;
+use crate::materials::{material, Material};
+use crate::matrices::{identity_matrix, Matrix};
+use crate::rays::Ray;
+use crate::shapes::shape_base;
+use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
+use crate::shapes::SyncShape;
+use crate::tuples::point;
+use crate::tuples::vector;
+use crate::tuples::Tuple;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+const ROOT_EPSILON: f64 = 1e-9;
+
+// a ring around the y axis, major radius is measured from the center to the middle of the tube
+#[derive(Clone, Debug, PartialEq)]
+pub struct Torus {
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
+    pub material: Material,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+pub fn torus(major_radius: f64, minor_radius: f64) -> Torus {
+    Torus {
+        invtransform: identity_matrix(),
+        normal_transform: identity_matrix(),
+        base: shape_base(),
+        material: material(),
+        major_radius,
+        minor_radius,
+    }
+}
+
+fn is_zero(f: f64) -> bool {
+    f.abs() < ROOT_EPSILON
+}
+
+// real roots of x^2 + b x + c
+fn solve_quadratic(b: f64, c: f64) -> Vec<f64> {
+    let p = b / 2.;
+    let d = p * p - c;
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0. {
+        vec![]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![-p - sqrt_d, -p + sqrt_d]
+    }
+}
+
+// real roots of x^3 + a x^2 + b x + c using Cardano's formula
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // substitute x = y - a/3 to eliminate the quadric term: y^3 + 3p y + 2q = 0
+    let p = (b - a * a / 3.) / 3.;
+    let q = (2. / 27. * a * a * a - a * b / 3. + c) / 2.;
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let roots = if is_zero(d) {
+        if is_zero(q) {
+            vec![0.]
+        } else {
+            let u = (-q).cbrt();
+            vec![2. * u, -u]
+        }
+    } else if d < 0. {
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1., 1.).acos() / 3.;
+        let t = 2. * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + PI / 3.).cos(),
+            -t * (phi - PI / 3.).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    roots.into_iter().map(|y| y - a / 3.).collect()
+}
+
+// real roots of x^4 + a x^3 + b x^2 + c x + d using Ferrari's method
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // substitute x = y - a/4 to eliminate the cubic term: y^4 + p y^2 + q y + r = 0
+    let sq_a = a * a;
+    let p = -3. / 8. * sq_a + b;
+    let q = sq_a * a / 8. - a * b / 2. + c;
+    let r = -3. / 256. * sq_a * sq_a + sq_a * b / 16. - a * c / 4. + d;
+
+    let roots = if is_zero(r) {
+        let mut roots = solve_cubic(0., p, q);
+        roots.push(0.);
+        roots
+    } else {
+        // any real root of the resolvent cubic splits the quartic into two quadratics
+        let z = solve_cubic(-p / 2., -r, r * p / 2. - q * q / 8.)[0];
+        let u = z * z - r;
+        let v = 2. * z - p;
+        if u < -ROOT_EPSILON || v < -ROOT_EPSILON {
+            return vec![];
+        }
+        let u = u.max(0.).sqrt();
+        let v = if q < 0. {
+            -v.max(0.).sqrt()
+        } else {
+            v.max(0.).sqrt()
+        };
+        let mut roots = solve_quadratic(v, z - u);
+        roots.extend(solve_quadratic(-v, z + u));
+        roots
+    };
+    roots.into_iter().map(|y| y - a / 4.).collect()
+}
+
+// newton iterations on the original polynomial win back the precision lost by substitutions
+fn polish(coefficients: &[f64; 5], x: f64) -> f64 {
+    (0..2).fold(x, |x, _| {
+        let (f, df) = coefficients
+            .iter()
+            .fold((0., 0.), |(f, df), c| (f * x + c, df * x + f));
+        if df.abs() > ROOT_EPSILON {
+            x - f / df
+        } else {
+            x
+        }
+    })
+}
+
+impl Shape for Torus {
+    fn local_bounds(&self) -> Bounds {
+        let (big, small) = (self.major_radius + self.minor_radius, self.minor_radius);
+        bound(point(-big, -small, -big), point(big, small, big))
+    }
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
+        self.invtransform = invtransform;
+    }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
+    fn local_normal_at(&self, p: Tuple) -> Tuple {
+        // gradient of (x^2 + y^2 + z^2 + R^2 - r^2)^2 - 4 R^2 (x^2 + z^2)
+        let sq_major = self.major_radius.powi(2);
+        let s = p.x * p.x + p.y * p.y + p.z * p.z + sq_major - self.minor_radius.powi(2);
+        vector(
+            p.x * (s - 2. * sq_major),
+            p.y * s,
+            p.z * (s - 2. * sq_major),
+        )
+        .normalized()
+    }
+    fn local_intersects(&self, rc: Arc<SyncShape>, ray: Ray) -> Vec<Intersection> {
+        if !self.local_bounds().intersects(&ray) {
+            return vec![];
+        }
+        let (o, d) = (&ray.origin, &ray.direction);
+        let sq_major = self.major_radius.powi(2);
+        let dd = d.x * d.x + d.y * d.y + d.z * d.z;
+        let od = o.x * d.x + o.y * d.y + o.z * d.z;
+        let k = o.x * o.x + o.y * o.y + o.z * o.z + sq_major - self.minor_radius.powi(2);
+
+        let coefficients = [
+            dd * dd,
+            4. * dd * od,
+            4. * od * od + 2. * dd * k - 4. * sq_major * (d.x * d.x + d.z * d.z),
+            4. * od * k - 8. * sq_major * (o.x * d.x + o.z * d.z),
+            k * k - 4. * sq_major * (o.x * o.x + o.z * o.z),
+        ];
+        let [a4, a3, a2, a1, a0] = coefficients;
+        let mut ts: Vec<f64> = solve_quartic(a3 / a4, a2 / a4, a1 / a4, a0 / a4)
+            .into_iter()
+            .map(|t| polish(&coefficients, t))
+            .collect();
+        ts.sort_unstable_by(|a, b| compare_t(*a, *b));
+        ts.into_iter()
+            .map(|t| intersection(t, rc.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use crate::rays::ray;
+    use hamcrest2::prelude::*;
+
+    #[test]
+    fn a_ray_passes_through_the_ring_body() {
+        let t = Arc::new(torus(1., 0.25));
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+
+        let xs = t.local_intersects(t.clone(), r);
+
+        assert_eq!(xs.len(), 4);
+        for (x, expected) in xs.iter().zip(&[3.75, 4.25, 5.75, 6.25]) {
+            assert_that!(x.t, close_to(*expected, 1e-6));
+        }
+    }
+
+    #[test]
+    fn a_ray_passes_through_the_hole() {
+        let t = Arc::new(torus(1., 0.25));
+        let r = ray(point(0., 5., 0.), vector(0., -1., 0.));
+
+        let xs = t.local_intersects(t.clone(), r);
+
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn a_ray_crosses_the_tube_from_above() {
+        let t = Arc::new(torus(1., 0.25));
+        let r = ray(point(1., 5., 0.), vector(0., -1., 0.));
+
+        let xs = t.local_intersects(t.clone(), r);
+
+        assert_eq!(xs.len(), 2);
+        assert_that!(xs[0].t, close_to(4.75, 1e-6));
+        assert_that!(xs[1].t, close_to(5.25, 1e-6));
+    }
+
+    #[test]
+    fn a_ray_misses_a_torus() {
+        let t = Arc::new(torus(1., 0.25));
+        let r = ray(point(0., 2., -5.), vector(0., 0., 1.));
+
+        let xs = t.local_intersects(t.clone(), r);
+
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn the_normal_on_a_torus() {
+        let t = torus(1., 0.25);
+        for (p, n) in &[
+            (point(1.25, 0., 0.), vector(1., 0., 0.)),
+            (point(0.75, 0., 0.), vector(-1., 0., 0.)),
+            (point(1., 0.25, 0.), vector(0., 1., 0.)),
+            (point(0., -0.25, 1.), vector(0., -1., 0.)),
+        ] {
+            assert_that!(t.local_normal_at(p.clone()), eq(n.clone()));
+        }
+    }
+
+    #[test]
+    fn a_torus_has_a_bounding_box() {
+        let t = torus(1., 0.25);
+
+        assert_eq!(
+            t.local_bounds(),
+            bound(point(-1.25, -0.25, -1.25), point(1.25, 0.25, 1.25))
+        );
+    }
+}
@@ -8,7 +8,7 @@ pub fn translation(x: f64, y: f64, z: f64) -> Matrix {
     data[0][3] = x;
     data[1][3] = y;
     data[2][3] = z;
-    Matrix { data }
+    Matrix::from(data)
 }
 
 pub fn scaling(x: f64, y: f64, z: f64) -> Matrix {
@@ -16,7 +16,7 @@ pub fn scaling(x: f64, y: f64, z: f64) -> Matrix {
     data[0][0] = x;
     data[1][1] = y;
     data[2][2] = z;
-    Matrix { data }
+    Matrix::from(data)
 }
 
 pub fn rotation_x(r: f64) -> Matrix {
@@ -27,7 +27,7 @@ pub fn rotation_x(r: f64) -> Matrix {
     data[1][2] = -s;
     data[2][1] = s;
     data[2][2] = c;
-    Matrix { data }
+    Matrix::from(data)
 }
 
 pub fn rotation_y(r: f64) -> Matrix {
@@ -38,7 +38,7 @@ pub fn rotation_y(r: f64) -> Matrix {
     data[0][2] = s;
     data[2][0] = -s;
     data[2][2] = c;
-    Matrix { data }
+    Matrix::from(data)
 }
 
 pub fn rotation_z(r: f64) -> Matrix {
@@ -49,7 +49,7 @@ pub fn rotation_z(r: f64) -> Matrix {
     data[0][1] = -s;
     data[1][0] = s;
     data[1][1] = c;
-    Matrix { data }
+    Matrix::from(data)
 }
 
 pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Matrix {
@@ -60,7 +60,27 @@ pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Matrix
     data[1][2] = yz;
     data[2][0] = zx;
     data[2][1] = zy;
-    Matrix { data }
+    Matrix::from(data)
+}
+
+// rotation about an axis through the origin, Rodrigues formula
+pub fn rotation(axis: &Tuple, r: f64) -> Matrix {
+    let k = axis.normalized();
+    let (x, y, z) = (k.x, k.y, k.z);
+    let c = r.cos();
+    let s = r.sin();
+    let t = 1. - c;
+    let mut data = identity_matrix().data;
+    data[0][0] = c + x * x * t;
+    data[0][1] = x * y * t - z * s;
+    data[0][2] = x * z * t + y * s;
+    data[1][0] = x * y * t + z * s;
+    data[1][1] = c + y * y * t;
+    data[1][2] = y * z * t - x * s;
+    data[2][0] = x * z * t - y * s;
+    data[2][1] = y * z * t + x * s;
+    data[2][2] = c + z * z * t;
+    Matrix::from(data)
 }
 
 pub fn view_transform(from: &Tuple, to: &Tuple, up: &Tuple) -> Matrix {
@@ -177,6 +197,32 @@ mod spec {
         assert_eq!(&full_quarter * &p, point(-1., 0., 0.));
     }
 
+    #[test]
+    fn rotating_about_a_cardinal_axis_matches_the_axis_rotations() {
+        let p = point(0., 1., 0.);
+
+        assert_eq!(
+            rotation(&vector(0., 0., 1.), PI / 2.) * p.clone(),
+            rotation_z(PI / 2.) * p
+        );
+        assert_eq!(rotation(&vector(2., 0., 0.), PI / 3.), rotation_x(PI / 3.));
+        assert_eq!(rotation(&vector(0., 1., 0.), PI / 5.), rotation_y(PI / 5.));
+        assert_eq!(
+            rotation(&vector(0., 0., 1.), -PI / 4.),
+            rotation_z(-PI / 4.)
+        );
+    }
+
+    #[test]
+    fn rotating_about_the_diagonal_cycles_the_axes() {
+        let r = rotation(&vector(1., 1., 1.), 2. * PI / 3.);
+
+        assert_eq!(&r * &point(1., 0., 0.), point(0., 1., 0.));
+        assert_eq!(&r * &point(0., 1., 0.), point(0., 0., 1.));
+        assert_eq!(&r * &vector(0., 0., 2.), vector(2., 0., 0.));
+        assert_eq!(&r * &point(1., 1., 1.), point(1., 1., 1.));
+    }
+
     #[test]
     fn a_shearing_transformation_moves_x_in_proportion_to_y() {
         let transform = shearing(1., 0., 0., 0., 0., 0.);
@@ -1,6 +1,6 @@
 use crate::bounds::bound_vector;
 use crate::bounds::Bounds;
-use crate::intersections::intersection;
+use crate::intersections::intersection_with_uv;
 use crate::intersections::Intersection;
 use crate::intersections::EPSILON;
 use crate::materials::material;
@@ -8,12 +8,14 @@ use crate::materials::Material;
 use crate::matrices::identity_matrix;
 use crate::matrices::Matrix;
 use crate::rays::Ray;
+use crate::shapes::shape_base;
 use crate::shapes::Shape;
+use crate::shapes::ShapeBase;
 use crate::shapes::SyncShape;
 use crate::tuples::Tuple;
 use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Triangle {
     p1: Tuple,
     p2: Tuple,
@@ -21,7 +23,11 @@ pub struct Triangle {
     e1: Tuple,
     e2: Tuple,
     normal: Tuple,
-    pub invtransform: Matrix,
+    normals: Option<[Tuple; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    invtransform: Matrix,
+    normal_transform: Matrix,
+    base: ShapeBase,
     pub material: Material,
     bounds: Bounds,
 }
@@ -40,11 +46,37 @@ impl Shape for Triangle {
         &self.invtransform
     }
     fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.normal_transform = invtransform.transpose();
         self.invtransform = invtransform;
     }
+    fn normal_transform(&self) -> &Matrix {
+        &self.normal_transform
+    }
+    fn base(&self) -> &ShapeBase {
+        &self.base
+    }
+    fn base_mut(&mut self) -> &mut ShapeBase {
+        &mut self.base
+    }
     fn local_normal_at(&self, _point: Tuple) -> Tuple {
         self.normal.clone()
     }
+    // texture coordinates at the hit, blended from the corners the same way smooth normals are
+    fn uv_at(&self, hit: &Intersection) -> Option<(f64, f64)> {
+        self.uvs.map(|[(u1, v1), (u2, v2), (u3, v3)]| {
+            let w = 1. - hit.u - hit.v;
+            (
+                u2 * hit.u + u3 * hit.v + u1 * w,
+                v2 * hit.u + v3 * hit.v + v1 * w,
+            )
+        })
+    }
+    fn local_normal_at_hit(&self, point: Tuple, hit: &Intersection) -> Tuple {
+        match &self.normals {
+            Some([n1, n2, n3]) => n2 * hit.u + n3 * hit.v + n1 * (1. - hit.u - hit.v),
+            None => self.local_normal_at(point),
+        }
+    }
     fn local_intersects(&self, rc: Arc<SyncShape>, ray: Ray) -> Vec<Intersection> {
         let d_e2 = ray.direction.cross(&self.e2);
         let det = self.e1.dot(&d_e2);
@@ -66,7 +98,7 @@ impl Shape for Triangle {
         }
 
         let t = f * self.e2.dot(&o_e1);
-        vec![intersection(t, rc)]
+        vec![intersection_with_uv(t, rc, u, v)]
     }
 }
 
@@ -84,15 +116,62 @@ pub fn triangle(p1: Tuple, p2: Tuple, p3: Tuple) -> Triangle {
         e1,
         e2,
         normal,
+        normals: None,
+        uvs: None,
         material,
         invtransform,
+        normal_transform: identity_matrix(),
+        base: shape_base(),
         bounds,
     }
 }
 
+pub fn smooth_triangle(
+    p1: Tuple,
+    p2: Tuple,
+    p3: Tuple,
+    n1: Tuple,
+    n2: Tuple,
+    n3: Tuple,
+) -> Triangle {
+    let mut triangle = triangle(p1, p2, p3);
+    triangle.normals = Some([n1, n2, n3]);
+    triangle
+}
+
+// a triangle with texture coordinates at its corners, like the uv records of an obj file
+pub fn textured_triangle(
+    p1: Tuple,
+    p2: Tuple,
+    p3: Tuple,
+    uv1: (f64, f64),
+    uv2: (f64, f64),
+    uv3: (f64, f64),
+) -> Triangle {
+    let mut triangle = triangle(p1, p2, p3);
+    triangle.uvs = Some([uv1, uv2, uv3]);
+    triangle
+}
+
+impl Triangle {
+    pub fn p1(&self) -> &Tuple {
+        &self.p1
+    }
+    pub fn p2(&self) -> &Tuple {
+        &self.p2
+    }
+    pub fn p3(&self) -> &Tuple {
+        &self.p3
+    }
+    pub fn set_uvs(&mut self, uvs: [(f64, f64); 3]) {
+        self.uvs = Some(uvs);
+    }
+}
+
 #[cfg(test)]
 mod spec {
     use super::*;
+    use crate::intersections::intersection;
     use crate::rays::ray;
     use crate::tuples::point;
     use crate::tuples::vector;
@@ -192,4 +271,103 @@ mod spec {
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 2.);
     }
+
+    fn test_smooth_triangle() -> Triangle {
+        smooth_triangle(
+            point(0., 1., 0.),
+            point(-1., 0., 0.),
+            point(1., 0., 0.),
+            vector(0., 1., 0.),
+            vector(-1., 0., 0.),
+            vector(1., 0., 0.),
+        )
+    }
+
+    #[test]
+    fn constructing_a_smooth_triangle() {
+        let tri = test_smooth_triangle();
+
+        assert_eq!(tri.p1, point(0., 1., 0.));
+        assert_eq!(tri.p2, point(-1., 0., 0.));
+        assert_eq!(tri.p3, point(1., 0., 0.));
+        assert_eq!(
+            tri.normals,
+            Some([vector(0., 1., 0.), vector(-1., 0., 0.), vector(1., 0., 0.)])
+        );
+    }
+
+    #[test]
+    fn an_intersection_with_a_smooth_triangle_stores_u_v() {
+        let tri = Arc::new(test_smooth_triangle());
+        let r = ray(point(-0.2, 0.3, -2.), vector(0., 0., 1.));
+
+        let xs = tri.local_intersects(tri.clone(), r);
+
+        assert!((xs[0].u - 0.45).abs() < 1e-5);
+        assert!((xs[0].v - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn a_smooth_triangle_uses_u_v_to_interpolate_the_normal() {
+        let tri = Arc::new(test_smooth_triangle());
+        let i = intersection_with_uv(1., tri.clone(), 0.45, 0.25);
+
+        let n = tri.normal_at_hit(&point(0., 0., 0.), &i);
+
+        assert_eq!(n, vector(-0.5547, 0.83205, 0.));
+    }
+
+    #[test]
+    fn preparing_the_normal_on_a_smooth_triangle() {
+        let tri = Arc::new(test_smooth_triangle());
+        let i = intersection_with_uv(1., tri.clone(), 0.45, 0.25);
+        let r = ray(point(-0.2, 0.3, -2.), vector(0., 0., 1.));
+        let xs = vec![i];
+
+        let comps = xs[0].prepare_computations(&r, &xs);
+
+        assert_eq!(comps.normalv, vector(-0.5547, 0.83205, 0.));
+    }
+
+    #[test]
+    fn a_flat_triangle_ignores_u_v() {
+        let t = Arc::new(triangle(
+            point(0., 1., 0.),
+            point(-1., 0., 0.),
+            point(1., 0., 0.),
+        ));
+        let i = intersection(1., t.clone());
+
+        assert_eq!(t.normal_at_hit(&point(0., 0.5, 0.), &i), t.normal);
+    }
+
+    #[test]
+    fn a_textured_triangle_interpolates_its_uvs() {
+        let tri = Arc::new(textured_triangle(
+            point(0., 1., 0.),
+            point(-1., 0., 0.),
+            point(1., 0., 0.),
+            (0.5, 1.),
+            (0., 0.),
+            (1., 0.),
+        ));
+        let corner = intersection_with_uv(1., tri.clone(), 1., 0.);
+        let centroid = intersection_with_uv(1., tri.clone(), 1. / 3., 1. / 3.);
+
+        assert_eq!(tri.uv_at(&corner), Some((0., 0.)));
+        let (u, v) = tri.uv_at(&centroid).unwrap();
+        assert!((u - 0.5).abs() < EPSILON);
+        assert!((v - 1. / 3.).abs() < EPSILON);
+    }
+
+    #[test]
+    fn a_plain_triangle_has_no_uvs() {
+        let tri = Arc::new(triangle(
+            point(0., 1., 0.),
+            point(-1., 0., 0.),
+            point(1., 0., 0.),
+        ));
+
+        assert_eq!(tri.uv_at(&intersection(1., tri.clone())), None);
+    }
 }
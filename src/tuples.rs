@@ -1,10 +1,12 @@
 use std::ops::Add;
 use std::ops::Div;
+use std::ops::Index;
 use std::ops::Mul;
 use std::ops::Neg;
 use std::ops::Sub;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tuple {
     pub x: f64,
     pub y: f64,
@@ -22,14 +24,27 @@ impl PartialEq for Tuple {
 }
 
 impl Tuple {
+    pub const ZERO: Tuple = Tuple {
+        x: 0.,
+        y: 0.,
+        z: 0.,
+        w: 0.,
+    };
+
+    #[cfg(test)]
     fn is_point(&self) -> bool {
         self.w == 1.0
     }
+    #[cfg(test)]
     fn is_vector(&self) -> bool {
         self.w == 0.0
     }
     pub fn magnitude(&self) -> f64 {
-        (self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt()
+        self.magnitude_squared().sqrt()
+    }
+    // enough to compare lengths without paying for the sqrt
+    pub fn magnitude_squared(&self) -> f64 {
+        self.x.powi(2) + self.y.powi(2) + self.z.powi(2)
     }
     pub fn normalized(&self) -> Tuple {
         self / self.magnitude()
@@ -48,6 +63,27 @@ impl Tuple {
     pub fn reflect(&self, normal: &Tuple) -> Tuple {
         self - normal * 2. * self.dot(normal)
     }
+    // angle in radians, zero when either vector has no length
+    pub fn angle_between(&self, other: &Tuple) -> f64 {
+        let lengths = self.magnitude() * other.magnitude();
+        if close(lengths, 0.) {
+            return 0.;
+        }
+        // rounding can push the cosine just outside -1..1
+        (self.dot(other) / lengths).clamp(-1., 1.).acos()
+    }
+    // component parallel to other
+    pub fn project_onto(&self, other: &Tuple) -> Tuple {
+        let length = other.dot(other);
+        if close(length, 0.) {
+            return Tuple::ZERO;
+        }
+        other * (self.dot(other) / length)
+    }
+    // component perpendicular to other
+    pub fn reject_from(&self, other: &Tuple) -> Tuple {
+        self - self.project_onto(other)
+    }
 }
 
 impl<'a> Add<Tuple> for &'a Tuple {
@@ -167,8 +203,12 @@ pub fn point(x: f64, y: f64, z: f64) -> Tuple {
 pub fn vector(x: f64, y: f64, z: f64) -> Tuple {
     Tuple { x, y, z, w: 0.0 }
 }
+pub fn origin() -> Tuple {
+    point(0., 0., 0.)
+}
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     pub red: f64,
     pub green: f64,
@@ -178,6 +218,78 @@ pub fn color(red: f64, green: f64, blue: f64) -> Color {
     Color { red, green, blue }
 }
 
+impl Color {
+    pub const BLACK: Color = Color {
+        red: 0.,
+        green: 0.,
+        blue: 0.,
+    };
+    pub const WHITE: Color = Color {
+        red: 1.,
+        green: 1.,
+        blue: 1.,
+    };
+
+    // linear light to the sRGB transfer curve used by image files and displays
+    pub fn to_srgb(&self) -> Color {
+        color(
+            srgb_encode(self.red),
+            srgb_encode(self.green),
+            srgb_encode(self.blue),
+        )
+    }
+    pub fn from_srgb(encoded: &Color) -> Color {
+        color(
+            srgb_decode(encoded.red),
+            srgb_decode(encoded.green),
+            srgb_decode(encoded.blue),
+        )
+    }
+    pub fn min(&self, other: &Color) -> Color {
+        color(
+            self.red.min(other.red),
+            self.green.min(other.green),
+            self.blue.min(other.blue),
+        )
+    }
+    pub fn max(&self, other: &Color) -> Color {
+        color(
+            self.red.max(other.red),
+            self.green.max(other.green),
+            self.blue.max(other.blue),
+        )
+    }
+    // every channel is clamped separately between the channels of lo and hi
+    pub fn clamp(&self, lo: &Color, hi: &Color) -> Color {
+        self.max(lo).min(hi)
+    }
+    pub fn clamped(&self) -> Color {
+        self.clamp(&Color::BLACK, &Color::WHITE)
+    }
+    // the 8 bit channels written to image files, rounded up
+    pub fn to_rgb8(&self) -> (u8, u8, u8) {
+        let c = self.clamped();
+        let byte = |f: f64| (f * 255.).ceil() as u8;
+        (byte(c.red), byte(c.green), byte(c.blue))
+    }
+}
+
+fn srgb_encode(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+fn srgb_decode(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 impl<'a> Add for &'a Color {
     type Output = Color;
 
@@ -235,6 +347,40 @@ impl Mul<f64> for Color {
     }
 }
 
+impl Div<f64> for &Color {
+    type Output = Color;
+
+    fn div(self, other: f64) -> Color {
+        Color {
+            red: self.red / other,
+            green: self.green / other,
+            blue: self.blue / other,
+        }
+    }
+}
+
+impl Div<f64> for Color {
+    type Output = Color;
+
+    fn div(self, other: f64) -> Color {
+        &self / other
+    }
+}
+
+// channels in rgb order
+impl Index<usize> for Color {
+    type Output = f64;
+
+    fn index(&self, channel: usize) -> &f64 {
+        match channel {
+            0 => &self.red,
+            1 => &self.green,
+            2 => &self.blue,
+            _ => panic!("color channel out of bounds: {}", channel),
+        }
+    }
+}
+
 // hadamard product
 impl<'a> Mul<&Color> for &'a Color {
     type Output = Color;
@@ -266,23 +412,10 @@ fn close(a: f64, b: f64) -> bool {
     a == b || (a - b).abs() <= 1e-5
 }
 
-pub fn f_u8(f: f64) -> u8 {
-    clip_range(f, 0.0, 255.0) as u8
-}
-
-fn clip_range(f: f64, low: f64, up: f64) -> f64 {
-    if f > 1.0 {
-        up
-    } else if f < 0.0 {
-        low
-    } else {
-        (f * up).ceil()
-    }
-}
-
 #[cfg(test)]
 mod spec {
     use super::*;
+    use std::f64::consts::PI;
 
     #[test]
     fn a_tuple_with_w_1_is_a_point() {
@@ -476,6 +609,25 @@ mod spec {
         assert_eq!(c * 2.0, color(0.4, 0.6, 0.8));
     }
 
+    #[test]
+    fn dividing_color_by_a_scalar() {
+        let c = color(0.4, 0.6, 0.8);
+        assert_eq!(&c / 2.0, color(0.2, 0.3, 0.4));
+        assert_eq!(c / 2.0, color(0.2, 0.3, 0.4));
+    }
+
+    #[test]
+    fn indexing_color_channels() {
+        let c = color(0.4, 0.6, 0.8);
+        assert_eq!([c[0], c[1], c[2]], [0.4, 0.6, 0.8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "color channel out of bounds: 3")]
+    fn indexing_past_the_blue_channel() {
+        let _ = color(0.4, 0.6, 0.8)[3];
+    }
+
     #[test]
     fn multiplying_colors() {
         let c1 = color(1.0, 0.2, 0.4);
@@ -483,6 +635,46 @@ mod spec {
         assert_eq!(c1 * c2, color(0.9, 0.2, 0.04));
     }
 
+    #[test]
+    fn encoding_linear_colors_to_srgb() {
+        let c = color(0., 0.5, 1.);
+
+        assert_eq!(c.to_srgb(), color(0., 0.73536, 1.));
+        assert_eq!(
+            color(0.001, 0.001, 0.001).to_srgb(),
+            color(0.01292, 0.01292, 0.01292)
+        );
+    }
+
+    #[test]
+    fn srgb_encoding_round_trips() {
+        for c in &[
+            color(0., 0., 0.),
+            color(0.001, 0.2, 0.5),
+            color(0.75, 0.9, 1.),
+        ] {
+            assert_eq!(&Color::from_srgb(&c.to_srgb()), c);
+        }
+    }
+
+    #[test]
+    fn per_channel_minimum_and_maximum_of_colors() {
+        let c1 = color(0.1, 0.9, 0.5);
+        let c2 = color(0.4, 0.3, 0.5);
+
+        assert_eq!(c1.min(&c2), color(0.1, 0.3, 0.5));
+        assert_eq!(c1.max(&c2), color(0.4, 0.9, 0.5));
+    }
+
+    #[test]
+    fn clamping_a_color_channel_by_channel() {
+        let lo = color(0.2, 0.2, 0.2);
+        let hi = color(0.8, 0.8, 0.8);
+
+        assert_eq!(color(0.1, 0.5, 1.5).clamp(&lo, &hi), color(0.2, 0.5, 0.8));
+        assert_eq!(color(-1., 0.8, 0.2).clamp(&lo, &hi), color(0.2, 0.8, 0.2));
+    }
+
     #[test]
     fn reflecting_a_vector_approaching_at_45() {
         let v = vector(1., -1., 0.);
@@ -499,4 +691,57 @@ mod spec {
         let r = v.reflect(&n);
         assert_eq!(r, vector(1., 0., 0.));
     }
+
+    #[test]
+    fn clamping_a_color_into_the_unit_range() {
+        let c = color(1.5, -0.2, 0.5);
+
+        assert_eq!(c.clamped(), color(1., 0., 0.5));
+        assert_eq!(c.to_rgb8(), (255, 0, 128));
+    }
+
+    #[test]
+    fn named_constants_match_their_literal_forms() {
+        assert_eq!(Color::BLACK, color(0., 0., 0.));
+        assert_eq!(Color::WHITE, color(1., 1., 1.));
+        assert_eq!(Tuple::ZERO, vector(0., 0., 0.));
+        assert_eq!(origin(), point(0., 0., 0.));
+    }
+
+    #[test]
+    fn the_angle_between_vectors() {
+        let examples = [
+            (vector(1., 0., 0.), vector(0., 1., 0.), PI / 2.),
+            (vector(1., 2., 3.), vector(1., 2., 3.), 0.),
+            (vector(1., 0., 0.), vector(-2., 0., 0.), PI),
+            (vector(0., 0., 0.), vector(1., 0., 0.), 0.),
+        ];
+        for (a, b, angle) in examples.iter() {
+            assert!(close(a.angle_between(b), *angle));
+        }
+    }
+
+    #[test]
+    fn projecting_a_vector_onto_another() {
+        let v = vector(3., 4., 0.);
+        let x = vector(1., 0., 0.);
+
+        assert_eq!(v.project_onto(&x), vector(3., 0., 0.));
+        assert_eq!(v.reject_from(&x), vector(0., 4., 0.));
+        assert_eq!(v.project_onto(&Tuple::ZERO), Tuple::ZERO);
+        assert_eq!(v.reject_from(&Tuple::ZERO), v);
+    }
+
+    #[test]
+    fn squared_magnitude_of_vectors() {
+        let examples = [
+            vector(1., 0., 0.),
+            vector(1., 2., 3.),
+            vector(-1., -2., -3.),
+            vector(3., 4., 0.),
+        ];
+        for v in examples.iter() {
+            assert!(close(v.magnitude_squared(), v.magnitude().powi(2)));
+        }
+    }
 }
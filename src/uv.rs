@@ -0,0 +1,482 @@
+use crate::canvas::Canvas;
+use crate::matrices::identity_matrix;
+use crate::matrices::Matrix;
+use crate::patterns::Pattern;
+#[cfg(feature = "serde")]
+use crate::patterns::PatternData;
+use crate::ppm::canvas_from_ppm;
+use crate::ppm::PpmError;
+use crate::shapes::SyncShape;
+use crate::tuples::Color;
+use crate::tuples::Tuple;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub type UvFn = fn(&Tuple) -> (f64, f64);
+
+pub fn spherical_uv(point: &Tuple) -> (f64, f64) {
+    let theta = point.x.atan2(point.z);
+    let radius = (point.x.powi(2) + point.y.powi(2) + point.z.powi(2)).sqrt();
+    let phi = (point.y / radius).acos();
+    let raw_u = theta / (2. * PI);
+    let u = 1. - (raw_u + 0.5);
+    let v = 1. - phi / PI;
+    (u, v)
+}
+
+pub fn planar_uv(point: &Tuple) -> (f64, f64) {
+    (point.x.rem_euclid(1.), point.z.rem_euclid(1.))
+}
+
+pub fn cylindrical_uv(point: &Tuple) -> (f64, f64) {
+    let theta = point.x.atan2(point.z);
+    let raw_u = theta / (2. * PI);
+    let u = 1. - (raw_u + 0.5);
+    let v = point.y.rem_euclid(1.);
+    (u, v)
+}
+
+// the mappings above by name, for saving texture maps
+#[cfg(feature = "serde")]
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UvMapping {
+    Spherical,
+    Planar,
+    Cylindrical,
+}
+
+#[cfg(feature = "serde")]
+impl UvMapping {
+    pub fn uv_fn(self) -> UvFn {
+        match self {
+            UvMapping::Spherical => spherical_uv,
+            UvMapping::Planar => planar_uv,
+            UvMapping::Cylindrical => cylindrical_uv,
+        }
+    }
+
+    // None for mappings other than the ones above
+    fn of(uv: UvFn) -> Option<UvMapping> {
+        [
+            UvMapping::Spherical,
+            UvMapping::Planar,
+            UvMapping::Cylindrical,
+        ]
+        .iter()
+        .copied()
+        .find(|m| m.uv_fn() as usize == uv as usize)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Face {
+    Left,
+    Front,
+    Right,
+    Back,
+    Up,
+    Down,
+}
+
+// the face is picked by the largest coordinate, u/v are measured within the face
+pub fn cube_uv(point: &Tuple) -> (Face, f64, f64) {
+    let (x, y, z) = (point.x, point.y, point.z);
+    let coord = x.abs().max(y.abs()).max(z.abs());
+    let wrap = |a: f64| a.rem_euclid(2.) / 2.;
+    if coord == x {
+        (Face::Right, wrap(1. - z), wrap(y + 1.))
+    } else if coord == -x {
+        (Face::Left, wrap(z + 1.), wrap(y + 1.))
+    } else if coord == y {
+        (Face::Up, wrap(x + 1.), wrap(1. - z))
+    } else if coord == -y {
+        (Face::Down, wrap(x + 1.), wrap(z + 1.))
+    } else if coord == z {
+        (Face::Front, wrap(x + 1.), wrap(y + 1.))
+    } else {
+        (Face::Back, wrap(1. - x), wrap(y + 1.))
+    }
+}
+
+pub type SyncUvPattern = dyn UvPattern + Sync + Send;
+
+pub trait UvPattern {
+    fn at_uv(&self, u: f64, v: f64) -> Color;
+    // only uv checkers can be saved
+    #[cfg(feature = "serde")]
+    fn checkers(&self) -> Option<&UvCheckers> {
+        None
+    }
+}
+
+// projects the pattern point onto the uv plane and samples a 2d pattern there
+pub struct TextureMap {
+    pub uv: UvFn,
+    pub pattern: Box<SyncUvPattern>,
+    invtransform: Matrix,
+}
+
+impl std::fmt::Debug for TextureMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "TextureMap ({:?})", self.invtransform)
+    }
+}
+
+pub fn texture_map(pattern: Box<SyncUvPattern>, uv: UvFn) -> TextureMap {
+    let invtransform = identity_matrix();
+    TextureMap {
+        uv,
+        pattern,
+        invtransform,
+    }
+}
+
+impl Pattern for TextureMap {
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.invtransform = invtransform;
+    }
+
+    fn at(&self, point: &Tuple) -> Color {
+        let (u, v) = (self.uv)(point);
+        self.pattern.at_uv(u, v)
+    }
+
+    fn at_surface(
+        &self,
+        shape: Arc<SyncShape>,
+        world_point: &Tuple,
+        uv: Option<(f64, f64)>,
+    ) -> Color {
+        match uv {
+            Some((u, v)) => self.pattern.at_uv(u, v),
+            None => self.at_shape(shape, world_point),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn data(&self) -> Option<PatternData> {
+        Some(PatternData::Uv {
+            mapping: UvMapping::of(self.uv)?,
+            checkers: self.pattern.checkers()?.clone(),
+            invtransform: self.invtransform.clone(),
+        })
+    }
+}
+
+// one 2d pattern per cube face, in the order of the Face variants
+pub struct CubeMap {
+    faces: [Box<SyncUvPattern>; 6],
+    invtransform: Matrix,
+}
+
+impl std::fmt::Debug for CubeMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "CubeMap ({:?})", self.invtransform)
+    }
+}
+
+pub fn cube_map(
+    left: Box<SyncUvPattern>,
+    front: Box<SyncUvPattern>,
+    right: Box<SyncUvPattern>,
+    back: Box<SyncUvPattern>,
+    up: Box<SyncUvPattern>,
+    down: Box<SyncUvPattern>,
+) -> CubeMap {
+    let invtransform = identity_matrix();
+    CubeMap {
+        faces: [left, front, right, back, up, down],
+        invtransform,
+    }
+}
+
+impl Pattern for CubeMap {
+    fn invtransform(&self) -> &Matrix {
+        &self.invtransform
+    }
+
+    fn set_invtransform(&mut self, invtransform: Matrix) {
+        self.invtransform = invtransform;
+    }
+
+    fn at(&self, point: &Tuple) -> Color {
+        let (face, u, v) = cube_uv(point);
+        self.faces[face as usize].at_uv(u, v)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvCheckers {
+    width: f64,
+    height: f64,
+    a: Color,
+    b: Color,
+}
+
+pub fn uv_checkers(width: f64, height: f64, a: Color, b: Color) -> UvCheckers {
+    UvCheckers {
+        width,
+        height,
+        a,
+        b,
+    }
+}
+
+impl UvPattern for UvCheckers {
+    fn at_uv(&self, u: f64, v: f64) -> Color {
+        let u2 = (u * self.width).floor();
+        let v2 = (v * self.height).floor();
+        if (u2 + v2) as i64 % 2 == 0 {
+            self.a.clone()
+        } else {
+            self.b.clone()
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn checkers(&self) -> Option<&UvCheckers> {
+        Some(self)
+    }
+}
+
+// texture image sampled at the nearest texel, v grows from the bottom row up
+#[derive(Clone)]
+pub struct UvImage {
+    canvas: Canvas,
+}
+
+pub fn uv_image(canvas: Canvas) -> UvImage {
+    UvImage { canvas }
+}
+
+pub fn uv_image_from_ppm(text: &str) -> Result<UvImage, PpmError> {
+    canvas_from_ppm(text).map(uv_image)
+}
+
+impl UvPattern for UvImage {
+    fn at_uv(&self, u: f64, v: f64) -> Color {
+        // an empty image has no pixel to show
+        if self.canvas.width == 0 || self.canvas.height == 0 {
+            return Color::BLACK;
+        }
+        let (u, v) = (u.clamp(0., 1.), v.clamp(0., 1.));
+        let x = (u * (self.canvas.width - 1) as f64).round() as usize;
+        let y = ((1. - v) * (self.canvas.height - 1) as f64).round() as usize;
+        self.canvas.pixel_at(x, y).clone()
+    }
+}
+
+#[cfg(test)]
+mod spec {
+    use super::*;
+    use crate::canvas::canvas;
+    use crate::tuples::color;
+    use crate::tuples::point;
+    use hamcrest2::prelude::*;
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    fn assert_uv(uv: (f64, f64), expected: (f64, f64)) {
+        assert_that!(uv.0, close_to(expected.0, 0.0001));
+        assert_that!(uv.1, close_to(expected.1, 0.0001));
+    }
+
+    fn black() -> Color {
+        color(0., 0., 0.)
+    }
+    fn white() -> Color {
+        color(1., 1., 1.)
+    }
+
+    const TEXTURE: &str = "P3\n2 2\n255\n255 0 0  0 255 0\n0 0 255  255 255 255\n";
+
+    #[test]
+    fn a_texture_returns_its_corner_colors() {
+        let image = uv_image_from_ppm(TEXTURE).unwrap();
+
+        assert_eq!(image.at_uv(0., 1.), color(1., 0., 0.));
+        assert_eq!(image.at_uv(1., 1.), color(0., 1., 0.));
+        assert_eq!(image.at_uv(0., 0.), color(0., 0., 1.));
+        assert_eq!(image.at_uv(1., 0.), color(1., 1., 1.));
+    }
+
+    #[test]
+    fn texture_coordinates_are_clamped() {
+        let image = uv_image_from_ppm(TEXTURE).unwrap();
+
+        assert_eq!(image.at_uv(-0.5, 1.5), color(1., 0., 0.));
+        assert_eq!(image.at_uv(2., -1.), color(1., 1., 1.));
+    }
+
+    #[test]
+    fn an_empty_texture_is_black() {
+        for &(width, height) in &[(0, 0), (0, 3), (3, 0)] {
+            let image = uv_image(canvas(width, height));
+
+            assert_eq!(image.at_uv(0.5, 0.5), Color::BLACK);
+        }
+    }
+
+    #[test]
+    fn a_texture_is_sampled_through_its_mapping() {
+        let image = uv_image_from_ppm(TEXTURE).unwrap();
+        let pattern = texture_map(Box::new(image), planar_uv);
+
+        assert_eq!(pattern.at(&point(0.1, 0., 0.9)), color(1., 0., 0.));
+        assert_eq!(pattern.at(&point(0.9, 0., 0.1)), color(1., 1., 1.));
+    }
+
+    #[test]
+    fn checker_pattern_in_2d() {
+        let checkers = uv_checkers(2., 2., black(), white());
+
+        assert_eq!(checkers.at_uv(0., 0.), black());
+        assert_eq!(checkers.at_uv(0.5, 0.), white());
+        assert_eq!(checkers.at_uv(0., 0.5), white());
+        assert_eq!(checkers.at_uv(0.5, 0.5), black());
+        assert_eq!(checkers.at_uv(1., 1.), black());
+    }
+
+    #[test]
+    fn using_a_spherical_mapping_on_a_3d_point() {
+        let sq2 = 2_f64.sqrt() / 2.;
+        let examples = [
+            (point(0., 0., -1.), (0., 0.5)),
+            (point(1., 0., 0.), (0.25, 0.5)),
+            (point(0., 0., 1.), (0.5, 0.5)),
+            (point(-1., 0., 0.), (0.75, 0.5)),
+            (point(0., 1., 0.), (0.5, 1.)),
+            (point(0., -1., 0.), (0.5, 0.)),
+            (point(sq2, sq2, 0.), (0.25, 0.75)),
+        ];
+
+        for (p, uv) in examples.iter() {
+            assert_uv(spherical_uv(p), *uv);
+        }
+    }
+
+    #[test]
+    fn using_a_texture_map_pattern_with_a_spherical_map() {
+        let checkers = uv_checkers(16., 8., black(), white());
+        let pattern = texture_map(Box::new(checkers), spherical_uv);
+        let examples = [
+            (point(0.4315, 0.4670, 0.7719), white()),
+            (point(-0.9654, 0.2552, -0.0534), black()),
+            (point(0.1039, 0.7090, 0.6975), white()),
+            (point(-0.4986, -0.7856, -0.3663), black()),
+            (point(-0.0317, -0.9395, 0.3411), black()),
+            (point(0.4809, -0.7721, 0.4154), black()),
+            (point(0.0285, -0.9612, -0.2745), black()),
+            (point(-0.5734, -0.2162, -0.7903), white()),
+            (point(0.7688, -0.1470, 0.6223), black()),
+            (point(-0.7652, 0.2175, 0.6060), black()),
+        ];
+
+        for (p, c) in examples.iter() {
+            assert_eq!(&pattern.at(p), c);
+        }
+    }
+
+    #[test]
+    fn using_a_planar_mapping_on_a_3d_point() {
+        let examples = [
+            (point(0.25, 0., 0.5), (0.25, 0.5)),
+            (point(0.25, 0., -0.25), (0.25, 0.75)),
+            (point(0.25, 0.5, -0.25), (0.25, 0.75)),
+            (point(1.25, 0., 0.5), (0.25, 0.5)),
+            (point(0.25, 0., -1.75), (0.25, 0.25)),
+            (point(1., 0., -1.), (0., 0.)),
+            (point(0., 0., 0.), (0., 0.)),
+        ];
+
+        for (p, uv) in examples.iter() {
+            assert_uv(planar_uv(p), *uv);
+        }
+    }
+
+    #[test]
+    fn using_a_cylindrical_mapping_on_a_3d_point() {
+        let h = FRAC_1_SQRT_2;
+        let examples = [
+            (point(0., 0., -1.), (0., 0.)),
+            (point(0., 0.5, -1.), (0., 0.5)),
+            (point(0., 1., -1.), (0., 0.)),
+            (point(h, 0.5, -h), (0.125, 0.5)),
+            (point(1., 0.5, 0.), (0.25, 0.5)),
+            (point(h, 0.5, h), (0.375, 0.5)),
+            (point(0., -0.25, 1.), (0.5, 0.75)),
+            (point(-h, 0.5, h), (0.625, 0.5)),
+            (point(-1., 1.25, 0.), (0.75, 0.25)),
+            (point(-h, 0.5, -h), (0.875, 0.5)),
+        ];
+
+        for (p, uv) in examples.iter() {
+            assert_uv(cylindrical_uv(p), *uv);
+        }
+    }
+
+    #[test]
+    fn identifying_the_face_of_a_cube_from_a_point() {
+        let examples = [
+            (point(-1., 0.5, -0.25), Face::Left),
+            (point(1.1, -0.75, 0.8), Face::Right),
+            (point(0.1, 0.6, 0.9), Face::Front),
+            (point(-0.7, 0., -2.), Face::Back),
+            (point(0.5, 1., 0.9), Face::Up),
+            (point(-0.2, -1.3, 1.1), Face::Down),
+        ];
+
+        for (p, face) in examples.iter() {
+            assert_eq!(cube_uv(p).0, *face);
+        }
+    }
+
+    #[test]
+    fn uv_mapping_the_faces_of_a_cube() {
+        let examples = [
+            (point(-0.5, 0.5, 1.), Face::Front, (0.25, 0.75)),
+            (point(0.5, -0.5, 1.), Face::Front, (0.75, 0.25)),
+            (point(0.5, 0.5, -1.), Face::Back, (0.25, 0.75)),
+            (point(-0.5, -0.5, -1.), Face::Back, (0.75, 0.25)),
+            (point(-1., 0.5, -0.5), Face::Left, (0.25, 0.75)),
+            (point(-1., -0.5, 0.5), Face::Left, (0.75, 0.25)),
+            (point(1., 0.5, 0.5), Face::Right, (0.25, 0.75)),
+            (point(1., -0.5, -0.5), Face::Right, (0.75, 0.25)),
+            (point(0.5, 1., -0.5), Face::Up, (0.75, 0.75)),
+            (point(-0.5, 1., 0.5), Face::Up, (0.25, 0.25)),
+            (point(0.5, -1., 0.5), Face::Down, (0.75, 0.75)),
+            (point(-0.5, -1., -0.5), Face::Down, (0.25, 0.25)),
+        ];
+
+        for (p, face, uv) in examples.iter() {
+            let (f, u, v) = cube_uv(p);
+            assert_eq!(f, *face);
+            assert_uv((u, v), *uv);
+        }
+    }
+
+    #[test]
+    fn a_cube_map_samples_the_pattern_of_each_face() {
+        let solid = |c: Color| Box::new(uv_checkers(1., 1., c.clone(), c));
+        let pattern = cube_map(
+            solid(color(1., 1., 0.)),
+            solid(color(0., 1., 1.)),
+            solid(color(1., 0., 0.)),
+            solid(color(0., 1., 0.)),
+            solid(color(1., 0.5, 0.)),
+            solid(color(1., 0., 1.)),
+        );
+
+        assert_eq!(pattern.at(&point(-1., 0., 0.)), color(1., 1., 0.));
+        assert_eq!(pattern.at(&point(0., 0., 1.)), color(0., 1., 1.));
+        assert_eq!(pattern.at(&point(1., 0., 0.)), color(1., 0., 0.));
+        assert_eq!(pattern.at(&point(0., 0., -1.)), color(0., 1., 0.));
+        assert_eq!(pattern.at(&point(0., 1., 0.)), color(1., 0.5, 0.));
+        assert_eq!(pattern.at(&point(0., -1., 0.)), color(1., 0., 1.));
+    }
+}
@@ -1,122 +1,381 @@
+use crate::background::environment_at;
+use crate::intersections::compare_t;
 use crate::intersections::hit;
 use crate::intersections::Comps;
 use crate::intersections::Intersection;
-use crate::lights::PointLight;
+use crate::lights::Light;
 use crate::rays::ray;
 use crate::rays::Ray;
+use crate::shapes::Shape;
 use crate::shapes::SyncShape;
-use crate::tuples::color;
 use crate::tuples::Color;
 use crate::tuples::Tuple;
+use crate::uv::UvImage;
+use std::cell::RefCell;
 use std::sync::Arc;
 
-pub const MAX_REFLECTIONS: i8 = 6;
+thread_local! {
+    static SCRATCH: RefCell<Vec<Intersection>> = const { RefCell::new(Vec::new()) };
+}
+
+pub const MAX_REFLECTIONS: usize = 6;
+
+#[derive(Debug)]
+pub struct PathNode {
+    pub point: Tuple,
+    pub object: Arc<SyncShape>,
+    pub color: Color,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SceneStats {
+    pub primitives: usize,
+    pub lights: usize,
+    pub has_bvh: bool,
+}
 
 #[derive(Clone)]
 pub struct World {
     pub objects: Vec<Arc<SyncShape>>,
-    pub light_sources: Vec<PointLight>,
+    pub light_sources: Vec<Light>,
+    // color of rays that miss every object, unless there is an environment around the scene
+    pub background: Color,
+    pub environment: Option<UvImage>,
+    pub fog: Option<Fog>,
+    // transparent objects tint the light passing through them instead of blocking it
+    pub transparent_shadows: bool,
+}
+
+// surfaces fade towards the fog color the further they are from the ray origin
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub color: Color,
+    pub density: f64,
+}
+
+pub fn fog(color: Color, density: f64) -> Fog {
+    Fog { color, density }
+}
+
+impl Fog {
+    fn apply(&self, surface: Color, distance: f64) -> Color {
+        let amount = 1. - (-self.density * distance).exp();
+        surface * (1. - amount) + &self.color * amount
+    }
 }
 
 pub fn world() -> World {
     World {
         objects: vec![],
         light_sources: vec![],
+        background: Color::BLACK,
+        environment: None,
+        fog: None,
+        transparent_shadows: false,
     }
 }
 
 impl World {
+    // wraps the shape and returns the handle that intersections will point at
+    pub fn add_object<T: Shape + Sync + Send + 'static>(&mut self, shape: T) -> Arc<SyncShape> {
+        let object: Arc<SyncShape> = Arc::new(shape);
+        self.objects.push(object.clone());
+        object
+    }
+
+    pub fn add_light<L: Into<Light>>(&mut self, light: L) {
+        self.light_sources.push(light.into());
+    }
+
+    pub fn complexity(&self) -> SceneStats {
+        SceneStats {
+            primitives: self.objects.iter().map(|o| o.primitive_count()).sum(),
+            lights: self.light_sources.len(),
+            has_bvh: self.objects.iter().any(|o| o.has_bounding_hierarchy()),
+        }
+    }
+
     fn intersects(&self, inray: &Ray) -> Vec<Intersection> {
-        let mut xs: Vec<Intersection> = self
-            .objects
-            .iter()
-            .flat_map(|object| object.intersects(object.clone(), inray))
-            .collect();
-        xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        let mut xs = vec![];
+        self.intersects_into(inray, &mut xs);
         xs
     }
 
-    fn shade_hit(&self, comps: Comps, remaining: i8) -> Color {
+    fn intersects_into(&self, inray: &Ray, xs: &mut Vec<Intersection>) {
+        xs.clear();
+        for object in &self.objects {
+            xs.extend(object.intersects(object.clone(), inray));
+        }
+        xs.sort_unstable_by(|a, b| compare_t(a.t, b.t));
+    }
+
+    // the sorted intersections of the ray in a list that every thread keeps for its next rays
+    // instead of allocating one per ray, f must not trace rays of its own
+    fn with_intersections<T, F: FnOnce(&[Intersection]) -> T>(&self, ray: &Ray, f: F) -> T {
+        SCRATCH.with(|scratch| {
+            let mut xs = scratch.borrow_mut();
+            self.intersects_into(ray, &mut xs);
+            let result = f(&xs);
+            // the hit objects are not kept alive until the next ray
+            xs.clear();
+            result
+        })
+    }
+
+    // emission and ambient are counted once, diffuse and specular once for every light
+    fn surface_color(&self, comps: &Comps) -> Color {
+        comps.object.material().emission.clone() + self.lit_color(comps)
+    }
+
+    // the light the surface itself reflects, the ambient light is the average intensity of the
+    // lights
+    fn lit_color(&self, comps: &Comps) -> Color {
+        let material = comps.object.material();
+        let n = self.light_sources.len().max(1) as f64;
+        let intensity = self
+            .light_sources
+            .iter()
+            .fold(Color::BLACK, |acc, light| &acc + light.intensity())
+            / n;
+        // patterns are looked up once, not once for every light
+        let surface = material.color_at(comps.object.clone(), &comps.over_point, comps.uv);
+        let ambient = material.ambient_lighting(&surface, &intensity);
         self.light_sources
             .iter()
             .map(|light| {
-                let material = comps.object.material();
-
-                let (refl, refr) = if material.reflective > 0. && material.transparency > 0. {
-                    let reflectance = comps.schlick();
-                    (reflectance, 1. - reflectance)
-                } else {
-                    (1., 1.)
-                };
-
-                return material.lighting(
-                    comps.object.clone(),
+                material.direct_lighting(
+                    &surface,
                     light,
                     &comps.over_point,
                     &comps.eyev,
                     &comps.normalv,
-                    self.is_shadowed(light, &comps.over_point),
-                ) + self.reflected_color(&comps, remaining) * refl
-                    + self.refracted_color(&comps, remaining) * refr;
+                    &light.attenuation_at(&comps.over_point, comps.time, self),
+                )
             })
-            .fold(color(0., 0., 0.), |acc, color| acc + color)
+            .fold(ambient, |acc, color| acc + color)
     }
 
-    pub fn color_at(&self, ray: &Ray, remaining: i8) -> Color {
-        let xs = &self.intersects(ray);
-        hit(xs)
-            .map(|hit| self.shade_hit(hit.prepare_computations(ray, xs), remaining))
-            .unwrap_or_else(|| color(0., 0., 0.))
+    fn shade_hit(&self, comps: Comps, remaining: usize) -> Color {
+        let material = comps.object.material();
+        let (reflective, transparency) = (material.reflective, material.transparency);
+        // secondary rays do not depend on the lights, they are traced once per hit
+        let reflectance = if reflective > 0. && transparency > 0. {
+            Some(comps.schlick())
+        } else {
+            None
+        };
+
+        if material.energy_conserving {
+            // a `reflective` share of the incoming light is mirrored and a `transparency` share
+            // passes through, only the rest lights the surface, emission is not light it receives
+            let absorbed = (1. - reflective - transparency).max(0.);
+            let (refl, refr) = match reflectance {
+                Some(r) => (
+                    (reflective + transparency) * r,
+                    (reflective + transparency) * (1. - r),
+                ),
+                None => (reflective, transparency),
+            };
+            material.emission.clone()
+                + self.lit_color(&comps) * absorbed
+                + self.reflected_light(&comps, remaining) * refl
+                + self.refracted_light(&comps, remaining) * refr
+        } else {
+            let (refl, refr) = reflectance.map_or((1., 1.), |r| (r, 1. - r));
+            self.surface_color(&comps)
+                + self.reflected_color(&comps, remaining) * refl
+                + self.refracted_color(&comps, remaining) * refr
+        }
+    }
+
+    // the shading state at the nearest hit of the ray, None when it misses everything
+    pub fn hit_comps(&self, ray: &Ray) -> Option<Comps> {
+        self.with_intersections(ray, |xs| {
+            hit(xs).map(|hit| hit.prepare_computations(ray, xs))
+        })
+    }
+
+    // ray parameter of the nearest hit, without preparing the shading state
+    pub fn hit_distance(&self, ray: &Ray) -> Option<f64> {
+        self.with_intersections(ray, |xs| hit(xs).map(|hit| hit.t))
+    }
+
+    // index into objects of the top level object owning the nearest hit, intersections of
+    // group children point at wrapped copies so every object is intersected on its own
+    pub fn hit_object_index(&self, ray: &Ray) -> Option<usize> {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter_map(|(i, object)| {
+                let xs = object.intersects(object.clone(), ray);
+                hit(&xs).map(|hit| (i, hit.t))
+            })
+            .min_by(|a, b| compare_t(a.1, b.1))
+            .map(|(i, _)| i)
+    }
+
+    pub fn color_at(&self, ray: &Ray, remaining: usize) -> Color {
+        self.hit_comps(ray)
+            .map(|comps| {
+                let t = comps.t;
+                let surface = self.shade_hit(comps, remaining);
+                match &self.fog {
+                    Some(fog) => fog.apply(surface, t),
+                    None => surface,
+                }
+            })
+            .unwrap_or_else(|| match &self.environment {
+                Some(image) => environment_at(image, &ray.direction),
+                None => self.background.clone(),
+            })
+    }
+
+    // follows a single ray through its reflections (or refractions when the surface is not
+    // reflective) and records the direct lighting picked up at every bounce
+    pub fn trace_path(&self, inray: &Ray) -> Vec<PathNode> {
+        let mut path = vec![];
+        let mut current = ray(inray.origin.clone(), inray.direction.clone());
+        let mut weight = 1.;
+        for _ in 0..=MAX_REFLECTIONS {
+            let comps = match self.hit_comps(&current) {
+                Some(comps) => comps,
+                None => break,
+            };
+            let material = comps.object.material();
+            let direct = self.surface_color(&comps);
+            path.push(PathNode {
+                point: comps.point.clone(),
+                object: comps.object.clone(),
+                color: direct * weight,
+            });
+
+            current = if material.reflective > 0. {
+                weight *= material.reflective;
+                ray(comps.over_point.clone(), comps.reflectv.clone()).at_time(comps.time)
+            } else if material.transparency > 0. && !comps.is_internal_reflection() {
+                weight *= material.transparency;
+                ray(comps.under_point.clone(), comps.refracted_direction()).at_time(comps.time)
+            } else {
+                break;
+            };
+        }
+        path
+    }
+
+    #[cfg(test)]
+    fn is_shadowed(&self, light: &Light, point: &Tuple) -> bool {
+        light.intensity_at(point, self) == 0.
+    }
+
+    // max_t is measured in lengths of the direction, 1 for a direction that ends at the light
+    pub fn is_blocked(&self, point: &Tuple, direction: &Tuple, max_t: f64) -> bool {
+        self.any_hit_before(&ray(point.clone(), direction.clone()), max_t)
+    }
+
+    // whether a shadow casting object is hit in 0..max_t, stops at the first one it finds
+    // instead of collecting and sorting every intersection
+    pub fn any_hit_before(&self, ray: &Ray, max_t: f64) -> bool {
+        self.objects.iter().any(|object| {
+            object
+                .intersects(object.clone(), ray)
+                .iter()
+                .any(|x| x.t >= 0. && x.t < max_t && x.object.material().casts_shadow)
+        })
     }
 
-    fn is_shadowed(&self, light: &PointLight, point: &Tuple) -> bool {
-        let v = &light.position - point;
-        let distance = v.magnitude();
-        let direction = v.normalized();
-        let r = ray(point.clone(), direction);
-        let intersections = self.intersects(&r);
-        hit(&intersections).map_or(false, |h| h.t < distance)
+    // light passing from the point along the direction, opaque objects block it entirely and
+    // transparent objects filter it through their color at every crossing before max_t
+    pub fn transmittance(&self, point: &Tuple, direction: &Tuple, max_t: f64, time: f64) -> Color {
+        let r = ray(point.clone(), direction.clone()).at_time(time);
+        let mut light = Color::WHITE;
+        for x in self.shadow_casters(&r) {
+            if x.t < 0. || x.t >= max_t {
+                continue;
+            }
+            let material = x.object.material();
+            if material.transparency == 0. || !self.transparent_shadows {
+                return Color::BLACK;
+            }
+            light = light * (&material.color * material.transparency);
+        }
+        light
+    }
+
+    fn shadow_casters(&self, r: &Ray) -> Vec<Intersection> {
+        let mut xs = self.intersects(r);
+        xs.retain(|x| x.object.material().casts_shadow);
+        xs
     }
 
-    fn reflected_color(&self, comps: &Comps, remaining: i8) -> Color {
+    fn reflected_color(&self, comps: &Comps, remaining: usize) -> Color {
+        self.reflected_light(comps, remaining) * comps.object.material().reflective
+    }
+
+    // the light arriving along the reflected ray, before the material weights it
+    fn reflected_light(&self, comps: &Comps, remaining: usize) -> Color {
         if remaining < 1 || comps.object.material().reflective == 0. {
-            color(0., 0., 0.)
+            Color::BLACK
         } else {
-            let reflect_ray = ray(comps.over_point.clone(), comps.reflectv.clone());
-            self.color_at(&reflect_ray, remaining - 1) * comps.object.material().reflective
+            let reflect_ray =
+                ray(comps.over_point.clone(), comps.reflectv.clone()).at_time(comps.time);
+            self.color_at(&reflect_ray, remaining - 1)
         }
     }
 
-    fn refracted_color(&self, comps: &Comps, remaining: i8) -> Color {
+    fn refracted_color(&self, comps: &Comps, remaining: usize) -> Color {
+        self.refracted_light(comps, remaining) * comps.object.material().transparency
+    }
+
+    // the light arriving along the refracted ray, before the material weights it
+    fn refracted_light(&self, comps: &Comps, remaining: usize) -> Color {
         if remaining == 0 {
-            return color(0., 0., 0.);
+            return Color::BLACK;
         }
         if comps.object.material().transparency == 0. {
-            return color(0., 0., 0.);
+            return Color::BLACK;
         }
         if comps.is_internal_reflection() {
-            return color(0., 0., 0.);
+            return Color::BLACK;
         }
-        let refract_ray = ray(comps.under_point.clone(), comps.refracted_direction());
-        self.color_at(&refract_ray, remaining - 1) * comps.object.material().transparency
+        let refract_ray =
+            ray(comps.under_point.clone(), comps.refracted_direction()).at_time(comps.time);
+        self.color_at(&refract_ray, remaining - 1)
     }
 }
 
 #[cfg(test)]
 pub mod spec {
     use super::*;
+    use crate::bounds::Bounds;
+    use crate::canvas::canvas;
+    use crate::cubes::cube;
+    use crate::groups::group;
     use crate::intersections::intersection;
+    use crate::lights::directional_light;
     use crate::lights::point_light;
+    use crate::materials::material;
+    use crate::materials::Material;
+    use crate::matrices::Matrix;
+    use crate::obj_file::parse_obj_with_materials;
     use crate::patterns::spec::test_pattern;
-    use crate::planes::plane;
+    use crate::planes::{plane, Plane};
     use crate::rays::ray;
+    use crate::shapes::ShapeBase;
+    use crate::spheres::glass_sphere;
     use crate::spheres::sphere;
+    use crate::spheres::Sphere;
+    use crate::transformations::rotation_x;
     use crate::transformations::scaling;
     use crate::transformations::translation;
+    use crate::triangles::triangle;
     use crate::tuples::color;
     use crate::tuples::point;
     use crate::tuples::vector;
+    use crate::uv::uv_image;
+    use crate::uv::{planar_uv, texture_map, uv_checkers};
     use hamcrest2::prelude::*;
+    use std::f64::consts::PI;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     pub fn default_world() -> World {
         let mut s1 = sphere();
@@ -124,10 +383,14 @@ pub mod spec {
         s1.material.diffuse = 0.7;
         s1.material.specular = 0.2;
         let mut s2 = sphere();
-        s2.invtransform = scaling(0.5, 0.5, 0.5).inverse();
+        s2.set_transform(scaling(0.5, 0.5, 0.5));
         World {
             objects: vec![Arc::new(s1), Arc::new(s2)],
-            light_sources: vec![point_light(point(-10., 10., -10.), color(1., 1., 1.))],
+            light_sources: vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()],
+            background: Color::BLACK,
+            environment: None,
+            fog: None,
+            transparent_shadows: false,
         }
     }
 
@@ -138,15 +401,27 @@ pub mod spec {
         assert_eq!(w.light_sources, vec!());
     }
 
+    #[test]
+    fn adding_objects_and_lights_to_a_world() {
+        let mut w = world();
+
+        let s = w.add_object(sphere());
+        w.add_light(point_light(point(-10., 10., -10.), color(1., 1., 1.)));
+
+        assert_eq!(w.objects.len(), 1);
+        assert!(Arc::ptr_eq(&w.objects[0], &s));
+        assert_eq!(w.light_sources.len(), 1);
+    }
+
     #[test]
     fn the_default_world() {
-        let light = point_light(point(-10., 10., -10.), color(1., 1., 1.));
+        let light = point_light(point(-10., 10., -10.), color(1., 1., 1.)).into();
         let mut s1 = sphere();
         s1.material.color = color(0.8, 1., 0.6);
         s1.material.diffuse = 0.7;
         s1.material.specular = 0.2;
         let mut s2 = sphere();
-        s2.invtransform = scaling(0.5, 0.5, 0.5).inverse();
+        s2.set_transform(scaling(0.5, 0.5, 0.5));
         let rc1: Arc<SyncShape> = Arc::new(s1);
         let rc2: Arc<SyncShape> = Arc::new(s2);
 
@@ -171,6 +446,29 @@ pub mod spec {
         assert_eq!(xs[3].t, 6.);
     }
 
+    #[test]
+    fn reused_intersection_lists_prepare_the_same_hit() {
+        let mut w = default_world();
+        let glass = Arc::new(glass_sphere());
+        w.objects.push(glass.clone());
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+        let xs = w.intersects(&r);
+        let expected = hit(&xs).unwrap().prepare_computations(&r, &xs);
+
+        for _ in 0..2 {
+            let comps = w.hit_comps(&r).unwrap();
+            assert_eq!(comps.t, expected.t);
+            assert_eq!(comps.point, expected.point);
+            assert_eq!(comps.normalv, expected.normalv);
+            assert_eq!((comps.n1, comps.n2), (expected.n1, expected.n2));
+            assert_eq!(w.hit_distance(&r), Some(expected.t));
+        }
+        drop(expected);
+        drop(xs);
+        // the thread's lists do not hold on to the objects between rays
+        assert_eq!(Arc::strong_count(&glass), 2);
+    }
+
     #[test]
     fn shading_an_intersection() {
         let w = default_world();
@@ -187,7 +485,7 @@ pub mod spec {
     #[test]
     fn shading_an_intersection_from_the_inside() {
         let mut w = default_world();
-        w.light_sources = vec![point_light(point(0., 0.25, 0.), color(1., 1., 1.))];
+        w.light_sources = vec![point_light(point(0., 0.25, 0.), color(1., 1., 1.)).into()];
         let r = ray(point(0., 0., 0.), vector(0., 0., 1.));
         let shape = w.objects[1].clone();
         let i = intersection(0.5, shape);
@@ -201,10 +499,10 @@ pub mod spec {
     #[test]
     fn shade_hit_is_given_an_intersection_in_shadow() {
         let mut w = default_world();
-        w.light_sources = vec![point_light(point(0., 0., -10.), color(1., 1., 1.))];
+        w.light_sources = vec![point_light(point(0., 0., -10.), color(1., 1., 1.)).into()];
         let s1 = sphere();
         let mut s2 = sphere();
-        s2.invtransform = translation(0., 0., 10.).inverse();
+        s2.set_transform(translation(0., 0., 10.));
         let s2rc = Arc::new(s2);
         w.objects.append(&mut vec![Arc::new(s1), s2rc.clone()]);
         let r = ray(point(0., 0., 5.), vector(0., 0., 1.));
@@ -216,6 +514,26 @@ pub mod spec {
         assert_that!(c, eq(color(0.1, 0.1, 0.1)));
     }
 
+    #[test]
+    fn an_emissive_surface_glows_in_shadow() {
+        let mut w = default_world();
+        w.light_sources = vec![point_light(point(0., 0., -10.), color(1., 1., 1.)).into()];
+        let s1 = sphere();
+        let mut s2 = sphere();
+        s2.set_transform(translation(0., 0., 10.));
+        s2.material.ambient = 0.;
+        s2.material.emission = color(0.5, 0., 0.);
+        let s2rc = Arc::new(s2);
+        w.objects.append(&mut vec![Arc::new(s1), s2rc.clone()]);
+        let r = ray(point(0., 0., 5.), vector(0., 0., 1.));
+        let i = intersection(4., s2rc.clone());
+
+        let comps = i.prepare_computations(&r, &[]);
+        let c = w.shade_hit(comps, MAX_REFLECTIONS);
+
+        assert_that!(c, eq(color(0.5, 0., 0.)));
+    }
+
     #[test]
     fn the_color_when_a_ray_misses() {
         let w = default_world();
@@ -226,6 +544,58 @@ pub mod spec {
         assert_eq!(c, color(0., 0., 0.));
     }
 
+    #[test]
+    fn the_color_when_a_ray_misses_shows_the_background() {
+        let mut w = default_world();
+        w.background = color(0.5, 0.7, 1.0);
+        let r = ray(point(0., 0., -5.), vector(0., 1., 0.));
+
+        let c = w.color_at(&r, MAX_REFLECTIONS);
+
+        assert_eq!(c, color(0.5, 0.7, 1.0));
+    }
+
+    #[test]
+    fn a_missed_ray_samples_the_environment() {
+        let mut image = canvas(1, 2);
+        image.write_pixel(0, 0, color(1., 1., 1.));
+        image.write_pixel(0, 1, color(0., 0., 1.));
+        let mut w = default_world();
+        w.environment = Some(uv_image(image));
+        let up = ray(point(0., 0., -5.), vector(0., 1., 0.));
+        let down = ray(point(0., 0., -5.), vector(0., -1., 0.));
+
+        assert_eq!(w.color_at(&up, MAX_REFLECTIONS), color(1., 1., 1.));
+        assert_eq!(w.color_at(&down, MAX_REFLECTIONS), color(0., 0., 1.));
+    }
+
+    #[test]
+    fn fog_hides_distant_surfaces() {
+        let mut near = plane();
+        near.set_transform(rotation_x(PI / 2.) * translation(0., 1., 0.));
+        let mut far = plane();
+        far.set_transform(rotation_x(PI / 2.) * translation(0., 100., 0.));
+        let fog_color = color(0.5, 0.5, 0.5);
+        let r = ray(point(0., 0., 0.), vector(0., 0., 1.));
+        let clear = |shape: Plane| {
+            let mut w = default_world();
+            w.objects = vec![Arc::new(shape)];
+            w
+        };
+        let foggy = |shape: Plane| {
+            let mut w = clear(shape);
+            w.fog = Some(fog(fog_color.clone(), 0.05));
+            w
+        };
+
+        let near_clear = clear(near.clone()).color_at(&r, MAX_REFLECTIONS);
+        let near_foggy = foggy(near).color_at(&r, MAX_REFLECTIONS);
+        let far_foggy = foggy(far).color_at(&r, MAX_REFLECTIONS);
+
+        assert!((near_foggy.red - near_clear.red).abs() < 0.05);
+        assert!((far_foggy.red - fog_color.red).abs() < 0.01);
+    }
+
     #[test]
     fn the_color_when_a_ray_hits() {
         let w = default_world();
@@ -244,11 +614,11 @@ pub mod spec {
         s1.material.specular = 0.2;
         s1.material.ambient = 1.;
         let mut s2 = sphere();
-        s2.invtransform = scaling(0.5, 0.5, 0.5).inverse();
+        s2.set_transform(scaling(0.5, 0.5, 0.5));
         s2.material.ambient = 1.;
         let mut w = world();
         w.objects = vec![Arc::new(s1), Arc::new(s2)];
-        w.light_sources = vec![point_light(point(-10., 10., -10.), color(1., 1., 1.))];
+        w.light_sources = vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()];
         let r = ray(point(0., 0., 0.75), vector(0., 0., -1.));
 
         let c = w.color_at(&r, MAX_REFLECTIONS);
@@ -284,6 +654,212 @@ pub mod spec {
         assert_that!(w.is_shadowed(&w.light_sources[0], &p), is(false));
     }
 
+    #[test]
+    fn there_is_no_shadow_from_an_object_that_does_not_cast_shadows() {
+        let mut w = default_world();
+        let mut outer = sphere();
+        outer.material.casts_shadow = false;
+        let mut inner = sphere();
+        inner.material.casts_shadow = false;
+        inner.set_transform(scaling(0.5, 0.5, 0.5));
+        w.objects = vec![Arc::new(outer), Arc::new(inner)];
+        let p = point(10., -10., 10.);
+
+        assert_that!(w.is_shadowed(&w.light_sources[0], &p), is(false));
+    }
+
+    #[test]
+    fn a_light_with_radius_casts_a_penumbra_at_a_shadow_edge() {
+        let mut slab = cube();
+        slab.set_transform(translation(-5., 2.5, 0.) * scaling(5., 0.1, 5.));
+        let mut w = world();
+        w.objects = vec![Arc::new(slab)];
+        let mut light = point_light(point(0., 5., 0.), color(1., 1., 1.));
+        let hard = Light::from(light.clone());
+
+        assert_eq!(hard.intensity_at(&point(1., 0., 0.), &w), 1.);
+        assert_eq!(hard.intensity_at(&point(-1., 0., 0.), &w), 0.);
+
+        light.radius = 1.;
+        light.samples = 32;
+        let edge = Light::from(light).intensity_at(&point(0., 0., 0.), &w);
+
+        assert!(edge > 0. && edge < 1., "intensity at the edge is {}", edge);
+    }
+
+    #[test]
+    fn a_red_transparent_sphere_casts_a_reddish_partial_shadow() {
+        let mut glass = sphere();
+        glass.material.color = color(1., 0., 0.);
+        glass.material.transparency = 0.5;
+        let mut w = world();
+        w.transparent_shadows = true;
+        w.objects = vec![Arc::new(glass)];
+        let light: Light = point_light(point(0., 10., 0.), color(1., 1., 1.)).into();
+
+        let shadow = light.attenuation_at(&point(0., -5., 0.), 0., &w);
+
+        assert_eq!(shadow, color(0.25, 0., 0.));
+        assert_eq!(
+            light.attenuation_at(&point(5., -5., 0.), 0., &w),
+            color(1., 1., 1.)
+        );
+    }
+
+    #[test]
+    fn transparent_objects_cast_black_shadows_unless_enabled() {
+        let mut glass = sphere();
+        glass.material.color = color(1., 0., 0.);
+        glass.material.transparency = 0.5;
+        let mut w = world();
+        w.objects = vec![Arc::new(glass)];
+        let light: Light = point_light(point(0., 10., 0.), color(1., 1., 1.)).into();
+
+        let shadow = light.attenuation_at(&point(0., -5., 0.), 0., &w);
+
+        assert_eq!(shadow, Color::BLACK);
+    }
+
+    #[test]
+    fn an_opaque_object_still_casts_a_black_shadow() {
+        let w = default_world();
+        let light = &w.light_sources[0];
+
+        assert_eq!(
+            light.attenuation_at(&point(10., -10., 10.), 0., &w),
+            color(0., 0., 0.)
+        );
+    }
+
+    #[test]
+    fn a_directional_light_illuminates_objects_at_any_depth_alike() {
+        let mut near = sphere();
+        near.set_transform(translation(-3., 0., 0.));
+        let mut far = sphere();
+        far.set_transform(translation(3., 0., 20.));
+        let mut w = world();
+        w.objects = vec![Arc::new(near), Arc::new(far)];
+        w.light_sources = vec![directional_light(vector(0., -1., 0.), color(1., 1., 1.)).into()];
+
+        let c1 = w.color_at(&ray(point(-3., 5., 0.), vector(0., -1., 0.)), 0);
+        let c2 = w.color_at(&ray(point(3., 5., 20.), vector(0., -1., 0.)), 0);
+
+        assert_eq!(c1, c2);
+        assert_eq!(c1, color(1.9, 1.9, 1.9));
+    }
+
+    // a sphere that counts how many times it was intersected
+    #[derive(Debug)]
+    struct CountingSphere {
+        sphere: Sphere,
+        calls: AtomicUsize,
+    }
+    impl Shape for CountingSphere {
+        fn material(&self) -> &Material {
+            self.sphere.material()
+        }
+        fn set_material(&mut self, material: Material) {
+            self.sphere.set_material(material);
+        }
+        fn invtransform(&self) -> &Matrix {
+            self.sphere.invtransform()
+        }
+        fn set_invtransform(&mut self, invtransform: Matrix) {
+            self.sphere.set_invtransform(invtransform);
+        }
+        fn normal_transform(&self) -> &Matrix {
+            self.sphere.normal_transform()
+        }
+        fn base(&self) -> &ShapeBase {
+            self.sphere.base()
+        }
+        fn base_mut(&mut self) -> &mut ShapeBase {
+            self.sphere.base_mut()
+        }
+        fn local_normal_at(&self, local_point: Tuple) -> Tuple {
+            self.sphere.local_normal_at(local_point)
+        }
+        fn local_intersects(&self, rc: Arc<SyncShape>, local_ray: Ray) -> Vec<Intersection> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.sphere.local_intersects(rc, local_ray)
+        }
+        fn local_bounds(&self) -> Bounds {
+            self.sphere.local_bounds()
+        }
+    }
+
+    #[test]
+    fn any_hit_before_stops_at_the_first_blocker() {
+        let near = sphere();
+        let far = Arc::new(CountingSphere {
+            sphere: sphere(),
+            calls: AtomicUsize::new(0),
+        });
+        let mut w = world();
+        w.objects = vec![Arc::new(near), far.clone()];
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+
+        assert!(w.any_hit_before(&r, 10.));
+        assert_eq!(far.calls.load(Ordering::Relaxed), 0);
+        assert!(!w.any_hit_before(&r, 3.));
+        assert_eq!(far.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn a_directional_light_is_shadowed_by_objects_at_any_distance() {
+        let mut s = sphere();
+        s.set_transform(translation(0., 1000., 0.));
+        let mut w = world();
+        w.objects = vec![Arc::new(s)];
+        let light = directional_light(vector(0., -1., 0.), color(1., 1., 1.)).into();
+
+        assert_that!(w.is_shadowed(&light, &point(0., 0., 0.)), is(true));
+        assert_that!(w.is_shadowed(&light, &point(5., 0., 0.)), is(false));
+    }
+
+    #[test]
+    fn reporting_the_complexity_of_a_world() {
+        let mut g = group();
+        g.add_child(triangle(
+            point(0., 1., 0.),
+            point(-1., 0., 0.),
+            point(1., 0., 0.),
+        ));
+        g.add_child(triangle(
+            point(0., 2., 0.),
+            point(-1., 1., 0.),
+            point(1., 1., 0.),
+        ));
+        g.add_child(triangle(
+            point(0., 3., 0.),
+            point(-1., 2., 0.),
+            point(1., 2., 0.),
+        ));
+        let mut w = world();
+        w.objects = vec![Arc::new(g)];
+        w.light_sources = vec![
+            point_light(point(-10., 10., -10.), color(1., 1., 1.)).into(),
+            point_light(point(10., 10., -10.), color(1., 1., 1.)).into(),
+        ];
+
+        assert_eq!(
+            w.complexity(),
+            SceneStats {
+                primitives: 3,
+                lights: 2,
+                has_bvh: true
+            }
+        );
+        assert_eq!(
+            default_world().complexity(),
+            SceneStats {
+                primitives: 2,
+                lights: 1,
+                has_bvh: false
+            }
+        );
+    }
+
     #[test]
     fn the_reflected_color_for_nonreflective_material() {
         let r = ray(point(0., 0., 0.), vector(0., 0., 1.));
@@ -292,12 +868,16 @@ pub mod spec {
         s1.material.diffuse = 0.7;
         s1.material.specular = 0.2;
         let mut s2 = sphere();
-        s2.invtransform = scaling(0.5, 0.5, 0.5).inverse();
+        s2.set_transform(scaling(0.5, 0.5, 0.5));
         s2.material.ambient = 1.;
         let shape = Arc::new(s2);
         let w = World {
             objects: vec![Arc::new(s1), shape.clone()],
-            light_sources: vec![point_light(point(-10., 10., -10.), color(1., 1., 1.))],
+            light_sources: vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()],
+            background: Color::BLACK,
+            environment: None,
+            fog: None,
+            transparent_shadows: false,
         };
         let i = intersection(1., shape.clone());
 
@@ -311,7 +891,7 @@ pub mod spec {
     fn the_reflected_color_for_a_reflective_material() {
         let mut shape = plane();
         shape.material.reflective = 0.5;
-        shape.invtransform = translation(0., -1., 0.).inverse();
+        shape.set_transform(translation(0., -1., 0.));
         let s = Arc::new(shape);
         let mut w = default_world();
         w.objects.push(s.clone());
@@ -329,7 +909,7 @@ pub mod spec {
     fn shade_hit_with_a_reflective_material() {
         let mut shape = plane();
         shape.material.reflective = 0.5;
-        shape.invtransform = translation(0., -1., 0.).inverse();
+        shape.set_transform(translation(0., -1., 0.));
         let s = Arc::new(shape);
         let mut w = default_world();
         w.objects.push(s.clone());
@@ -343,16 +923,50 @@ pub mod spec {
         assert_eq!(c, color(0.87675, 0.92433, 0.82917));
     }
 
+    #[test]
+    fn shade_hit_adds_the_reflection_once_for_several_lights() {
+        let mut shape = plane();
+        shape.material.reflective = 0.5;
+        shape.set_transform(translation(0., -1., 0.));
+        let s = Arc::new(shape);
+        let mut w = default_world();
+        w.objects.push(s.clone());
+        w.light_sources
+            .push(point_light(point(10., 10., -10.), color(0.5, 0.5, 0.5)).into());
+        let sq2 = 2_f64.sqrt();
+        let r = ray(point(0., 0., -3.), vector(0., -sq2 / 2., sq2 / 2.));
+        let i = intersection(sq2, s.clone());
+        let comps = i.prepare_computations(&r, &[]);
+        let surface = w.surface_color(&comps);
+        let reflected = w.reflected_color(&comps, MAX_REFLECTIONS);
+
+        let c = w.shade_hit(comps, MAX_REFLECTIONS);
+
+        assert_eq!(c, surface + reflected);
+    }
+
+    #[test]
+    fn ambient_light_is_counted_once_for_several_lights() {
+        let mut w = default_world();
+        w.add_light(point_light(point(-10., 10., -10.), color(1., 1., 1.)));
+        let r = ray(point(0., 0., -5.), vector(0., 0., 1.));
+
+        let c = w.color_at(&r, MAX_REFLECTIONS);
+
+        // twice the single light shading less the second copy of the ambient 0.1 * (0.8, 1, 0.6)
+        assert_eq!(c, color(0.68132, 0.85166, 0.511));
+    }
+
     #[test]
     fn color_at_with_mutually_reflective_surfaces() {
         let mut lower = plane();
         lower.material.reflective = 1.;
-        lower.invtransform = translation(0., -1., 0.).inverse();
+        lower.set_transform(translation(0., -1., 0.));
         let mut upper = plane();
         upper.material.reflective = 1.;
-        upper.invtransform = translation(0., 1., 0.).inverse();
+        upper.set_transform(translation(0., 1., 0.));
         let mut w = world();
-        w.light_sources = vec![point_light(point(0., 0., 0.), color(1., 1., 1.))];
+        w.light_sources = vec![point_light(point(0., 0., 0.), color(1., 1., 1.)).into()];
         w.objects = vec![Arc::new(lower), Arc::new(upper)];
         let r = ray(point(0., 0., 0.), vector(0., 1., 0.));
 
@@ -363,7 +977,7 @@ pub mod spec {
     fn the_reflected_color_at_maximum_recursive_depth() {
         let mut shape = plane();
         shape.material.reflective = 0.5;
-        shape.invtransform = translation(0., -1., 0.).inverse();
+        shape.set_transform(translation(0., -1., 0.));
         let s = Arc::new(shape);
         let mut w = default_world();
         w.objects.push(s.clone());
@@ -444,10 +1058,10 @@ pub mod spec {
         a.material.diffuse = 0.7;
         a.material.specular = 0.2;
         a.material.ambient = 1.;
-        a.material.pattern = Some(Box::new(test_pattern()));
+        a.material.pattern = Some(Arc::new(test_pattern()));
         let a = Arc::new(a);
         let mut b = sphere();
-        b.invtransform = scaling(0.5, 0.5, 0.5).inverse();
+        b.set_transform(scaling(0.5, 0.5, 0.5));
         b.material.transparency = 1.;
         b.material.refractive_index = 1.5;
         let b = Arc::new(b);
@@ -471,14 +1085,14 @@ pub mod spec {
     #[test]
     fn shade_hit_with_a_transparent_material() {
         let mut floor = plane();
-        floor.invtransform = translation(0., -1., 0.).inverse();
+        floor.set_transform(translation(0., -1., 0.));
         floor.material.transparency = 0.5;
         floor.material.refractive_index = 1.5;
         let floor = Arc::new(floor);
         let mut ball = sphere();
         ball.material.color = color(1., 0., 0.);
         ball.material.ambient = 0.5;
-        ball.invtransform = translation(0., -3.5, -0.5).inverse();
+        ball.set_transform(translation(0., -3.5, -0.5));
         let ball = Arc::new(ball);
         let mut w = default_world();
         w.objects.push(floor.clone());
@@ -496,7 +1110,7 @@ pub mod spec {
     #[test]
     fn shade_hit_with_a_reflective_transparent_material() {
         let mut floor = plane();
-        floor.invtransform = translation(0., -1., 0.).inverse();
+        floor.set_transform(translation(0., -1., 0.));
         floor.material.reflective = 0.5;
         floor.material.transparency = 0.5;
         floor.material.refractive_index = 1.5;
@@ -504,7 +1118,7 @@ pub mod spec {
         let mut ball = sphere();
         ball.material.color = color(1., 0., 0.);
         ball.material.ambient = 0.5;
-        ball.invtransform = translation(0., -3.5, -0.5).inverse();
+        ball.set_transform(translation(0., -3.5, -0.5));
         let ball = Arc::new(ball);
         let mut w = default_world();
         w.objects.push(floor.clone());
@@ -518,4 +1132,120 @@ pub mod spec {
 
         assert_eq!(c, color(0.93391, 0.69643, 0.69243));
     }
+
+    fn floor_over_a_ball(floor: Plane) -> (World, Arc<SyncShape>) {
+        let mut floor = floor;
+        floor.set_transform(translation(0., -1., 0.));
+        floor.material.refractive_index = 1.5;
+        floor.material.energy_conserving = true;
+        let floor: Arc<SyncShape> = Arc::new(floor);
+        let mut ball = sphere();
+        ball.material.color = color(1., 0., 0.);
+        ball.material.ambient = 0.5;
+        ball.set_transform(translation(0., -3.5, -0.5));
+        let mut w = default_world();
+        w.objects.push(floor.clone());
+        w.objects.push(Arc::new(ball));
+        (w, floor)
+    }
+
+    #[test]
+    fn an_energy_conserving_mirror_only_shows_the_reflection() {
+        let mut floor = plane();
+        floor.material.reflective = 1.;
+        let (w, floor) = floor_over_a_ball(floor);
+        let sq2 = 2_f64.sqrt();
+        let r = ray(point(0., 0., -3.), vector(0., -sq2 / 2., sq2 / 2.));
+        let xs = vec![intersection(sq2, floor)];
+        let comps = xs[0].prepare_computations(&r, &xs);
+        let reflected = w.reflected_light(&comps, 5);
+
+        let c = w.shade_hit(comps, 5);
+
+        assert_ne!(reflected, Color::BLACK);
+        assert_eq!(c, reflected);
+    }
+
+    #[test]
+    fn energy_conserving_reflective_glass_splits_the_light_once() {
+        let mut floor = plane();
+        floor.material.reflective = 0.5;
+        floor.material.transparency = 0.5;
+        let (w, floor) = floor_over_a_ball(floor);
+        let sq2 = 2_f64.sqrt();
+        let r = ray(point(0., 0., -3.), vector(0., -sq2 / 2., sq2 / 2.));
+        let xs = vec![intersection(sq2, floor)];
+        let comps = xs[0].prepare_computations(&r, &xs);
+        let reflectance = comps.schlick();
+        let reflected = w.reflected_light(&comps, 5);
+        let refracted = w.refracted_light(&comps, 5);
+
+        let c = w.shade_hit(comps, 5);
+
+        // nothing is left to light the floor, all the light is split between the two rays
+        assert_eq!(c, reflected * reflectance + refracted * (1. - reflectance));
+        assert_eq!(c, color(0.49498, 0.02002, 0.01201));
+    }
+
+    #[test]
+    fn energy_conserving_glass_adds_its_own_emission() {
+        let mut floor = plane();
+        floor.material.transparency = 1.;
+        floor.material.emission = color(0.2, 0.1, 0.);
+        let (w, floor) = floor_over_a_ball(floor);
+        let sq2 = 2_f64.sqrt();
+        let r = ray(point(0., 0., -3.), vector(0., -sq2 / 2., sq2 / 2.));
+        let xs = vec![intersection(sq2, floor)];
+        let comps = xs[0].prepare_computations(&r, &xs);
+        let refracted = w.refracted_light(&comps, 5);
+
+        let c = w.shade_hit(comps, 5);
+
+        assert_eq!(c, refracted + color(0.2, 0.1, 0.));
+    }
+
+    #[test]
+    fn a_textured_obj_triangle_is_shaded_by_its_texture_coordinates() {
+        let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.25 0.25\nusemtl tex\nf 1/1 2/1 3/1\n";
+        let mut m = material();
+        m.ambient = 1.;
+        m.diffuse = 0.;
+        m.specular = 0.;
+        // planar mapping of the hit point would land on a black square
+        m.pattern = Some(Arc::new(texture_map(
+            Box::new(uv_checkers(2., 2., Color::WHITE, Color::BLACK)),
+            planar_uv,
+        )));
+        let materials = [("tex".to_string(), m)].iter().cloned().collect();
+        let parsed = parse_obj_with_materials(file.as_bytes(), &materials).unwrap();
+        let mut w = default_world();
+        w.objects = vec![Arc::new(parsed.to_group())];
+        let r = ray(point(0.25, 0.25, -5.), vector(0., 0., 1.));
+
+        assert_eq!(w.color_at(&r, MAX_REFLECTIONS), Color::WHITE);
+    }
+
+    #[test]
+    fn tracing_a_path_off_a_mirror_onto_a_wall() {
+        let mut mirror = plane();
+        mirror.material.reflective = 1.;
+        mirror.set_transform(translation(0., -1., 0.));
+        let mirror: Arc<SyncShape> = Arc::new(mirror);
+        let mut wall = plane();
+        wall.set_transform(translation(0., 0., 5.) * rotation_x(PI / 2.));
+        let wall: Arc<SyncShape> = Arc::new(wall);
+        let mut w = world();
+        w.objects = vec![mirror.clone(), wall.clone()];
+        w.light_sources = vec![point_light(point(0., 5., 0.), color(1., 1., 1.)).into()];
+        let sq2 = 2_f64.sqrt();
+        let r = ray(point(0., 0., -3.), vector(0., -sq2 / 2., sq2 / 2.));
+
+        let path = w.trace_path(&r);
+
+        assert_eq!(path.len(), 2);
+        assert_eq!(path[0].point, point(0., -1., -2.));
+        assert_that!(path[0].object.clone(), eq(mirror.clone()));
+        assert_eq!(path[1].point, point(0., 6., 5.));
+        assert_that!(path[1].object.clone(), eq(wall.clone()));
+    }
 }
@@ -0,0 +1,28 @@
+use ray_tracer::camera::camera;
+use ray_tracer::lights::point_light;
+use ray_tracer::spheres::sphere;
+use ray_tracer::transformations::view_transform;
+use ray_tracer::tuples::{color, point, vector};
+use ray_tracer::world::world;
+use std::f64::consts::PI;
+use std::fs;
+use std::fs::File;
+use std::sync::Arc;
+
+#[test]
+fn rendering_without_a_window_writes_a_ppm() {
+    let mut w = world();
+    w.objects = vec![Arc::new(sphere())];
+    w.light_sources = vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()];
+    let mut c = camera(11, 11, PI / 2.);
+    c.invtransform =
+        view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)).inverse();
+
+    let canvas = c.render_parallel(w);
+    let path = std::env::temp_dir().join("ray-tracer-headless.ppm");
+    canvas.write_ppm(&mut File::create(&path).unwrap()).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(contents.starts_with("P3\n11 11\n255\n"));
+}
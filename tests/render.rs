@@ -0,0 +1,32 @@
+use ray_tracer::camera::camera;
+use ray_tracer::lights::point_light;
+use ray_tracer::shapes::Shape;
+use ray_tracer::spheres::sphere;
+use ray_tracer::transformations::{scaling, view_transform};
+use ray_tracer::tuples::{color, point, vector};
+use ray_tracer::world::world;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+#[test]
+fn rendering_the_default_world_through_the_library() {
+    let mut s1 = sphere();
+    s1.material.color = color(0.8, 1., 0.6);
+    s1.material.diffuse = 0.7;
+    s1.material.specular = 0.2;
+    let mut s2 = sphere();
+    s2.set_transform(scaling(0.5, 0.5, 0.5));
+    let mut w = world();
+    w.objects = vec![Arc::new(s1), Arc::new(s2)];
+    w.light_sources = vec![point_light(point(-10., 10., -10.), color(1., 1., 1.)).into()];
+
+    let mut c = camera(11, 11, PI / 2.);
+    let from = point(0., 0., -5.);
+    let to = point(0., 0., 0.);
+    let up = vector(0., 1., 0.);
+    c.invtransform = view_transform(&from, &to, &up).inverse();
+
+    let image = c.render(w);
+
+    assert_eq!(image.pixel_at(5, 5), &color(0.38066, 0.47583, 0.2855));
+}